@@ -0,0 +1,112 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Weak;
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::CodexFeedback;
+use crate::FeedbackInner;
+
+/// Name of the rolling file [`CodexFeedback::enable_autosave`] keeps overwriting.
+pub(crate) const AUTOSAVE_FILE_NAME: &str = "codex-feedback-autosave.log";
+
+/// A running autosave thread; dropping `stop` wakes it up and makes it exit.
+pub(crate) struct AutosaveHandle {
+    stop: mpsc::Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl CodexFeedback {
+    /// Starts a background thread that writes the shared buffer to
+    /// `dir/codex-feedback-autosave.log` every `interval`, overwriting the previous
+    /// copy, so recent logs survive even if the process dies without saving. Any
+    /// autosave already running is stopped first.
+    ///
+    /// The file is written to a temporary name and renamed into place, so readers
+    /// never observe a half-written copy.
+    pub fn enable_autosave(&self, dir: &Path, interval: Duration) {
+        self.disable_autosave();
+        let (stop, stopped) = mpsc::channel();
+        let inner = Arc::downgrade(&self.inner);
+        let path = dir.join(AUTOSAVE_FILE_NAME);
+        let thread = std::thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                if !autosave_once(&inner, &path) {
+                    break;
+                }
+            }
+        });
+        *self.inner.autosave.lock().expect("mutex poisoned") =
+            Some(AutosaveHandle { stop, thread });
+    }
+
+    /// Stops the autosave thread, if any, and waits for it to exit. The last
+    /// autosaved file is left in place.
+    pub fn disable_autosave(&self) {
+        let handle = self.inner.autosave.lock().expect("mutex poisoned").take();
+        if let Some(AutosaveHandle { stop, thread }) = handle {
+            drop(stop);
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Writes one autosave; returns `false` once the feedback instance is gone.
+fn autosave_once(inner: &Weak<FeedbackInner>, path: &Path) -> bool {
+    let Some(inner) = inner.upgrade() else {
+        return false;
+    };
+    let snapshot = CodexFeedback { inner }.snapshot(None);
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    // Best effort: a failed write is retried on the next tick.
+    if fs::write(&tmp, snapshot.as_bytes()).is_ok() {
+        let _ = fs::rename(&tmp, path);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::time::Instant;
+
+    use tracing_subscriber::fmt::writer::MakeWriter;
+
+    use super::*;
+
+    fn wait_for_contents(path: &Path, needle: &str) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if fs::read_to_string(path).is_ok_and(|text| text.contains(needle)) {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        panic!("{} never contained {needle:?}", path.display());
+    }
+
+    #[test]
+    fn autosave_rewrites_file_until_disabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(AUTOSAVE_FILE_NAME);
+        let fb = CodexFeedback::with_capacity(1024);
+        let mut w = fb.make_writer().make_writer();
+
+        fb.enable_autosave(dir.path(), Duration::from_millis(10));
+        w.write_all(b"first\n").unwrap();
+        wait_for_contents(&path, "first");
+        w.write_all(b"second\n").unwrap();
+        wait_for_contents(&path, "first\nsecond\n");
+
+        fb.disable_autosave();
+        assert!(fb.inner.autosave.lock().unwrap().is_none());
+        w.write_all(b"third\n").unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        pretty_assertions::assert_eq!(fs::read_to_string(&path).unwrap(), "first\nsecond\n");
+    }
+}
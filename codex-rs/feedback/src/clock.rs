@@ -0,0 +1,88 @@
+use std::time::Duration;
+use std::time::Instant;
+
+use chrono::DateTime;
+use chrono::Utc;
+
+/// Source of the current time for time-dependent features such as
+/// [`crate::CodexFeedback::with_line_timestamps`]; injectable via
+/// [`crate::CodexFeedback::with_clock`] so tests can control it.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Wall-clock time, for timestamps such as [`crate::CapturedEvent::timestamp`].
+    /// Clocks that control [`Clock::now`] should move this along with it.
+    fn now_utc(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    /// Waits for `duration` to pass on this clock; see [`crate::CodexFeedback::replay`].
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// The real monotonic clock; the default.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+#[cfg(test)]
+pub(crate) use mock::MockClock;
+
+#[cfg(test)]
+mod mock {
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use chrono::DateTime;
+    use chrono::Utc;
+
+    use super::Clock;
+
+    /// A clock that only moves when told to. Its wall-clock time starts at
+    /// [`MockClock::EPOCH`].
+    pub(crate) struct MockClock {
+        started: Instant,
+        now: Mutex<Instant>,
+    }
+
+    impl MockClock {
+        /// 2025-01-01T00:00:00Z.
+        pub(crate) const EPOCH: i64 = 1_735_689_600;
+
+        pub(crate) fn new() -> Self {
+            let started = Instant::now();
+            Self {
+                started,
+                now: Mutex::new(started),
+            }
+        }
+
+        pub(crate) fn advance(&self, by: Duration) {
+            *self.now.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+
+        fn now_utc(&self) -> DateTime<Utc> {
+            let elapsed = self.now().duration_since(self.started);
+            DateTime::from_timestamp(Self::EPOCH, 0).expect("valid epoch")
+                + chrono::Duration::from_std(elapsed).expect("elapsed fits")
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.advance(duration);
+        }
+    }
+}
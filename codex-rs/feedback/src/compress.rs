@@ -0,0 +1,307 @@
+use std::collections::VecDeque;
+use std::io;
+#[cfg(feature = "gzip")]
+use std::io::Read;
+#[cfg(feature = "gzip")]
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[cfg(feature = "gzip")]
+use flate2::Compression;
+#[cfg(feature = "gzip")]
+use flate2::read::GzDecoder;
+#[cfg(feature = "gzip")]
+use flate2::write::GzEncoder;
+
+use crate::CodexLogSnapshot;
+use crate::FeedbackError;
+
+/// A compression algorithm for [`CodexLogSnapshot::save_compressed`], so callers
+/// can plug in whichever format their tooling standardizes on.
+pub trait Compressor {
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>>;
+
+    /// File name extension for the compressed output, e.g. `"gz"`, appended after
+    /// `.log`. `None` keeps the plain `.log` name.
+    fn extension(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// The inverse of a [`Compressor`], needed to keep history compressed in memory;
+/// see [`crate::CodexFeedback::with_in_memory_compression`].
+pub trait Decompressor {
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+/// gzip at the default level, saved as `.log.gz` so
+/// [`crate::CodexFeedback::from_gzip_file`] can load it back.
+#[cfg(feature = "gzip")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GzipCompressor;
+
+#[cfg(feature = "gzip")]
+impl Compressor for GzipCompressor {
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        encoder.finish()
+    }
+
+    fn extension(&self) -> Option<&str> {
+        Some("gz")
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl Decompressor for GzipCompressor {
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        GzDecoder::new(data).read_to_end(&mut out)?;
+        Ok(out)
+    }
+}
+
+/// zstd at the library's default level, saved as `.log.zst`.
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZstdCompressor;
+
+#[cfg(feature = "zstd")]
+impl Compressor for ZstdCompressor {
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        zstd::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL)
+    }
+
+    fn extension(&self) -> Option<&str> {
+        Some("zst")
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Decompressor for ZstdCompressor {
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        zstd::decode_all(data)
+    }
+}
+
+trait HistoryCodec: Compressor + Decompressor + Send + Sync {}
+
+impl<T: Compressor + Decompressor + Send + Sync> HistoryCodec for T {}
+
+/// Bytes evicted from a ring buffer, kept as compressed segments of
+/// `segment_len` bytes (plus the uncompressed segment still being filled) until
+/// the compressed segments add up to more than `segment_len` themselves.
+pub(crate) struct CompressedHistory {
+    codec: Box<dyn HistoryCodec>,
+    segment_len: usize,
+    segments: VecDeque<Segment>,
+    pending: Vec<u8>,
+    /// Sum of the stored (possibly compressed) segment sizes.
+    stored_len: usize,
+    /// Uncompressed length of the segments and `pending`.
+    len: usize,
+    /// Absolute offset just past the newest byte held.
+    end: u64,
+}
+
+struct Segment {
+    data: Vec<u8>,
+    len: usize,
+    /// False when compressing failed or did not make the segment any smaller.
+    compressed: bool,
+}
+
+impl CompressedHistory {
+    pub(crate) fn new(
+        codec: impl Compressor + Decompressor + Send + Sync + 'static,
+        segment_len: usize,
+    ) -> Self {
+        Self {
+            codec: Box::new(codec),
+            segment_len: segment_len.max(1),
+            segments: VecDeque::new(),
+            pending: Vec::new(),
+            stored_len: 0,
+            len: 0,
+            end: 0,
+        }
+    }
+
+    /// Appends `bytes`, which were written at absolute offset `start`. Anything
+    /// held that does not end right there is discarded first, so the history is
+    /// always one contiguous range.
+    pub(crate) fn archive(&mut self, start: u64, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        if start != self.end {
+            self.segments.clear();
+            self.pending.clear();
+            self.stored_len = 0;
+            self.len = 0;
+        }
+        self.pending.extend_from_slice(bytes);
+        self.len += bytes.len();
+        self.end = start + bytes.len() as u64;
+        if self.pending.len() >= self.segment_len {
+            self.seal();
+        }
+    }
+
+    fn seal(&mut self) {
+        let raw = std::mem::take(&mut self.pending);
+        let segment = match self.codec.compress(&raw) {
+            Ok(data) if data.len() < raw.len() => Segment {
+                data,
+                len: raw.len(),
+                compressed: true,
+            },
+            _ => Segment {
+                len: raw.len(),
+                data: raw,
+                compressed: false,
+            },
+        };
+        self.stored_len += segment.data.len();
+        self.segments.push_back(segment);
+        while self.stored_len > self.segment_len {
+            let Some(oldest) = self.segments.pop_front() else {
+                break;
+            };
+            self.stored_len -= oldest.data.len();
+            self.len -= oldest.len;
+        }
+    }
+
+    /// Absolute offsets of the bytes held.
+    pub(crate) fn offsets(&self) -> Range<u64> {
+        self.end - self.len as u64..self.end
+    }
+
+    /// Decompresses everything held, oldest first.
+    pub(crate) fn bytes(&self) -> io::Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(self.len);
+        for segment in &self.segments {
+            if segment.compressed {
+                out.extend(self.codec.decompress(&segment.data)?);
+            } else {
+                out.extend_from_slice(&segment.data);
+            }
+        }
+        out.extend_from_slice(&self.pending);
+        Ok(out)
+    }
+
+    pub(crate) fn memory_footprint(&self) -> usize {
+        self.segments.capacity() * std::mem::size_of::<Segment>()
+            + self
+                .segments
+                .iter()
+                .map(|segment| segment.data.capacity())
+                .sum::<usize>()
+            + self.pending.capacity()
+    }
+}
+
+impl CodexLogSnapshot {
+    /// Compresses the snapshot with `compressor` and writes it into `dir` as
+    /// `codex-feedback-{thread_id}.log[.{extension}]`.
+    pub fn save_compressed(
+        &self,
+        dir: &Path,
+        compressor: &dyn Compressor,
+    ) -> Result<PathBuf, FeedbackError> {
+        let contents = compressor.compress(self.as_bytes())?;
+        let mut name = self.file_name();
+        if let Some(extension) = compressor.extension() {
+            name = format!("{name}.{extension}");
+        }
+        self.write_file_at(dir.join(name), &contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    struct Identity;
+
+    impl Compressor for Identity {
+        fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+            Ok(data.to_vec())
+        }
+
+        fn extension(&self) -> Option<&str> {
+            Some("id")
+        }
+    }
+
+    struct Failing;
+
+    impl Compressor for Failing {
+        fn compress(&self, _data: &[u8]) -> io::Result<Vec<u8>> {
+            Err(io::Error::other("no codec"))
+        }
+    }
+
+    #[test]
+    fn save_compressed_writes_compressor_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = CodexLogSnapshot::new(b"hello\n".to_vec(), "thread-1".to_string());
+
+        let path = snapshot.save_compressed(dir.path(), &Identity).unwrap();
+
+        assert_eq!(path, dir.path().join("codex-feedback-thread-1.log.id"));
+        assert_eq!(fs::read(&path).unwrap(), b"hello\n");
+        assert!(matches!(
+            snapshot.save_compressed(dir.path(), &Failing),
+            Err(FeedbackError::Io(_))
+        ));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_compressor_output_loads_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let text: String = (0..200)
+            .map(|i| format!("INFO request {i} done\n"))
+            .collect();
+        let snapshot = CodexLogSnapshot::new(text.clone().into_bytes(), "thread-1".to_string());
+
+        let path = snapshot
+            .save_compressed(dir.path(), &GzipCompressor)
+            .unwrap();
+
+        assert_eq!(path, dir.path().join("codex-feedback-thread-1.log.gz"));
+        let compressed = fs::read(&path).unwrap();
+        assert!(compressed.len() < text.len(), "{}", compressed.len());
+        assert_eq!(
+            GzipCompressor.decompress(&compressed).unwrap(),
+            text.as_bytes()
+        );
+        let loaded = crate::CodexFeedback::from_gzip_file(&path, text.len()).unwrap();
+        assert_eq!(loaded.snapshot(None).as_bytes(), text.as_bytes());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_compressor_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = CodexLogSnapshot::new(b"hello\n".to_vec(), "thread-1".to_string());
+
+        let path = snapshot
+            .save_compressed(dir.path(), &ZstdCompressor)
+            .unwrap();
+
+        assert_eq!(path, dir.path().join("codex-feedback-thread-1.log.zst"));
+        let compressed = fs::read(&path).unwrap();
+        assert_eq!(ZstdCompressor.decompress(&compressed).unwrap(), b"hello\n");
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+
+use anyhow::Result;
+use anyhow::anyhow;
+
+/// Feedback tunables as they appear in an application config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedbackConfig {
+    /// Maximum number of bytes kept in the ring, e.g. `"4 MiB"`; see [`parse_byte_size`].
+    pub capacity: String,
+}
+
+impl Default for FeedbackConfig {
+    fn default() -> Self {
+        Self {
+            capacity: "4 MiB".to_string(),
+        }
+    }
+}
+
+/// A size in bytes, taken by the size-limiting APIs so sizes cannot be confused
+/// with line or item counts. Plain `usize` byte counts still convert via `From`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByteSize(pub usize);
+
+impl ByteSize {
+    pub const fn bytes(n: usize) -> Self {
+        Self(n)
+    }
+
+    /// `n` KiB (1024 bytes each), saturating at `usize::MAX`.
+    pub const fn kib(n: usize) -> Self {
+        Self(n.saturating_mul(1 << 10))
+    }
+
+    /// `n` MiB (1024 KiB each), saturating at `usize::MAX`.
+    pub const fn mib(n: usize) -> Self {
+        Self(n.saturating_mul(1 << 20))
+    }
+
+    pub const fn as_usize(self) -> usize {
+        self.0
+    }
+}
+
+impl fmt::Display for ByteSize {
+    /// Formats like [`format_bytes`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_bytes(self.0))
+    }
+}
+
+impl From<usize> for ByteSize {
+    fn from(bytes: usize) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<ByteSize> for usize {
+    fn from(size: ByteSize) -> Self {
+        size.0
+    }
+}
+
+/// Parses a human-readable byte size such as `"512 KiB"`, `"4MiB"`, or `"1 GiB"`.
+///
+/// The number must be a non-negative integer. The unit is optional (plain bytes) and may
+/// be one of `B`, `KiB`, `MiB`, or `GiB`, separated from the number by optional
+/// whitespace.
+pub fn parse_byte_size(input: &str) -> Result<usize> {
+    let trimmed = input.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    if number.is_empty() {
+        return Err(anyhow!(
+            "invalid byte size `{input}`: expected a non-negative number"
+        ));
+    }
+    let multiplier: usize = match unit.trim_start() {
+        "" | "B" => 1,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        other => {
+            return Err(anyhow!(
+                "invalid byte size `{input}`: unknown unit `{other}` (expected B, KiB, MiB, or GiB)"
+            ));
+        }
+    };
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| anyhow!("invalid byte size `{input}`: value is too large"))
+}
+
+/// Formats a byte count for people, e.g. `"3.7 MiB"`: plain bytes below 1 KiB,
+/// otherwise the largest of KiB, MiB, or GiB that keeps the value under 1024
+/// after rounding to one decimal.
+pub fn format_bytes(n: usize) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if n < 1 << 10 {
+        return format!("{n} B");
+    }
+    let mut value = n as f64 / 1024.0;
+    for (i, unit) in UNITS.iter().enumerate() {
+        if (value * 10.0).round() < 10240.0 || i == UNITS.len() - 1 {
+            return format!("{value:.1} {unit}");
+        }
+        value /= 1024.0;
+    }
+    unreachable!("the last unit always formats")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parses_binary_units() {
+        assert_eq!(parse_byte_size("512 KiB").unwrap(), 512 * 1024);
+        assert_eq!(parse_byte_size("4 MiB").unwrap(), 4 * 1024 * 1024);
+        assert_eq!(parse_byte_size("1GiB").unwrap(), 1024 * 1024 * 1024);
+        assert_eq!(parse_byte_size("100").unwrap(), 100);
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        for input in ["-4 MiB", "four MiB", "4 MB", "", "1.5 MiB"] {
+            assert!(
+                parse_byte_size(input).is_err(),
+                "{input:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn formats_bytes_at_unit_boundaries() {
+        for (n, expected) in [
+            (0, "0 B"),
+            (1023, "1023 B"),
+            (1024, "1.0 KiB"),
+            (1536, "1.5 KiB"),
+            ((1 << 20) - 1, "1.0 MiB"),
+            (3 * (1 << 20) + 734_003, "3.7 MiB"),
+            (3 << 19, "1.5 MiB"),
+            (1 << 30, "1.0 GiB"),
+            (5000 << 30, "5000.0 GiB"),
+        ] {
+            assert_eq!(format_bytes(n), expected, "{n}");
+        }
+        assert_eq!(ByteSize::kib(4).to_string(), "4.0 KiB");
+    }
+
+    #[test]
+    fn byte_size_helpers_match_raw_counts() {
+        assert_eq!(ByteSize::kib(512), ByteSize::from(512 * 1024));
+        assert_eq!(ByteSize::mib(4).as_usize(), 4 * 1024 * 1024);
+        assert_eq!(ByteSize::bytes(100), ByteSize(100));
+        assert_eq!(
+            ByteSize::mib(4),
+            ByteSize::from(parse_byte_size("4 MiB").unwrap())
+        );
+        assert_eq!(ByteSize::kib(usize::MAX), ByteSize(usize::MAX));
+    }
+}
@@ -0,0 +1,117 @@
+//! CRC-32 (IEEE 802.3, as used by zip and gzip), small enough not to warrant a
+//! dependency.
+
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+const TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Extends `crc`, the checksum of some bytes, to the checksum of those bytes
+/// followed by `data`. The checksum of no bytes is 0.
+pub(crate) fn update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc = TABLE[((crc ^ u32::from(byte)) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// Given the checksums of `a ++ b` and of `a`, returns the checksum of `b` alone,
+/// where `b` is `len_b` bytes long. This is what makes evicting from the front of
+/// the ring cheap: only the evicted bytes are hashed, not the remaining ones.
+pub(crate) fn remove_prefix(crc_ab: u32, crc_a: u32, len_b: usize) -> u32 {
+    crc_ab ^ multmodp(x8nmodp(len_b as u64), crc_a)
+}
+
+/// Multiplies `a` and `b` modulo the CRC polynomial (bit-reflected, as in zlib's
+/// `crc32_combine`).
+const fn multmodp(a: u32, mut b: u32) -> u32 {
+    let mut m: u32 = 1 << 31;
+    let mut p = 0;
+    loop {
+        if a & m != 0 {
+            p ^= b;
+            if a & (m - 1) == 0 {
+                break;
+            }
+        }
+        m >>= 1;
+        b = if b & 1 != 0 {
+            (b >> 1) ^ POLYNOMIAL
+        } else {
+            b >> 1
+        };
+    }
+    p
+}
+
+/// `X2N[k]` is x^(2^k) modulo the polynomial.
+const X2N: [u32; 32] = {
+    let mut table = [0; 32];
+    let mut p: u32 = 1 << 30;
+    table[0] = p;
+    let mut k = 1;
+    while k < 32 {
+        p = multmodp(p, p);
+        table[k] = p;
+        k += 1;
+    }
+    table
+};
+
+/// x^(8 * n) modulo the polynomial, i.e. the effect of appending `n` zero bytes.
+fn x8nmodp(mut n: u64) -> u32 {
+    let mut p: u32 = 1 << 31;
+    let mut k = 3;
+    while n != 0 {
+        if n & 1 != 0 {
+            p = multmodp(X2N[k & 31], p);
+        }
+        n >>= 1;
+        k += 1;
+    }
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn matches_the_standard_check_value() {
+        assert_eq!(update(0, b""), 0);
+        assert_eq!(update(0, b"123456789"), 0xCBF4_3926);
+        assert_eq!(update(update(0, b"1234"), b"56789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn removing_a_prefix_leaves_the_suffix_checksum() {
+        let data: Vec<u8> = (0..=255).cycle().take(3000).collect();
+        for split in [0, 1, 7, 256, 2999, 3000] {
+            let (a, b) = data.split_at(split);
+            assert_eq!(
+                remove_prefix(update(0, &data), update(0, a), b.len()),
+                update(0, b),
+                "split at {split}"
+            );
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::sync::PoisonError;
+use std::sync::atomic::Ordering;
+
+use crate::CodexFeedback;
+use crate::FeedbackInner;
+
+/// What happens when the last handle to a [`CodexFeedback`] goes away while it
+/// still holds logs nobody looked at; see [`CodexFeedback::with_drop_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Drop silently.
+    #[default]
+    Quiet,
+    /// Emit a `tracing` warning naming how many bytes are lost.
+    Warn,
+}
+
+impl CodexFeedback {
+    /// Sets what happens when the last clone of this instance (including its
+    /// writers and layer) is dropped while the shared buffer holds bytes and no
+    /// snapshot was ever taken, or while double-buffered writes (see
+    /// [`CodexFeedback::with_double_buffering`]) are still staged.
+    pub fn with_drop_policy(self, policy: DropPolicy) -> Self {
+        *self.inner.drop_policy.lock().expect("mutex poisoned") = policy;
+        self
+    }
+}
+
+impl Drop for FeedbackInner {
+    fn drop(&mut self) {
+        let policy = *self
+            .drop_policy
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        if policy == DropPolicy::Quiet {
+            return;
+        }
+        let staged = self
+            .staged
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len();
+        let unsnapshotted = if self.snapshotted.load(Ordering::Relaxed) {
+            0
+        } else {
+            self.ring
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner)
+                .len()
+        };
+        let buffered = staged + unsnapshotted;
+        if buffered > 0 {
+            tracing::warn!("dropping feedback with {buffered} buffered bytes never submitted");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use pretty_assertions::assert_eq;
+    use tracing_subscriber::fmt::writer::MakeWriter;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    /// Messages logged while `f` runs.
+    fn logged(f: impl FnOnce()) -> Vec<String> {
+        let sink = CodexFeedback::with_capacity(1024);
+        let subscriber = tracing_subscriber::registry().with(sink.layer());
+        tracing::subscriber::with_default(subscriber, f);
+        sink.snapshot_events()
+            .into_iter()
+            .map(|event| event.message)
+            .collect()
+    }
+
+    fn write(fb: &CodexFeedback, text: &str) {
+        fb.make_writer()
+            .make_writer()
+            .write_all(text.as_bytes())
+            .expect("write");
+    }
+
+    #[test]
+    fn warn_policy_reports_unsubmitted_bytes_on_drop() {
+        let messages = logged(|| {
+            let fb = CodexFeedback::with_capacity(1024).with_drop_policy(DropPolicy::Warn);
+            write(&fb, "lost\n");
+            drop(fb);
+        });
+        assert_eq!(
+            messages,
+            ["dropping feedback with 5 buffered bytes never submitted"]
+        );
+
+        let messages = logged(|| {
+            let fb = CodexFeedback::with_capacity(1024).with_drop_policy(DropPolicy::Warn);
+            write(&fb, "kept\n");
+            let _ = fb.snapshot(None);
+            drop(fb);
+        });
+        assert_eq!(messages, Vec::<String>::new());
+    }
+
+    #[test]
+    fn quiet_policy_drops_silently() {
+        let messages = logged(|| {
+            let fb = CodexFeedback::with_capacity(1024).with_drop_policy(DropPolicy::Quiet);
+            write(&fb, "lost\n");
+            drop(fb);
+        });
+        assert_eq!(messages, Vec::<String>::new());
+    }
+}
@@ -0,0 +1,120 @@
+use std::borrow::Cow;
+
+/// How bytes handed to the feedback writers are encoded; see
+/// [`crate::CodexFeedback::with_input_encoding`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputEncoding {
+    /// Stored as-is.
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+/// Incrementally converts written bytes to UTF-8, carrying split code units and
+/// surrogate pairs over to the next write.
+#[derive(Debug, Default)]
+pub(crate) struct Transcoder {
+    encoding: InputEncoding,
+    /// Whether a byte order mark may still appear.
+    at_start: bool,
+    /// Bytes of an incomplete code unit or surrogate pair from the last write.
+    pending: Vec<u8>,
+}
+
+impl Transcoder {
+    pub(crate) fn new(encoding: InputEncoding) -> Self {
+        Self {
+            encoding,
+            at_start: true,
+            pending: Vec::new(),
+        }
+    }
+
+    pub(crate) fn transcode<'a>(&mut self, buf: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.encoding == InputEncoding::Utf8 {
+            return Cow::Borrowed(buf);
+        }
+        let mut data = std::mem::take(&mut self.pending);
+        data.extend_from_slice(buf);
+        if self.at_start {
+            if data.len() < 2 {
+                self.pending = data;
+                return Cow::Borrowed(&[]);
+            }
+            self.at_start = false;
+            // A byte order mark wins over the configured endianness.
+            let bom = match data[..2] {
+                [0xFF, 0xFE] => Some(InputEncoding::Utf16Le),
+                [0xFE, 0xFF] => Some(InputEncoding::Utf16Be),
+                _ => None,
+            };
+            if let Some(encoding) = bom {
+                self.encoding = encoding;
+                data.drain(..2);
+            }
+        }
+
+        let big_endian = self.encoding == InputEncoding::Utf16Be;
+        let mut units: Vec<u16> = data
+            .chunks_exact(2)
+            .map(|pair| {
+                let pair = [pair[0], pair[1]];
+                if big_endian {
+                    u16::from_be_bytes(pair)
+                } else {
+                    u16::from_le_bytes(pair)
+                }
+            })
+            .collect();
+        let mut keep = data.len() % 2;
+        if units
+            .last()
+            .is_some_and(|unit| (0xD800..0xDC00).contains(unit))
+        {
+            // A high surrogate whose pair has not arrived yet.
+            units.pop();
+            keep += 2;
+        }
+        self.pending = data.split_off(data.len() - keep);
+
+        let text: String = char::decode_utf16(units)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect();
+        Cow::Owned(text.into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn utf8_passes_through() {
+        let mut transcoder = Transcoder::new(InputEncoding::Utf8);
+        assert!(matches!(
+            transcoder.transcode(b"\xFF\xFEhi"),
+            Cow::Borrowed(b"\xFF\xFEhi")
+        ));
+    }
+
+    #[test]
+    fn utf16_pieces_split_mid_character_are_joined() {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(utf16le("héllo 🦀\n"));
+        let mut transcoder = Transcoder::new(InputEncoding::Utf16Be);
+
+        let out: Vec<u8> = bytes
+            .chunks(3)
+            .flat_map(|chunk| transcoder.transcode(chunk).into_owned())
+            .collect();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "héllo 🦀\n");
+    }
+}
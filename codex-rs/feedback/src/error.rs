@@ -0,0 +1,67 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Errors returned when saving a feedback snapshot to disk.
+#[derive(Debug)]
+pub enum FeedbackError {
+    /// The target volume (or the user's quota on it) is full. Callers can ask the
+    /// user to free up space or pick another directory.
+    OutOfSpace {
+        path: PathBuf,
+        /// Size of the file that could not be written.
+        needed: usize,
+    },
+    /// A compressed snapshot at `path` could not be decoded because it is
+    /// truncated or not valid gzip.
+    Corrupt {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Io(io::Error),
+}
+
+impl FeedbackError {
+    /// Classifies a failed write of `needed` bytes to `path`.
+    pub(crate) fn from_write(err: io::Error, path: PathBuf, needed: usize) -> Self {
+        match err.kind() {
+            io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => {
+                FeedbackError::OutOfSpace { path, needed }
+            }
+            _ => FeedbackError::Io(err),
+        }
+    }
+}
+
+impl fmt::Display for FeedbackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeedbackError::OutOfSpace { path, needed } => write!(
+                f,
+                "not enough space to write {needed} bytes of feedback to {}",
+                path.display()
+            ),
+            FeedbackError::Corrupt { path, source } => write!(
+                f,
+                "feedback archive {} is truncated or corrupt: {source}",
+                path.display()
+            ),
+            FeedbackError::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for FeedbackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FeedbackError::OutOfSpace { .. } => None,
+            FeedbackError::Corrupt { source, .. } | FeedbackError::Io(source) => Some(source),
+        }
+    }
+}
+
+impl From<io::Error> for FeedbackError {
+    fn from(err: io::Error) -> Self {
+        FeedbackError::Io(err)
+    }
+}
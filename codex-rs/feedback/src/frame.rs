@@ -0,0 +1,142 @@
+use anyhow::Result;
+use anyhow::bail;
+
+use crate::CodexLogSnapshot;
+
+/// First bytes of every frame produced by [`CodexLogSnapshot::to_frame`].
+const FRAME_MAGIC: &[u8; 4] = b"CXFB";
+const FRAME_VERSION: u8 = 1;
+/// Magic, version, flags, byte length (u64 LE), thread id length (u32 LE).
+const FRAME_HEADER_LEN: usize = 4 + 1 + 1 + 8 + 4;
+/// Set in the flags byte when the schema version (u32 LE) follows the thread id.
+/// Frames without it were written before schema versions and decode as version 1.
+const FRAME_FLAG_SCHEMA_VERSION: u8 = 0b1;
+
+impl CodexLogSnapshot {
+    /// Encodes the snapshot as a self-describing, length-framed byte string: a
+    /// `CXFB` magic, a version byte, a flags byte, the log length (u64 LE), the
+    /// thread id length (u32 LE), the thread id, the snapshot's schema version
+    /// (u32 LE), and finally the log bytes.
+    pub fn to_frame(&self) -> Vec<u8> {
+        let bytes = self.as_bytes();
+        let thread_id = self.thread_id.as_bytes();
+        let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + thread_id.len() + 4 + bytes.len());
+        frame.extend_from_slice(FRAME_MAGIC);
+        frame.push(FRAME_VERSION);
+        frame.push(FRAME_FLAG_SCHEMA_VERSION);
+        frame.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        frame.extend_from_slice(&(thread_id.len() as u32).to_le_bytes());
+        frame.extend_from_slice(thread_id);
+        frame.extend_from_slice(&self.schema_version.to_le_bytes());
+        frame.extend_from_slice(bytes);
+        frame
+    }
+
+    /// Decodes a frame produced by [`CodexLogSnapshot::to_frame`], rejecting unknown
+    /// magic or versions and frames whose lengths do not match their contents.
+    pub fn from_frame(frame: &[u8]) -> Result<CodexLogSnapshot> {
+        if frame.len() < FRAME_HEADER_LEN {
+            bail!(
+                "feedback frame too short: {} bytes, need at least {FRAME_HEADER_LEN}",
+                frame.len()
+            );
+        }
+        let (header, rest) = frame.split_at(FRAME_HEADER_LEN);
+        if &header[..4] != FRAME_MAGIC {
+            bail!("not a feedback frame: bad magic");
+        }
+        let version = header[4];
+        if version != FRAME_VERSION {
+            bail!("unsupported feedback frame version {version}");
+        }
+        let mut len = [0; 8];
+        len.copy_from_slice(&header[6..14]);
+        let bytes_len = u64::from_le_bytes(len);
+        let mut len = [0; 4];
+        len.copy_from_slice(&header[14..18]);
+        let thread_id_len = u32::from_le_bytes(len) as u64;
+        let has_schema_version = header[5] & FRAME_FLAG_SCHEMA_VERSION != 0;
+        let schema_len = if has_schema_version { 4 } else { 0 };
+
+        if thread_id_len
+            .checked_add(schema_len)
+            .and_then(|len| len.checked_add(bytes_len))
+            != Some(rest.len() as u64)
+        {
+            bail!(
+                "feedback frame length mismatch: header declares {thread_id_len} + {schema_len} + {bytes_len} bytes, found {}",
+                rest.len()
+            );
+        }
+        let (thread_id, rest) = rest.split_at(thread_id_len as usize);
+        let thread_id = String::from_utf8(thread_id.to_vec())
+            .map_err(|_| anyhow::anyhow!("feedback frame thread id is not valid UTF-8"))?;
+        let (schema_version, bytes) = rest.split_at(schema_len as usize);
+        let mut snapshot = CodexLogSnapshot::new(bytes.to_vec(), thread_id);
+        if has_schema_version {
+            let mut version = [0; 4];
+            version.copy_from_slice(schema_version);
+            snapshot.schema_version = u32::from_le_bytes(version);
+        }
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn frame_round_trips() {
+        let snap = CodexLogSnapshot::new(b"log \xff bytes\n".to_vec(), "thread-1".to_string());
+        let frame = snap.to_frame();
+        assert_eq!(&frame[..4], b"CXFB");
+
+        let decoded = CodexLogSnapshot::from_frame(&frame).unwrap();
+        assert_eq!(decoded.thread_id, "thread-1");
+        assert_eq!(decoded.as_bytes(), snap.as_bytes());
+    }
+
+    #[test]
+    fn frames_without_schema_flag_decode_as_version_one() {
+        let mut frame = b"CXFB\x01\x00".to_vec();
+        frame.extend_from_slice(&3u64.to_le_bytes());
+        frame.extend_from_slice(&1u32.to_le_bytes());
+        frame.extend_from_slice(b"tabc");
+
+        let decoded = CodexLogSnapshot::from_frame(&frame).unwrap();
+        assert_eq!(decoded.schema_version, 1);
+        assert_eq!(decoded.as_bytes(), b"abc");
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        let frame = CodexLogSnapshot::new(b"abc".to_vec(), "t".to_string()).to_frame();
+
+        let mut bad_magic = frame.clone();
+        bad_magic[0] = b'X';
+        let mut bad_version = frame.clone();
+        bad_version[4] = 9;
+        let mut bad_utf8 = frame.clone();
+        bad_utf8[FRAME_HEADER_LEN] = 0xff;
+
+        for (input, expected) in [
+            (&frame[..5], "too short"),
+            (&bad_magic[..], "bad magic"),
+            (&bad_version[..], "version 9"),
+            (&frame[..frame.len() - 1], "length mismatch"),
+            (&[frame.as_slice(), b"x"].concat()[..], "length mismatch"),
+            (&bad_utf8[..], "not valid UTF-8"),
+        ] {
+            let Err(err) = CodexLogSnapshot::from_frame(input) else {
+                panic!("expected {expected:?} error");
+            };
+            let err = err.to_string();
+            assert!(
+                err.contains(expected),
+                "{err:?} should mention {expected:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use codex_protocol::ConversationId;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use crate::ByteSize;
+use crate::CodexFeedback;
+use crate::FeedbackError;
+
+/// Bytes decompressed per step by [`CodexFeedback::from_gzip_file`].
+const DECODE_CHUNK_LEN: usize = 64 * 1024;
+
+impl CodexFeedback {
+    /// Snapshots the buffer for `session_id` (chosen as in
+    /// [`CodexFeedback::snapshot`]) and writes it gzipped into `dir` as
+    /// `codex-feedback-{thread_id}.log.gz`, e.g. for a compact feedback artifact.
+    /// The buffer is copied under a single ring lock and compressed straight into
+    /// the file, so no compressed copy is held in memory.
+    pub fn save_gzip(
+        &self,
+        session_id: Option<ConversationId>,
+        dir: &Path,
+    ) -> Result<PathBuf, FeedbackError> {
+        let snapshot = self.fresh_snapshot(session_id);
+        let path = dir.join(format!("{}.gz", snapshot.file_name()));
+        let contents = snapshot.as_bytes();
+        let write = || -> io::Result<()> {
+            let file = BufWriter::new(File::create(&path)?);
+            let mut encoder = GzEncoder::new(file, Compression::default());
+            encoder.write_all(contents)?;
+            encoder.finish()?.flush()
+        };
+        write().map_err(|err| FeedbackError::from_write(err, path.clone(), contents.len()))?;
+        snapshot.remember_saved_path(&path);
+        Ok(path)
+    }
+
+    /// Loads a gzipped log (e.g. a saved `.log.gz` snapshot) into a new buffer of
+    /// `max_bytes`, decompressing it in chunks so a large archive never has to fit
+    /// in memory; only its trailing `max_bytes` are kept. A truncated or corrupt
+    /// archive fails with [`FeedbackError::Corrupt`].
+    pub fn from_gzip_file(
+        path: &Path,
+        max_bytes: impl Into<ByteSize>,
+    ) -> Result<Self, FeedbackError> {
+        let mut decoder = GzDecoder::new(File::open(path)?);
+        let feedback = Self::with_capacity(max_bytes);
+        let mut ring = feedback.inner.ring_write();
+        let mut chunk = vec![0; DECODE_CHUNK_LEN];
+        loop {
+            let read = match decoder.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    return Err(match err.kind() {
+                        io::ErrorKind::InvalidData
+                        | io::ErrorKind::InvalidInput
+                        | io::ErrorKind::UnexpectedEof => FeedbackError::Corrupt {
+                            path: path.to_path_buf(),
+                            source: err,
+                        },
+                        _ => FeedbackError::Io(err),
+                    });
+                }
+            };
+            ring.push_bytes(&chunk[..read]);
+        }
+        drop(ring);
+        Ok(feedback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+    use tracing_subscriber::fmt::writer::MakeWriter;
+
+    use super::*;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn save_gzip_decompresses_to_the_ring_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let fb = CodexFeedback::with_capacity(4096);
+        let id = ConversationId::new();
+        let mut writer = fb.make_writer_for(id).make_writer();
+        for i in 0..500 {
+            writer
+                .write_all(format!("INFO line {i}\n").as_bytes())
+                .unwrap();
+        }
+
+        let path = fb.save_gzip(Some(id), dir.path()).unwrap();
+        let expected = fb.snapshot(Some(id));
+        assert_eq!(path, dir.path().join(format!("codex-feedback-{id}.log.gz")));
+        let mut decompressed = Vec::new();
+        GzDecoder::new(File::open(&path).unwrap())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, expected.as_bytes());
+
+        let reloaded = CodexFeedback::from_gzip_file(&path, 4096).unwrap();
+        assert_eq!(reloaded.snapshot(None).as_bytes(), expected.as_bytes());
+    }
+
+    #[test]
+    fn from_gzip_file_keeps_the_trailing_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("codex-feedback-thread.log.gz");
+        let original: String = (0..20_000).map(|i| format!("INFO line {i}\n")).collect();
+        fs::write(&path, gzip(original.as_bytes())).unwrap();
+
+        let loaded = CodexFeedback::from_gzip_file(&path, 1024).unwrap();
+        let tail = &original.as_bytes()[original.len() - 1024..];
+        assert_eq!(loaded.snapshot(None).as_bytes(), tail);
+
+        let whole = CodexFeedback::from_gzip_file(&path, original.len()).unwrap();
+        assert_eq!(whole.snapshot(None).as_bytes(), original.as_bytes());
+    }
+
+    #[test]
+    fn from_gzip_file_rejects_truncated_and_corrupt_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let compressed = gzip(b"INFO some log line\nINFO another line\n");
+
+        let truncated = dir.path().join("truncated.log.gz");
+        fs::write(&truncated, &compressed[..compressed.len() / 2]).unwrap();
+        assert!(matches!(
+            CodexFeedback::from_gzip_file(&truncated, 1024),
+            Err(FeedbackError::Corrupt { path, .. }) if path == truncated
+        ));
+
+        let corrupt = dir.path().join("corrupt.log.gz");
+        fs::write(&corrupt, b"plain text, not gzip").unwrap();
+        assert!(matches!(
+            CodexFeedback::from_gzip_file(&corrupt, 1024),
+            Err(FeedbackError::Corrupt { .. })
+        ));
+
+        assert!(matches!(
+            CodexFeedback::from_gzip_file(&dir.path().join("missing.log.gz"), 1024),
+            Err(FeedbackError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+use std::sync::Weak;
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::CodexFeedback;
+use crate::FeedbackInner;
+use crate::WriteFeatures;
+
+/// A running idle-sweep thread; dropping `stop` wakes it up and makes it exit.
+pub(crate) struct IdleSweepHandle {
+    stop: mpsc::Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl CodexFeedback {
+    /// Frees conversation buffers (see [`CodexFeedback::make_writer_for`]) that
+    /// have not been written to for `timeout`, as measured by the injected
+    /// [`crate::Clock`], so abandoned conversations do not hold memory forever. A
+    /// background thread calls [`CodexFeedback::sweep_idle`] every `timeout`, so a
+    /// buffer may outlive its timeout by up to that long again. Buffers last
+    /// written before this was enabled count as written now.
+    pub fn with_conversation_idle_timeout(self, timeout: Duration) -> Self {
+        self.stop_idle_sweep();
+        let now = self.inner.clock.lock().expect("mutex poisoned").now();
+        for ring in self
+            .inner
+            .conversations
+            .write()
+            .expect("rwlock poisoned")
+            .values_mut()
+        {
+            ring.last_write_at.get_or_insert(now);
+        }
+        *self.inner.idle_timeout.lock().expect("mutex poisoned") = Some(timeout);
+        self.inner
+            .write_features
+            .enable(WriteFeatures::IDLE_TIMEOUT);
+
+        let (stop, stopped) = mpsc::channel();
+        let inner = Arc::downgrade(&self.inner);
+        let thread = std::thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(timeout) {
+                if !sweep_once(&inner) {
+                    break;
+                }
+            }
+        });
+        *self.inner.idle_sweep.lock().expect("mutex poisoned") =
+            Some(IdleSweepHandle { stop, thread });
+        self
+    }
+
+    /// Frees every conversation buffer idle for longer than the
+    /// [`CodexFeedback::with_conversation_idle_timeout`] right away and returns how
+    /// many were freed. Does nothing without a timeout.
+    pub fn sweep_idle(&self) -> usize {
+        let Some(timeout) = *self.inner.idle_timeout.lock().expect("mutex poisoned") else {
+            return 0;
+        };
+        let now = self.inner.clock.lock().expect("mutex poisoned").now();
+        let mut conversations = self.inner.conversations.write().expect("rwlock poisoned");
+        let idle: Vec<_> = conversations
+            .iter()
+            .filter(|(_, ring)| {
+                ring.last_write_at
+                    .is_some_and(|at| now.saturating_duration_since(at) >= timeout)
+            })
+            .map(|(&id, _)| id)
+            .collect();
+        for id in &idle {
+            conversations.remove(id);
+        }
+        drop(conversations);
+        self.inner.forget_conversations(&idle);
+        idle.len()
+    }
+
+    /// Stops the idle-sweep thread, if any, and waits for it to exit.
+    pub(crate) fn stop_idle_sweep(&self) {
+        let handle = self.inner.idle_sweep.lock().expect("mutex poisoned").take();
+        if let Some(IdleSweepHandle { stop, thread }) = handle {
+            drop(stop);
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Runs one sweep; returns `false` once the feedback instance is gone.
+fn sweep_once(inner: &Weak<FeedbackInner>) -> bool {
+    let Some(inner) = inner.upgrade() else {
+        return false;
+    };
+    CodexFeedback { inner }.sweep_idle();
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use codex_protocol::ConversationId;
+    use pretty_assertions::assert_eq;
+    use tracing_subscriber::fmt::writer::MakeWriter;
+
+    use super::*;
+    use crate::clock::MockClock;
+
+    #[test]
+    fn idle_conversations_are_freed() {
+        let clock = Arc::new(MockClock::new());
+        let fb = CodexFeedback::with_capacity(1024)
+            .with_clock(clock.clone())
+            .with_conversation_idle_timeout(Duration::from_secs(3600));
+        let write = |id: ConversationId| {
+            fb.make_writer_for(id)
+                .make_writer()
+                .write_all(b"hello\n")
+                .expect("write");
+        };
+        let (idle, active) = (ConversationId::new(), ConversationId::new());
+        write(idle);
+        write(active);
+
+        clock.advance(Duration::from_secs(3000));
+        write(active);
+        assert_eq!(fb.sweep_idle(), 0);
+
+        clock.advance(Duration::from_secs(600));
+        assert_eq!(fb.sweep_idle(), 1);
+        assert!(!fb.has_logs_for(&idle));
+        assert_eq!(fb.conversation_count(), 1);
+        assert_eq!(fb.snapshot(Some(active)).as_bytes(), b"hello\nhello\n");
+
+        fb.stop_idle_sweep();
+        assert!(fb.inner.idle_sweep.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn sweep_without_timeout_keeps_everything() {
+        let fb = CodexFeedback::with_capacity(1024);
+        fb.make_writer_for(ConversationId::new())
+            .make_writer()
+            .write_all(b"hello\n")
+            .expect("write");
+        assert_eq!(fb.sweep_idle(), 0);
+        assert_eq!(fb.conversation_count(), 1);
+    }
+}
@@ -0,0 +1,840 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+
+use chrono::DateTime;
+use chrono::SecondsFormat;
+use chrono::Utc;
+use codex_protocol::ConversationId;
+use serde_json::Value;
+use serde_json::json;
+use tracing::Event;
+use tracing::Level;
+use tracing::Subscriber;
+use tracing::field::Field;
+use tracing::field::Visit;
+use tracing::span;
+use tracing_subscriber::Layer;
+use tracing_subscriber::Registry;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::CodexFeedback;
+use crate::DEFAULT_REDACTION_RULESET;
+use crate::FeedbackInner;
+use crate::wrap_to_width;
+
+/// Oldest events are dropped once this many are buffered.
+pub(crate) const MAX_CAPTURED_EVENTS: usize = 10_000;
+
+/// Only the innermost this-many entered spans are recorded with each event.
+pub(crate) const MAX_CAPTURED_SPAN_DEPTH: usize = 16;
+
+/// Event field naming a Chrome trace phase (`B`, `E`, `X`, `i`, ...); see
+/// [`CodexFeedback::snapshot_chrome_trace`].
+const TRACE_PHASE_FIELD: &str = "phase";
+/// Event field holding a duration in milliseconds; see
+/// [`CodexFeedback::snapshot_chrome_trace`].
+const TRACE_DURATION_FIELD: &str = "duration_ms";
+
+/// Width of the `+12.345s` column in [`CodexFeedback::snapshot_pretty`].
+const PRETTY_TIME_WIDTH: usize = 9;
+/// Width of the level column in [`CodexFeedback::snapshot_pretty`].
+const PRETTY_LEVEL_WIDTH: usize = 5;
+
+/// A span's `conversation_id` field, kept in its [`Registry`] extensions by
+/// [`CodexFeedbackLayer`].
+struct SpanConversation(ConversationId);
+
+/// The conversation of the innermost span, from the current one outwards, that has
+/// a `conversation_id` field; see [`CodexFeedback::make_writer_from_span`]. Only
+/// spans of a [`Registry`]-based subscriber are looked up.
+pub(crate) fn current_conversation() -> Option<ConversationId> {
+    tracing::Span::current()
+        .with_subscriber(|(id, dispatch)| {
+            dispatch
+                .downcast_ref::<Registry>()?
+                .span(id)?
+                .scope()
+                .find_map(|span| {
+                    span.extensions()
+                        .get::<SpanConversation>()
+                        .map(|conversation| conversation.0)
+                })
+        })
+        .flatten()
+}
+
+/// One `tracing` event recorded by [`CodexFeedbackLayer`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedEvent {
+    pub timestamp: DateTime<Utc>,
+    pub level: Level,
+    pub target: String,
+    /// The event's formatted message, or empty if it had none.
+    pub message: String,
+    /// Every field other than `message`, keeping numbers and booleans typed.
+    pub fields: BTreeMap<String, Value>,
+    /// The event's span and its ancestors, outermost first, keeping only the
+    /// innermost [`MAX_CAPTURED_SPAN_DEPTH`].
+    pub spans: Vec<CapturedSpan>,
+}
+
+impl CapturedEvent {
+    pub fn to_json(&self) -> Value {
+        let spans: Vec<Value> = self.spans.iter().map(CapturedSpan::to_json).collect();
+        json!({
+            "timestamp": self.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            "level": self.level.as_str(),
+            "target": self.target,
+            "message": self.message,
+            "fields": self.fields,
+            "spans": spans,
+        })
+    }
+}
+
+/// A span that was entered when a [`CapturedEvent`] fired.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedSpan {
+    pub name: String,
+    /// The span's fields as of the event, including any recorded after creation.
+    pub fields: BTreeMap<String, Value>,
+}
+
+impl CapturedSpan {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "fields": self.fields,
+        })
+    }
+}
+
+/// A `tracing_subscriber` layer that records events as structured
+/// [`CapturedEvent`]s alongside the text buffer; see [`CodexFeedback::layer`].
+#[derive(Clone)]
+pub struct CodexFeedbackLayer {
+    inner: Arc<FeedbackInner>,
+}
+
+impl CodexFeedback {
+    /// Returns a layer that captures structured events into this instance, to be
+    /// installed next to the fmt layer writing through [`CodexFeedback::make_writer`].
+    pub fn layer(&self) -> CodexFeedbackLayer {
+        CodexFeedbackLayer {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Returns the events captured so far by [`CodexFeedback::layer`], oldest first,
+    /// with secrets in messages and string fields (of the event and its spans)
+    /// masked like [`CodexFeedback::snapshot`]'s text. Every structured export
+    /// below is built from these.
+    pub fn snapshot_events(&self) -> Vec<CapturedEvent> {
+        let events: Vec<CapturedEvent> = self
+            .inner
+            .events
+            .lock()
+            .expect("mutex poisoned")
+            .iter()
+            .cloned()
+            .collect();
+        events
+            .into_iter()
+            .map(|event| redact_event(&self.inner, event))
+            .collect()
+    }
+
+    /// Like [`CodexFeedback::snapshot_events`], but only the events whose target is
+    /// one of `allow` or lies under one of them as a module path (`codex_core`
+    /// allows `codex_core::exec`, but not `codex_core_ext`).
+    pub fn snapshot_targets(&self, allow: &[&str]) -> Vec<CapturedEvent> {
+        let events: Vec<CapturedEvent> = self
+            .inner
+            .events
+            .lock()
+            .expect("mutex poisoned")
+            .iter()
+            .filter(|event| {
+                allow
+                    .iter()
+                    .any(|prefix| target_matches(&event.target, prefix))
+            })
+            .cloned()
+            .collect();
+        events
+            .into_iter()
+            .map(|event| redact_event(&self.inner, event))
+            .collect()
+    }
+
+    /// Passes each event from [`CodexFeedback::snapshot_events`] to `into`, oldest
+    /// first, waiting between them for the time that separated them when they were
+    /// captured, divided by `speed` (`2.0` replays twice as fast). A `speed` that is
+    /// not a positive finite number replays without waiting. Waiting goes through
+    /// the injected [`crate::Clock`].
+    pub fn replay(&self, mut into: impl FnMut(&CapturedEvent), speed: f64) {
+        let clock = self.inner.clock.lock().expect("mutex poisoned").clone();
+        let paced = speed.is_finite() && speed > 0.0;
+        let mut previous: Option<DateTime<Utc>> = None;
+        for event in self.snapshot_events() {
+            if let Some(previous) = previous.filter(|_| paced) {
+                let gap = event
+                    .timestamp
+                    .signed_duration_since(previous)
+                    .to_std()
+                    .unwrap_or_default();
+                clock.sleep(gap.div_f64(speed));
+            }
+            previous = Some(event.timestamp);
+            into(&event);
+        }
+    }
+
+    /// Renders [`CodexFeedback::snapshot_events`] as newline-delimited JSON, one
+    /// object per event with `timestamp`, `level`, `target`, `message`, `fields`,
+    /// and `spans` (each with a `name` and `fields`).
+    pub fn snapshot_events_ndjson(&self) -> String {
+        self.snapshot_events()
+            .iter()
+            .map(|event| format!("{}\n", event.to_json()))
+            .collect()
+    }
+
+    /// Renders [`CodexFeedback::snapshot_events`] in the Chrome trace event format
+    /// (`{"traceEvents": [...]}`), as loaded by `chrome://tracing` and Perfetto.
+    ///
+    /// An event's `phase` field, if it is a string, becomes its phase; otherwise an
+    /// event with a numeric `duration_ms` field becomes a complete (`X`) event
+    /// spanning that long from its timestamp, and any other event an instant
+    /// (`i`). The message is the event name, the target its category, and the
+    /// remaining fields its `args`. Events whose timestamp does not fit in
+    /// microseconds since the epoch are left out.
+    pub fn snapshot_chrome_trace(&self) -> String {
+        let pid = std::process::id();
+        let trace_events: Vec<Value> = self
+            .snapshot_events()
+            .iter()
+            .filter_map(|event| chrome_trace_event(event, pid))
+            .collect();
+        json!({
+            "traceEvents": trace_events,
+            "displayTimeUnit": "ms",
+        })
+        .to_string()
+    }
+}
+
+/// Masks secrets in `event`'s message and string fields, and in its spans' string
+/// fields, with the default redaction rule set.
+fn redact_event(inner: &FeedbackInner, mut event: CapturedEvent) -> CapturedEvent {
+    let redact = |text: &mut String| {
+        if let Cow::Owned(redacted) = inner.redact(text, DEFAULT_REDACTION_RULESET) {
+            *text = redacted;
+        }
+    };
+    let redact_fields = |fields: &mut BTreeMap<String, Value>| {
+        for value in fields.values_mut() {
+            if let Value::String(text) = value {
+                redact(text);
+            }
+        }
+    };
+    redact(&mut event.message);
+    redact_fields(&mut event.fields);
+    for span in &mut event.spans {
+        redact_fields(&mut span.fields);
+    }
+    event
+}
+
+fn chrome_trace_event(event: &CapturedEvent, pid: u32) -> Option<Value> {
+    let ts = event.timestamp.timestamp_nanos_opt()? / 1_000;
+    let mut args = event.fields.clone();
+    let phase = args.remove(TRACE_PHASE_FIELD);
+    let duration_ms = args.remove(TRACE_DURATION_FIELD);
+    let duration_us = duration_ms
+        .as_ref()
+        .and_then(Value::as_f64)
+        .map(|ms| (ms * 1_000.0).round() as i64);
+    let phase = match (phase.as_ref().and_then(Value::as_str), duration_us) {
+        (Some(phase), _) => phase.to_string(),
+        (None, Some(_)) => "X".to_string(),
+        (None, None) => "i".to_string(),
+    };
+    let mut trace_event = json!({
+        "name": event.message,
+        "cat": event.target,
+        "ph": phase,
+        "ts": ts,
+        "pid": pid,
+        "tid": 0,
+        "args": args,
+    });
+    match (phase.as_str(), duration_us) {
+        ("X", Some(dur)) => trace_event["dur"] = json!(dur),
+        // Instants are drawn across their thread's track only.
+        ("i", _) => trace_event["s"] = json!("t"),
+        _ => {}
+    }
+    Some(trace_event)
+}
+
+impl CodexFeedback {
+    /// Renders [`CodexFeedback::snapshot_events`] for a terminal: one row per event
+    /// with the time since the first event, the level, and the message followed by
+    /// its fields, in aligned columns. Messages longer than fits in `width` display
+    /// columns (wide CJK characters count twice, as in
+    /// [`crate::CodexLogSnapshot::wrapped`]) wrap onto continuation rows indented
+    /// to the message column.
+    pub fn snapshot_pretty(&self, width: usize) -> String {
+        format_pretty(&self.snapshot_events(), width)
+    }
+}
+
+fn format_pretty(events: &[CapturedEvent], width: usize) -> String {
+    let Some(first) = events.first() else {
+        return String::new();
+    };
+    let indent = PRETTY_TIME_WIDTH + 1 + PRETTY_LEVEL_WIDTH + 1;
+    let message_width = width.saturating_sub(indent).max(1);
+    let mut out = String::new();
+    for event in events {
+        let elapsed = event
+            .timestamp
+            .signed_duration_since(first.timestamp)
+            .num_milliseconds() as f64
+            / 1000.0;
+        let mut text = event.message.clone();
+        for (key, value) in &event.fields {
+            let value = value
+                .as_str()
+                .map_or_else(|| value.to_string(), str::to_string);
+            text.push_str(&format!(" {key}={value}"));
+        }
+        let mut rows = wrap_to_width(&text, message_width).into_iter();
+        let first_row = rows.next().unwrap_or_default();
+        out.push_str(&format!(
+            "{:>PRETTY_TIME_WIDTH$} {:<PRETTY_LEVEL_WIDTH$} {first_row}\n",
+            format!("+{elapsed:.3}s"),
+            event.level.as_str(),
+        ));
+        for row in rows {
+            out.push_str(&format!("{:indent$}{row}\n", ""));
+        }
+    }
+    out
+}
+
+impl<S> Layer<S> for CodexFeedbackLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if !self.inner.enabled {
+            return;
+        }
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let metadata = event.metadata();
+        let timestamp = self.inner.clock.lock().expect("mutex poisoned").now_utc();
+        let mut spans: Vec<CapturedSpan> = ctx
+            .event_scope(event)
+            .into_iter()
+            .flatten()
+            .take(MAX_CAPTURED_SPAN_DEPTH)
+            .filter_map(|span| span.extensions().get::<CapturedSpan>().cloned())
+            .collect();
+        spans.reverse();
+        let captured = CapturedEvent {
+            timestamp,
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            message: visitor.message,
+            fields: visitor.fields,
+            spans,
+        };
+        push_event(
+            &mut self.inner.events.lock().expect("mutex poisoned"),
+            captured,
+        );
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if !self.inner.enabled {
+            return;
+        }
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = ConversationIdVisitor::default();
+        attrs.record(&mut visitor);
+        let mut fields = FieldVisitor::default();
+        attrs.record(&mut fields);
+        // Another instance's layer in the same subscriber may have stored the same
+        // data already.
+        let mut extensions = span.extensions_mut();
+        if let Some(conversation) = visitor.conversation {
+            extensions.replace(SpanConversation(conversation));
+        }
+        extensions.replace(CapturedSpan {
+            name: attrs.metadata().name().to_string(),
+            fields: fields.fields,
+        });
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        values.record(&mut visitor);
+        if let Some(captured) = span.extensions_mut().get_mut::<CapturedSpan>() {
+            captured.fields.extend(visitor.fields);
+        }
+    }
+}
+
+/// Picks up a span's `conversation_id` field, whether recorded as a string, with
+/// `%`, or with `?`.
+#[derive(Default)]
+struct ConversationIdVisitor {
+    conversation: Option<ConversationId>,
+}
+
+impl ConversationIdVisitor {
+    fn parse(&mut self, field: &Field, value: &str) {
+        if field.name() == "conversation_id" {
+            self.conversation = ConversationId::from_string(value.trim_matches('"')).ok();
+        }
+    }
+}
+
+impl Visit for ConversationIdVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.parse(field, value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.parse(field, &format!("{value:?}"));
+    }
+}
+
+fn target_matches(target: &str, prefix: &str) -> bool {
+    target
+        .strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+fn push_event(events: &mut VecDeque<CapturedEvent>, event: CapturedEvent) {
+    if events.len() >= MAX_CAPTURED_EVENTS {
+        events.pop_front();
+    }
+    events.push_back(event);
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: BTreeMap<String, Value>,
+}
+
+impl FieldVisitor {
+    fn insert(&mut self, field: &Field, value: Value) {
+        self.fields.insert(field.name().to_string(), value);
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.insert(field, Value::from(value));
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.insert(field, Value::from(format!("{value:?}")));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use pretty_assertions::assert_eq;
+    use tracing_subscriber::fmt::writer::MakeWriter;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[test]
+    fn ndjson_has_one_parseable_line_per_event() {
+        let fb = CodexFeedback::with_capacity(1024);
+        let subscriber = tracing_subscriber::registry().with(fb.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "codex::turn", turn = 3, ok = true, "turn {} done", 3);
+            tracing::warn!(path = %"/tmp/x", retry = 1.5, "slow write");
+            tracing::error!(code = -2, "no message fields");
+        });
+
+        let ndjson = fb.snapshot_events_ndjson();
+        let lines: Vec<Value> = ndjson
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        for line in &lines {
+            let keys: Vec<&String> = line.as_object().unwrap().keys().collect();
+            assert_eq!(
+                keys,
+                ["fields", "level", "message", "spans", "target", "timestamp"]
+            );
+            assert!(
+                DateTime::parse_from_rfc3339(line["timestamp"].as_str().unwrap()).is_ok(),
+                "{line}"
+            );
+        }
+
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[0]["target"], "codex::turn");
+        assert_eq!(lines[0]["message"], "turn 3 done");
+        assert_eq!(lines[0]["fields"], json!({"turn": 3, "ok": true}));
+        assert_eq!(lines[1]["level"], "WARN");
+        assert_eq!(lines[1]["fields"], json!({"path": "/tmp/x", "retry": 1.5}));
+        assert_eq!(lines[2]["fields"], json!({"code": -2}));
+    }
+
+    #[test]
+    fn events_record_the_entered_span_stack() {
+        let fb = CodexFeedback::with_capacity(1024);
+        let subscriber = tracing_subscriber::registry().with(fb.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            let turn = tracing::info_span!("turn", number = 2, model = tracing::field::Empty);
+            let _turn = turn.enter();
+            turn.record("model", "o3");
+            let exec = tracing::info_span!("exec", cmd = "ls");
+            exec.in_scope(|| tracing::info!("inside"));
+            tracing::info!("outside exec");
+
+            fn nest(depth: usize) {
+                if depth == MAX_CAPTURED_SPAN_DEPTH + 2 {
+                    tracing::info!("deep");
+                } else {
+                    tracing::info_span!("nested", depth).in_scope(|| nest(depth + 1));
+                }
+            }
+            nest(0);
+        });
+
+        let events = fb.snapshot_events();
+        let names = |event: &CapturedEvent| -> Vec<String> {
+            event.spans.iter().map(|span| span.name.clone()).collect()
+        };
+        assert_eq!(names(&events[0]), ["turn", "exec"]);
+        assert_eq!(
+            events[0].spans[0].fields,
+            BTreeMap::from([
+                ("model".to_string(), json!("o3")),
+                ("number".to_string(), json!(2)),
+            ])
+        );
+        assert_eq!(
+            events[0].to_json()["spans"][1],
+            json!({"name": "exec", "fields": {"cmd": "ls"}})
+        );
+        assert_eq!(names(&events[1]), ["turn"]);
+
+        let deep = &events[2].spans;
+        assert_eq!(deep.len(), MAX_CAPTURED_SPAN_DEPTH);
+        assert_eq!(deep[0].fields["depth"], json!(2));
+        assert_eq!(
+            deep[MAX_CAPTURED_SPAN_DEPTH - 1].fields["depth"],
+            json!(MAX_CAPTURED_SPAN_DEPTH + 1)
+        );
+    }
+
+    #[test]
+    fn two_instances_share_span_extensions() {
+        let (first, second) = (CodexFeedback::new(), CodexFeedback::new());
+        let conversation = ConversationId::new();
+        let subscriber = tracing_subscriber::registry()
+            .with(first.layer())
+            .with(second.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            let turn = tracing::info_span!("turn", conversation_id = %conversation);
+            turn.in_scope(|| {
+                tracing::info!("inside");
+                assert_eq!(current_conversation(), Some(conversation));
+            });
+        });
+
+        for fb in [&first, &second] {
+            let events = fb.snapshot_events();
+            assert_eq!(events[0].spans[0].name, "turn");
+        }
+        assert_eq!(current_conversation(), None);
+    }
+
+    #[test]
+    fn exports_redact_fields_and_use_the_injected_clock() {
+        use std::time::Duration;
+
+        use crate::clock::MockClock;
+
+        let clock = Arc::new(MockClock::new());
+        let fb = CodexFeedback::with_capacity(1024).with_clock(clock.clone());
+        let subscriber = tracing_subscriber::registry().with(fb.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("auth", header = "Bearer abcdefghijklmnop");
+            let _entered = span.enter();
+            tracing::info!(
+                key = "sk-abcdefghijklmnopxyz",
+                attempt = 1,
+                "using sk-abcdefghijklmnopxyz"
+            );
+            clock.advance(Duration::from_millis(1_500));
+            tracing::info!("later");
+        });
+
+        let ndjson = fb.snapshot_events_ndjson();
+        assert!(!ndjson.contains("abcdefghijklmnop"), "{ndjson}");
+        let events = fb.snapshot_events();
+        assert_eq!(events[0].message, "using sk-[REDACTED]");
+        assert_eq!(events[0].fields["key"], json!("sk-[REDACTED]"));
+        assert_eq!(events[0].fields["attempt"], json!(1));
+        assert!(!fb.snapshot_chrome_trace().contains("abcdefghijklmnop"));
+        assert!(!fb.snapshot_pretty(200).contains("abcdefghijklmnop"));
+
+        let epoch = DateTime::from_timestamp(MockClock::EPOCH, 0).unwrap();
+        assert_eq!(events[0].timestamp, epoch);
+        assert_eq!(
+            events[1].timestamp,
+            epoch + chrono::Duration::milliseconds(1_500)
+        );
+    }
+
+    #[test]
+    fn chrome_trace_matches_the_trace_event_schema() {
+        let fb = CodexFeedback::with_capacity(1024);
+        let subscriber = tracing_subscriber::registry().with(fb.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "codex::turn", phase = "B", turn = 1, "turn");
+            tracing::info!(target: "codex::exec", duration_ms = 12.5, cmd = "ls", "exec");
+            tracing::warn!("retrying");
+            tracing::info!(target: "codex::turn", phase = "E", "turn");
+        });
+
+        let trace: Value = serde_json::from_str(&fb.snapshot_chrome_trace()).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 4);
+        for event in events {
+            assert!(event["name"].is_string(), "{event}");
+            assert!(event["cat"].is_string(), "{event}");
+            assert!(event["ts"].is_i64(), "{event}");
+            assert!(event["pid"].is_u64(), "{event}");
+            assert!(event["tid"].is_u64(), "{event}");
+            assert!(event["args"].is_object(), "{event}");
+        }
+        let phases: Vec<&str> = events
+            .iter()
+            .map(|event| event["ph"].as_str().unwrap())
+            .collect();
+        assert_eq!(phases, ["B", "X", "i", "E"]);
+        assert!(
+            events
+                .windows(2)
+                .all(|pair| pair[0]["ts"].as_i64() <= pair[1]["ts"].as_i64())
+        );
+
+        assert_eq!(events[0]["args"], json!({"turn": 1}));
+        assert_eq!(events[1]["dur"], json!(12_500));
+        assert_eq!(events[1]["args"], json!({"cmd": "ls"}));
+        assert_eq!(events[2]["s"], "t");
+        assert_eq!(events[3]["name"], "turn");
+    }
+
+    #[test]
+    fn keeps_only_the_newest_events() {
+        let mut events = VecDeque::new();
+        for i in 0..MAX_CAPTURED_EVENTS + 5 {
+            push_event(
+                &mut events,
+                CapturedEvent {
+                    timestamp: Utc::now(),
+                    level: Level::INFO,
+                    target: String::new(),
+                    message: i.to_string(),
+                    fields: BTreeMap::new(),
+                    spans: Vec::new(),
+                },
+            );
+        }
+        assert_eq!(events.len(), MAX_CAPTURED_EVENTS);
+        assert_eq!(events.front().unwrap().message, "5");
+    }
+
+    #[test]
+    fn replay_keeps_order_and_scales_gaps() {
+        use std::time::Duration;
+
+        use crate::Clock;
+        use crate::clock::MockClock;
+
+        let clock = Arc::new(MockClock::new());
+        let fb = CodexFeedback::with_capacity(1024).with_clock(clock.clone());
+        let start = Utc::now();
+        for (millis, message) in [(0, "first"), (100, "second"), (1_100, "third")] {
+            push_event(
+                &mut fb.inner.events.lock().unwrap(),
+                CapturedEvent {
+                    timestamp: start + chrono::Duration::milliseconds(millis),
+                    level: Level::INFO,
+                    target: String::new(),
+                    message: message.to_string(),
+                    fields: BTreeMap::new(),
+                    spans: Vec::new(),
+                },
+            );
+        }
+
+        let began = clock.now();
+        let mut replayed = Vec::new();
+        fb.replay(
+            |event| replayed.push((event.message.clone(), clock.now() - began)),
+            4.0,
+        );
+        let messages: Vec<&str> = replayed.iter().map(|(m, _)| m.as_str()).collect();
+        assert_eq!(messages, ["first", "second", "third"]);
+        for ((_, elapsed), expected_millis) in replayed.iter().zip([0, 25, 275]) {
+            let expected = Duration::from_millis(expected_millis);
+            assert!(
+                elapsed.abs_diff(expected) < Duration::from_micros(1),
+                "{elapsed:?} should be about {expected:?}"
+            );
+        }
+
+        let unpaced = clock.now();
+        fb.replay(|_| {}, f64::INFINITY);
+        assert_eq!(clock.now(), unpaced);
+    }
+
+    #[test]
+    fn pretty_rows_are_aligned_and_wrapped() {
+        let start = Utc::now();
+        let event = |millis: i64, level: Level, message: &str| CapturedEvent {
+            timestamp: start + chrono::Duration::milliseconds(millis),
+            level,
+            target: String::new(),
+            message: message.to_string(),
+            fields: BTreeMap::new(),
+            spans: Vec::new(),
+        };
+        let mut failed = event(12_345, Level::ERROR, "write failed");
+        failed.fields.insert("path".to_string(), json!("/tmp/x"));
+        failed.fields.insert("retries".to_string(), json!(3));
+        let events = [
+            event(0, Level::INFO, "started"),
+            event(
+                1_500,
+                Level::WARN,
+                "a slow operation took longer than expected",
+            ),
+            failed,
+            event(13_000, Level::INFO, "日本語のテキストを表示する"),
+        ];
+
+        assert_eq!(
+            format_pretty(&events, 40),
+            concat!(
+                "  +0.000s INFO  started\n",
+                "  +1.500s WARN  a slow operation took lo\n",
+                "                nger than expected\n",
+                " +12.345s ERROR write failed path=/tmp/x\n",
+                "                 retries=3\n",
+                " +13.000s INFO  日本語のテキストを表示す\n",
+                "                る\n",
+            )
+        );
+    }
+
+    #[test]
+    fn snapshot_targets_keeps_allowed_subtrees() {
+        let fb = CodexFeedback::with_capacity(1024);
+        let subscriber = tracing_subscriber::registry().with(fb.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "codex_core", "core");
+            tracing::info!(target: "codex_core::exec", "exec");
+            tracing::info!(target: "codex_core_ext", "ext");
+            tracing::info!(target: "codex_tui::chat", "chat");
+            tracing::info!(target: "hyper", "http");
+        });
+
+        let messages = |allow: &[&str]| -> Vec<String> {
+            fb.snapshot_targets(allow)
+                .into_iter()
+                .map(|event| event.message)
+                .collect()
+        };
+        assert_eq!(messages(&["codex_core"]), vec!["core", "exec"]);
+        assert_eq!(
+            messages(&["codex_core::exec", "codex_tui"]),
+            vec!["exec", "chat"]
+        );
+        assert_eq!(messages(&[]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn span_conversation_id_routes_writes() {
+        let fb = CodexFeedback::with_capacity(1024);
+        let conversation = ConversationId::new();
+        let make_writer = fb.make_writer_from_span();
+        let write = |text: &str| {
+            make_writer
+                .make_writer()
+                .write_all(text.as_bytes())
+                .unwrap();
+        };
+        let subscriber = tracing_subscriber::registry().with(fb.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            write("before\n");
+            let turn = tracing::info_span!("turn", conversation_id = %conversation);
+            turn.in_scope(|| {
+                write("in turn\n");
+                // Nested spans without the field stay in the same conversation.
+                tracing::info_span!("tool").in_scope(|| write("in tool\n"));
+            });
+            write("after\n");
+        });
+
+        assert_eq!(
+            fb.snapshot(Some(conversation)).as_bytes(),
+            b"in turn\nin tool\n"
+        );
+        assert_eq!(fb.snapshot(None).as_bytes(), b"before\nafter\n");
+    }
+}
@@ -4,20 +4,335 @@
 //! to avoid introducing a dependency on native-tls/OpenSSL and to prevent sending logs
 //! off-box by default. Callers can still save snapshots to disk via `save_to_temp_file`.
 
+use std::borrow::Cow;
+#[cfg(test)]
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
 use std::collections::VecDeque;
+use std::fmt;
 use std::fs;
 use std::io::Write;
 use std::io::{self};
+use std::ops::Range;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::sync::RwLock;
+use std::sync::RwLockReadGuard;
+use std::sync::RwLockWriteGuard;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::Result;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
 use codex_protocol::ConversationId;
+use codex_protocol::protocol::EventMsg;
+use codex_protocol::protocol::FeedbackSubmittedEvent;
+use compress::CompressedHistory;
+use lines::LineSplitter;
+use lines::TrailingNewline;
+use regex_lite::Regex;
+use sha2::Digest;
+use sha2::Sha256;
+use tracing::Level;
 use tracing_subscriber::fmt::writer::MakeWriter;
+use unicode_width::UnicodeWidthChar;
+
+mod autosave;
+mod clock;
+mod compress;
+mod config;
+mod crc;
+mod drop_policy;
+mod encoding;
+mod error;
+mod frame;
+#[cfg(feature = "gzip")]
+mod gzip;
+mod idle;
+mod layer;
+mod lines;
+#[cfg(feature = "log")]
+mod log_bridge;
+mod meta;
+mod metrics;
+mod quota;
+mod redact;
+mod report;
+#[cfg(feature = "serde")]
+mod serde_base64;
+mod shared;
+mod store;
+mod tee;
+mod upload;
+
+pub use clock::Clock;
+pub use clock::SystemClock;
+pub use compress::Compressor;
+pub use compress::Decompressor;
+#[cfg(feature = "gzip")]
+pub use compress::GzipCompressor;
+#[cfg(feature = "zstd")]
+pub use compress::ZstdCompressor;
+pub use config::ByteSize;
+pub use config::FeedbackConfig;
+pub use config::format_bytes;
+pub use config::parse_byte_size;
+pub use drop_policy::DropPolicy;
+pub use encoding::InputEncoding;
+pub use error::FeedbackError;
+pub use layer::CapturedEvent;
+pub use layer::CapturedSpan;
+pub use layer::CodexFeedbackLayer;
+#[cfg(feature = "log")]
+pub use log_bridge::FeedbackLogger;
+pub use meta::HeaderFormat;
+pub use quota::FeedbackDiskQuota;
+pub use redact::RedactionStyle;
+pub use redact::redact_preview;
+pub use report::FeedbackReport;
+pub use shared::SharedSnapshot;
+pub use store::BufferStore;
+pub use store::CircularVecStore;
+pub use store::VecDequeStore;
+pub use upload::UploadRetryPolicy;
 
 const DEFAULT_MAX_BYTES: usize = 4 * 1024 * 1024; // 4 MiB
 
+/// Name of the redaction rule set applied when a snapshot does not name one; see
+/// [`CodexFeedback::add_redaction_ruleset`].
+pub const DEFAULT_REDACTION_RULESET: &str = "default";
+
+/// How much of a line is inspected to tell its level while it is being buffered.
+const LEVEL_PREFIX_LEN: usize = 256;
+
+/// Distinct lines remembered by [`CodexFeedback::snapshot_deduped`].
+pub const MAX_DEDUP_LINES: usize = 100_000;
+
+/// Stands in for each line [`CodexFeedback::snapshot_allowlisted`] does not reveal.
+const HIDDEN_LINE: &str = "[hidden]";
+
+/// Lines kept by [`CodexFeedback::pin_line`]; pinning more drops the oldest pin.
+pub const MAX_PINNED_LINES: usize = 32;
+
+/// First line of [`CodexLogSnapshot::added_lines_since`] when the baseline was
+/// evicted entirely.
+const BASELINE_NOT_FOUND_NOTE: &str = "[baseline not found; showing all lines]";
+
+/// Appended to files written by [`CodexLogSnapshot::save_to_temp_file_limited`] when
+/// the log had to be cut.
+const TRUNCATED_FOOTER: &str = "[truncated to fit]\n";
+
+/// Start of the header line written by [`CodexLogSnapshot::save_to_dir_with_checksum`].
+const CHECKSUM_HEADER_PREFIX: &str = "# codex-feedback ";
+
+/// Schema version of snapshots whose [`CodexFeedback`] did not choose one with
+/// [`CodexFeedback::with_snapshot_schema_version`].
+pub const SNAPSHOT_SCHEMA_VERSION: u32 = 1;
+
+/// Start of the first line of snapshots from a [`CodexFeedback`] configured with
+/// [`CodexFeedback::with_snapshot_schema_version`]; the version number follows.
+const SCHEMA_VERSION_PREFIX: &str = "# codex-feedback-schema: ";
+
+/// Start of the line naming the build in snapshots from a [`CodexFeedback`]
+/// configured with [`CodexFeedback::with_build_info`].
+const BUILD_INFO_PREFIX: &str = "# codex-feedback-build: ";
+
+/// Line ending the prelude written by [`CodexFeedback::with_snapshot_schema_version`]
+/// and [`CodexFeedback::with_build_info`], unless changed with
+/// [`CodexFeedback::with_prelude_separator`].
+pub const DEFAULT_PRELUDE_SEPARATOR: &str = "--- logs ---";
+
+/// `thread_id` of snapshots from [`CodexFeedback::snapshot_anonymous`].
+pub const ANONYMOUS_THREAD_ID: &str = "anon";
+
+/// When set to `1` or `true`, [`CodexFeedback::new`] returns a disabled instance that
+/// discards every write and only ever produces empty snapshots.
+pub const FEEDBACK_DISABLED_ENV_VAR: &str = "CODEX_FEEDBACK_DISABLED";
+
+/// Receives protocol events produced by [`CodexFeedback`], e.g.
+/// [`EventMsg::FeedbackSubmitted`].
+pub type FeedbackEventSink = Arc<dyn Fn(EventMsg) + Send + Sync>;
+
+type RearmableCallback = Arc<dyn Fn() + Send + Sync>;
+
+type BytesThresholdCallback = Arc<dyn Fn(&CodexLogSnapshot) + Send + Sync>;
+
+type LineCallback = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// State for [`CodexFeedback::on_line`].
+struct LineListener {
+    callback: LineCallback,
+    /// Incomplete last line per destination buffer.
+    pending: BTreeMap<Option<ConversationId>, Vec<u8>>,
+}
+
+/// State for [`CodexFeedback::with_min_snapshot_interval`].
+struct SnapshotCache {
+    interval: Duration,
+    /// When each session's last fresh snapshot was taken, and the snapshot. Entries
+    /// older than `interval` are dropped whenever a new one is stored.
+    entries: BTreeMap<Option<ConversationId>, (Instant, Arc<CodexLogSnapshot>)>,
+}
+
+/// State for [`CodexFeedback::with_write_rate_limit`].
+struct WriteRateLimit {
+    bytes_per_window: usize,
+    window_start: Instant,
+    /// Bytes accepted since `window_start`.
+    used: usize,
+    /// Bytes dropped since the last accepted write.
+    dropped: usize,
+}
+
+impl WriteRateLimit {
+    const WINDOW: Duration = Duration::from_secs(1);
+
+    /// Returns `None` if a write of `len` bytes at `now` must be dropped, and
+    /// otherwise the number of bytes dropped since the previous accepted write.
+    fn admit(&mut self, now: Instant, len: usize) -> Option<usize> {
+        if now.saturating_duration_since(self.window_start) >= Self::WINDOW {
+            self.window_start = now;
+            self.used = 0;
+        }
+        if self.used.saturating_add(len) > self.bytes_per_window {
+            self.dropped = self.dropped.saturating_add(len);
+            return None;
+        }
+        self.used += len;
+        Some(std::mem::take(&mut self.dropped))
+    }
+}
+
+/// Recent writes to the shared buffer, for [`CodexFeedback::estimated_time_to_full`].
+#[derive(Default)]
+struct WriteRate {
+    /// When each write happened and how many bytes it carried, oldest first.
+    samples: VecDeque<(Instant, usize)>,
+}
+
+impl WriteRate {
+    const WINDOW: Duration = Duration::from_secs(10);
+
+    fn record(&mut self, now: Instant, len: usize) {
+        self.samples.push_back((now, len));
+        self.expire(now);
+    }
+
+    /// Bytes per second over the window, counting the bytes written after the
+    /// oldest sample across the time since it. `None` without two samples apart
+    /// in time.
+    fn bytes_per_sec(&self, now: Instant) -> Option<f64> {
+        let mut recent = self
+            .samples
+            .iter()
+            .skip_while(|&&(at, _)| now.saturating_duration_since(at) > Self::WINDOW);
+        let &(first, _) = recent.next()?;
+        let elapsed = now.saturating_duration_since(first).as_secs_f64();
+        let bytes: usize = recent.map(|&(_, len)| len).sum();
+        (elapsed > 0.0 && bytes > 0).then(|| bytes as f64 / elapsed)
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(at, _)) = self.samples.front() {
+            if now.saturating_duration_since(at) <= Self::WINDOW {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+}
+
+/// Optional write-path features, each flagged once it is configured, so
+/// [`FeedbackWriter::store`] only takes the locks of the features in use.
+#[derive(Default)]
+struct WriteFeatures(AtomicU32);
+
+impl WriteFeatures {
+    const TEE: u32 = 1 << 0;
+    const WRITE_FILTER: u32 = 1 << 1;
+    const TRANSCODE: u32 = 1 << 2;
+    const NUL_REPLACEMENT: u32 = 1 << 3;
+    const RATE_LIMIT: u32 = 1 << 4;
+    const DROP_PATTERN: u32 = 1 << 5;
+    const BYTES_THRESHOLD: u32 = 1 << 6;
+    const LEVEL_THRESHOLD: u32 = 1 << 7;
+    const LINE_LISTENER: u32 = 1 << 8;
+    const FIRST_WRAP: u32 = 1 << 9;
+    const IDLE_TIMEOUT: u32 = 1 << 10;
+    const MAX_CONVERSATIONS: u32 = 1 << 11;
+
+    /// Flags `feature` as configured. Called after storing the feature's state, so a
+    /// write that sees the flag finds the state under the feature's lock.
+    fn enable(&self, feature: u32) {
+        self.0.fetch_or(feature, Ordering::Release);
+    }
+
+    fn has(&self, feature: u32) -> bool {
+        self.0.load(Ordering::Acquire) & feature != 0
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Locks taken on this thread through [`write_path_lock`].
+    static WRITE_PATH_LOCKS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Passes through the result of taking a lock on the write path, counting it in
+/// tests so they can check how many locks a write takes.
+fn write_path_lock<T>(locked: T) -> T {
+    #[cfg(test)]
+    WRITE_PATH_LOCKS.with(|locks| locks.set(locks.get() + 1));
+    locked
+}
+
+/// A callback that runs once and then waits until the shared buffer is emptied
+/// again; see [`CodexFeedback::on_first_wrap`] and
+/// [`CodexFeedback::on_level_threshold`].
+struct Rearmable {
+    callback: RearmableCallback,
+    armed: bool,
+}
+
+impl Rearmable {
+    fn new(f: impl Fn() + Send + Sync + 'static) -> Self {
+        Self {
+            callback: Arc::new(f),
+            armed: true,
+        }
+    }
+
+    /// Disarms the callback, returning it if it was armed.
+    fn fire(&mut self) -> Option<RearmableCallback> {
+        std::mem::take(&mut self.armed).then(|| self.callback.clone())
+    }
+}
+
+/// State for [`CodexFeedback::on_level_threshold`].
+struct LevelThreshold {
+    /// Buffered length, in bytes, at which the callback fires.
+    at: usize,
+    callback: Rearmable,
+}
+
+/// Registered by [`CodexFeedback::on_bytes_threshold`].
+struct BytesThreshold {
+    every: u64,
+    /// Value of the shared ring's write counter at which the callback fires next.
+    next_at: u64,
+    callback: BytesThresholdCallback,
+}
+
 #[derive(Clone)]
 pub struct CodexFeedback {
     inner: Arc<FeedbackInner>,
@@ -31,184 +346,6779 @@ impl Default for CodexFeedback {
 
 impl CodexFeedback {
     pub fn new() -> Self {
+        if feedback_disabled_by_env() {
+            return Self::disabled();
+        }
         Self::with_capacity(DEFAULT_MAX_BYTES)
     }
 
-    pub(crate) fn with_capacity(max_bytes: usize) -> Self {
-        Self {
-            inner: Arc::new(FeedbackInner::new(max_bytes)),
+    /// Builds a feedback buffer sized according to `cfg`, failing if the configured
+    /// capacity cannot be parsed.
+    pub fn from_config(cfg: &FeedbackConfig) -> Result<Self> {
+        let max_bytes = parse_byte_size(&cfg.capacity)?;
+        if feedback_disabled_by_env() {
+            return Ok(Self::disabled());
         }
+        Ok(Self::with_capacity(max_bytes))
     }
 
-    pub fn make_writer(&self) -> FeedbackMakeWriter {
-        FeedbackMakeWriter {
-            inner: self.inner.clone(),
+    pub(crate) fn with_capacity(max_bytes: impl Into<ByteSize>) -> Self {
+        Self {
+            inner: Arc::new(FeedbackInner::new(max_bytes.into().as_usize())),
         }
     }
 
-    pub fn snapshot(&self, session_id: Option<ConversationId>) -> CodexLogSnapshot {
-        let bytes = {
-            let guard = self.inner.ring.lock().expect("mutex poisoned");
-            guard.snapshot_bytes()
-        };
-        CodexLogSnapshot {
-            bytes,
-            thread_id: session_id
-                .map(|id| id.to_string())
-                .unwrap_or("no-active-thread-".to_string() + &ConversationId::new().to_string()),
+    fn disabled() -> Self {
+        let mut inner = FeedbackInner::new(0);
+        inner.enabled = false;
+        Self {
+            inner: Arc::new(inner),
         }
     }
-}
 
-struct FeedbackInner {
-    ring: Mutex<RingBuffer>,
-}
+    /// Whether this instance buffers anything at all; see [`FEEDBACK_DISABLED_ENV_VAR`].
+    pub fn is_enabled(&self) -> bool {
+        self.inner.enabled
+    }
 
-impl FeedbackInner {
-    fn new(max_bytes: usize) -> Self {
-        Self {
-            ring: Mutex::new(RingBuffer::new(max_bytes)),
+    /// Routes events emitted by this instance (such as [`EventMsg::FeedbackSubmitted`])
+    /// to `sink`, replacing any previously registered sink.
+    pub fn with_event_sink(self, sink: impl Fn(EventMsg) + Send + Sync + 'static) -> Self {
+        *self.inner.event_sink.lock().expect("mutex poisoned") = Some(Arc::new(sink));
+        self
+    }
+
+    /// Keeps at most `max` per-conversation buffers (see
+    /// [`CodexFeedback::make_writer_for`]). When a write would create one more, the
+    /// buffer that was written to least recently is dropped, logs and all.
+    pub fn with_max_conversations(self, max: usize) -> Self {
+        evict_least_recently_written(
+            &mut self.inner.conversations.write().expect("rwlock poisoned"),
+            max,
+        );
+        *self.inner.max_conversations.lock().expect("mutex poisoned") = Some(max);
+        self.inner
+            .write_features
+            .enable(WriteFeatures::MAX_CONVERSATIONS);
+        self
+    }
+
+    /// Number of per-conversation buffers currently held.
+    pub fn conversation_count(&self) -> usize {
+        self.inner
+            .conversations
+            .read()
+            .expect("rwlock poisoned")
+            .len()
+    }
+
+    /// Calls `f` with a snapshot of the shared buffer every time another `threshold`
+    /// bytes have been written to it since registering or since `f` last ran, e.g. to
+    /// save logs by volume instead of on a timer. `f` runs on the writing thread
+    /// after the buffer lock is released.
+    pub fn on_bytes_threshold(
+        &self,
+        threshold: u64,
+        f: impl Fn(&CodexLogSnapshot) + Send + Sync + 'static,
+    ) {
+        let every = threshold.max(1);
+        let written = self.inner.ring_read().total_written;
+        *self.inner.bytes_threshold.lock().expect("mutex poisoned") = Some(BytesThreshold {
+            every,
+            next_at: written.saturating_add(every),
+            callback: Arc::new(f),
+        });
+        self.inner
+            .write_features
+            .enable(WriteFeatures::BYTES_THRESHOLD);
+    }
+
+    /// Decouples writers from readers of the shared buffer, for callers that snapshot
+    /// at a high rate (e.g. a TUI redrawing at 60 Hz). Writers then only append to a
+    /// small staging buffer under a short-lived lock, and each reader swaps that
+    /// buffer out in O(1) and merges it into the ring before copying, so copying a
+    /// large ring never blocks a writer.
+    ///
+    /// Since bytes reach the ring when they are merged rather than when written, line
+    /// times (see [`CodexFeedback::with_line_timestamps`]) record merge times. The
+    /// staging buffer is merged by the writer itself once it reaches the capacity.
+    pub fn with_double_buffering(self) -> Self {
+        self.inner.double_buffered.store(true, Ordering::Relaxed);
+        self
+    }
+
+    /// Prepares for process exit: stops accepting writes, merges any bytes still
+    /// staged by [`CodexFeedback::with_double_buffering`] into the ring, and stops
+    /// and joins the [`CodexFeedback::enable_autosave`], [`CodexFeedback::tee_to`]
+    /// and [`CodexFeedback::with_conversation_idle_timeout`] threads, so a snapshot
+    /// taken afterwards is final.
+    /// Writes and [`CodexFeedback::ingest`] calls after `shutdown` are dropped, and
+    /// no longer mirrored by [`CodexFeedback::with_tee`], although writes are still
+    /// reported as successful.
+    pub fn shutdown(&self) {
+        self.inner.shut_down.store(true, Ordering::SeqCst);
+        self.inner.merge_staged();
+        self.disable_autosave();
+        self.stop_tee();
+        self.stop_idle_sweep();
+    }
+
+    /// Runs `f` once, the first time a write makes any buffer drop data to stay within
+    /// its limits, and not again until the shared buffer is emptied with
+    /// [`CodexFeedback::drain_snapshot`] or [`CodexFeedback::clear_keeping_tail`].
+    /// It runs on the writing thread after the buffer lock is released, so it may
+    /// itself log. Replaces any earlier callback.
+    pub fn on_first_wrap(&self, f: impl Fn() + Send + Sync + 'static) {
+        *self.inner.first_wrap.lock().expect("mutex poisoned") = Some(Rearmable::new(f));
+        self.inner.write_features.enable(WriteFeatures::FIRST_WRAP);
+    }
+
+    /// Runs `f` once, the first time a write leaves the shared buffer at least
+    /// `fraction` full (clamped to `0.0..=1.0`), e.g. to prompt the user to submit
+    /// feedback before history starts being evicted. Like
+    /// [`CodexFeedback::on_first_wrap`], it is re-armed by emptying the shared buffer
+    /// and runs after the buffer lock is released.
+    pub fn on_level_threshold(&self, fraction: f32, f: impl Fn() + Send + Sync + 'static) {
+        let max = self.inner.ring.read().expect("rwlock poisoned").max;
+        let at = (f64::from(fraction.clamp(0.0, 1.0)) * max as f64).ceil() as usize;
+        *self.inner.level_threshold.lock().expect("mutex poisoned") = Some(LevelThreshold {
+            at,
+            callback: Rearmable::new(f),
+        });
+        self.inner
+            .write_features
+            .enable(WriteFeatures::LEVEL_THRESHOLD);
+    }
+
+    /// Calls `f` with every complete line written through this instance's writers,
+    /// without its terminator, as soon as the line is finished. Bytes of an
+    /// unfinished line are held back (up to the buffer capacity) until its newline
+    /// arrives; lines written for different conversations are assembled separately.
+    /// Lines are passed as written, unredacted, and `f` runs after the buffer locks
+    /// are released. Replaces any earlier callback.
+    pub fn on_line(&self, f: impl Fn(&str) + Send + Sync + 'static) {
+        *self.inner.line_listener.lock().expect("mutex poisoned") = Some(LineListener {
+            callback: Arc::new(f),
+            pending: BTreeMap::new(),
+        });
+        self.inner
+            .write_features
+            .enable(WriteFeatures::LINE_LISTENER);
+    }
+
+    /// Also copies everything written through this instance's writers to `sink`
+    /// (e.g. stderr), unredacted, after it is buffered. Mirroring is best effort: the
+    /// first failed write to `sink` drops it, so a broken mirror never costs any
+    /// feedback logs. Flushing a writer flushes `sink` as well, returning its error.
+    pub fn with_tee(self, sink: impl Write + Send + 'static) -> Self {
+        *self.inner.tee.lock().expect("mutex poisoned") = Some(Box::new(sink));
+        self.inner.write_features.enable(WriteFeatures::TEE);
+        self
+    }
+
+    /// Converts everything written through this instance's writers from `encoding`
+    /// to UTF-8 before it is buffered, e.g. for Windows console output captured as
+    /// UTF-16. A byte order mark at the start of the stream is dropped and, for
+    /// UTF-16, overrides the configured byte order. The tee still sees the raw bytes.
+    pub fn with_input_encoding(self, encoding: InputEncoding) -> Self {
+        *self.inner.transcoder.lock().expect("mutex poisoned") =
+            encoding::Transcoder::new(encoding);
+        self.inner.write_features.enable(WriteFeatures::TRANSCODE);
+        self
+    }
+
+    /// Makes writes that the buffer cannot store at all (e.g. into a zero-capacity
+    /// ring) fail with an `io::Error` instead of silently reporting success. Intended
+    /// for tests; the default is lenient.
+    pub fn with_strict_writes(self) -> Self {
+        self.inner.strict_writes.store(true, Ordering::Relaxed);
+        self
+    }
+
+    /// Makes each writer from [`CodexFeedback::make_writer`] (and the other
+    /// `make_writer*` methods) hold back written bytes until they complete a line or
+    /// reach `max_bytes`, then store them in one go, so an event formatted in many
+    /// small `write` calls takes the buffer's lock once rather than per call. What
+    /// is held back is stored when the writer is flushed or dropped. Since every
+    /// writer coalesces only its own writes, lines from different threads still
+    /// land in the order they were completed.
+    pub fn with_write_coalescing(self, max_bytes: impl Into<ByteSize>) -> Self {
+        self.inner
+            .coalesce_limit
+            .store(max_bytes.into().as_usize().max(1), Ordering::Relaxed);
+        self
+    }
+
+    /// Changes the shared buffer's byte capacity, evicting the oldest bytes at once
+    /// if it now holds more. Runs under the buffer's write lock, so it is safe to
+    /// call while other threads write: each write lands entirely before or after
+    /// the change.
+    pub fn set_capacity(&self, max_bytes: impl Into<ByteSize>) {
+        self.inner.ring_write().set_max(max_bytes.into().as_usize());
+    }
+
+    /// Keeps at most the newest `max_lines` newline-terminated lines, evicting whole
+    /// lines from the front regardless of their length. The byte capacity still
+    /// applies as a ceiling so a few huge lines cannot grow the buffer unbounded.
+    pub fn with_line_capacity(self, max_lines: usize) -> Self {
+        self.inner.ring_write().set_max_lines(Some(max_lines));
+        for ring in self
+            .inner
+            .conversations
+            .write()
+            .expect("rwlock poisoned")
+            .values_mut()
+        {
+            ring.set_max_lines(Some(max_lines));
         }
+        *self
+            .inner
+            .conversation_line_capacity
+            .lock()
+            .expect("mutex poisoned") = Some(max_lines);
+        self
     }
-}
 
-#[derive(Clone)]
-pub struct FeedbackMakeWriter {
-    inner: Arc<FeedbackInner>,
-}
+    /// Whether buffers allocate their full byte capacity up front (the default) or
+    /// grow as they fill. Preallocating keeps large allocations out of the logging
+    /// path, at the cost of holding the whole capacity from the start even for
+    /// conversations that log only a few lines. Applies to the shared buffer,
+    /// existing conversation buffers and ones created later.
+    pub fn with_preallocate(self, preallocate: bool) -> Self {
+        self.inner.ring_write().set_preallocate(preallocate);
+        for ring in self
+            .inner
+            .conversations
+            .write()
+            .expect("rwlock poisoned")
+            .values_mut()
+        {
+            ring.set_preallocate(preallocate);
+        }
+        self.inner.preallocate.store(preallocate, Ordering::Relaxed);
+        self
+    }
 
-impl<'a> MakeWriter<'a> for FeedbackMakeWriter {
-    type Writer = FeedbackWriter;
+    /// Keeps bytes evicted from the shared buffer in memory, compressed with `codec`,
+    /// so snapshots of it reach back further than its capacity. The buffer itself
+    /// remains the uncompressed window of the most recent output; evicted bytes are
+    /// compressed in segments of that same size, and the oldest segments are
+    /// dropped once the compressed ones together outgrow it as well. Snapshots
+    /// decompress the whole history first (falling back to just the buffer if that
+    /// fails), trading CPU for a longer history in the same memory. With the `gzip`
+    /// feature, [`CodexFeedback::with_in_memory_gzip`] picks a built-in codec.
+    ///
+    /// History older than a [`CodexFeedback::drain_snapshot`] or another removal
+    /// from the front of the buffer, other than eviction, is discarded.
+    pub fn with_in_memory_compression(
+        self,
+        codec: impl Compressor + Decompressor + Send + Sync + 'static,
+    ) -> Self {
+        {
+            let mut ring = self.inner.ring_write();
+            let segment_len = ring.max;
+            ring.history = Some(CompressedHistory::new(codec, segment_len));
+        }
+        self
+    }
 
-    fn make_writer(&'a self) -> Self::Writer {
-        FeedbackWriter {
-            inner: self.inner.clone(),
+    /// [`CodexFeedback::with_in_memory_compression`] with [`GzipCompressor`], the
+    /// built-in codec for callers without a preferred format.
+    #[cfg(feature = "gzip")]
+    pub fn with_in_memory_gzip(self) -> Self {
+        self.with_in_memory_compression(GzipCompressor)
+    }
+
+    /// Stores buffered bytes in the [`BufferStore`]s returned by `make` instead of
+    /// the default [`VecDequeStore`]: one for the shared buffer, whose current
+    /// contents move over, and one for every conversation buffer created
+    /// afterwards. The buffers keep deciding what is kept and evicted, so the store
+    /// changes only how the bytes are held.
+    pub fn with_buffer_store(
+        self,
+        make: impl Fn() -> Box<dyn BufferStore> + Send + Sync + 'static,
+    ) -> Self {
+        {
+            let mut ring = self.inner.ring_write();
+            ring.set_store(make());
+            ring.set_preallocate(self.inner.preallocate.load(Ordering::Relaxed));
         }
+        *self.inner.buffer_store.lock().expect("mutex poisoned") = Some(Arc::new(make));
+        self
     }
-}
 
-pub struct FeedbackWriter {
-    inner: Arc<FeedbackInner>,
-}
+    /// Indexes the lines of the shared buffer logged at ERROR level, as level-tagged
+    /// fmt layer output is, for [`CodexFeedback::error_offsets`]. Lines already
+    /// buffered are indexed now. Off by default, since it looks at the start of
+    /// every line as it is written.
+    pub fn with_error_index(self) -> Self {
+        self.inner.ring_write().set_error_index(true);
+        self
+    }
 
-impl Write for FeedbackWriter {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let mut guard = self.inner.ring.lock().map_err(|_| io::ErrorKind::Other)?;
-        guard.push_bytes(buf);
-        Ok(buf.len())
+    /// Chooses how secrets (API keys, bearer tokens, `password=...` values) are
+    /// masked in snapshots taken from this instance. Defaults to
+    /// [`RedactionStyle::Full`]. The buffer itself keeps the original bytes.
+    pub fn with_redaction_style(self, style: RedactionStyle) -> Self {
+        *self.inner.redaction.lock().expect("mutex poisoned") = style;
+        self
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        Ok(())
+    /// Registers `patterns` as the redaction rule set `name`, replacing any set of
+    /// that name. Matches are masked in the configured [`RedactionStyle`], on top of
+    /// the built-in secret patterns. The [`DEFAULT_REDACTION_RULESET`] set applies to
+    /// every snapshot and line accessor; other sets only to
+    /// [`CodexFeedback::snapshot_redacted_with`].
+    pub fn add_redaction_ruleset(&self, name: &str, patterns: Vec<Regex>) {
+        self.inner
+            .redaction_rulesets
+            .lock()
+            .expect("mutex poisoned")
+            .insert(name.to_string(), patterns);
     }
-}
 
-struct RingBuffer {
-    max: usize,
-    buf: VecDeque<u8>,
-}
+    /// Bounds the work redaction does per snapshot: after `max_replacements` secrets
+    /// have been masked it stops scanning and appends a `[redaction limit reached]`
+    /// line. Text past that point is not redacted.
+    pub fn with_redaction_limit(self, max_replacements: usize) -> Self {
+        *self.inner.redaction_limit.lock().expect("mutex poisoned") = Some(max_replacements);
+        self
+    }
 
-impl RingBuffer {
-    fn new(capacity: usize) -> Self {
-        Self {
-            max: capacity,
-            buf: VecDeque::with_capacity(capacity),
+    /// Caps how many bytes of each blob [`CodexFeedback::ingest`] keeps; the default
+    /// is half of the shared buffer's capacity.
+    pub fn with_ingest_cap(self, max_bytes: impl Into<ByteSize>) -> Self {
+        *self.inner.ingest_cap.lock().expect("mutex poisoned") = Some(max_bytes.into().as_usize());
+        self
+    }
+
+    /// Drops writes once more than `bytes_per_sec` bytes have been accepted within the
+    /// current one-second window, so a log storm cannot flush everything else out of
+    /// the buffer. Writes are kept or dropped whole. When writes are accepted again,
+    /// a single `[rate limited: dropped N bytes]` line accounts for everything
+    /// dropped in between. Time comes from the injected [`Clock`].
+    pub fn with_write_rate_limit(self, bytes_per_sec: usize) -> Self {
+        let now = self.inner.clock.lock().expect("mutex poisoned").now();
+        *self.inner.write_rate_limit.lock().expect("mutex poisoned") = Some(WriteRateLimit {
+            bytes_per_window: bytes_per_sec,
+            window_start: now,
+            used: 0,
+            dropped: 0,
+        });
+        self.inner.write_features.enable(WriteFeatures::RATE_LIMIT);
+        self
+    }
+
+    /// Discards written lines matching `pattern` (tested without the line terminator)
+    /// instead of buffering them, e.g. periodic heartbeats. A partial line is held
+    /// back until its newline arrives so the whole line can be tested; it does not
+    /// appear in snapshots until then.
+    pub fn with_drop_pattern(self, pattern: Regex) -> Self {
+        *self.inner.drop_pattern.lock().expect("mutex poisoned") = Some(pattern);
+        self.inner
+            .write_features
+            .enable(WriteFeatures::DROP_PATTERN);
+        self
+    }
+
+    /// Calls `filter` with every write before it is buffered (after
+    /// [`CodexFeedback::with_write_coalescing`], before transcoding), discarding the
+    /// write when it returns `false`, e.g. to drop everything during a quiet window.
+    /// It runs on the logging hot path for every write, so it must be cheap and must
+    /// not block; it is called without any buffer lock held.
+    pub fn with_write_filter(self, filter: impl Fn(&[u8]) -> bool + Send + Sync + 'static) -> Self {
+        *self.inner.write_filter.lock().expect("mutex poisoned") = Some(Arc::new(filter));
+        self.inner
+            .write_features
+            .enable(WriteFeatures::WRITE_FILTER);
+        self
+    }
+
+    /// Replaces every NUL byte in written data with `replacement` (e.g. `b'?'`)
+    /// before it is buffered, since NULs confuse many log viewers. Applied after
+    /// [`CodexFeedback::with_input_encoding`] decoding, so UTF-16 input is unaffected
+    /// until then.
+    pub fn with_nul_replacement(self, replacement: u8) -> Self {
+        *self.inner.nul_replacement.lock().expect("mutex poisoned") = Some(replacement);
+        self.inner
+            .write_features
+            .enable(WriteFeatures::NUL_REPLACEMENT);
+        self
+    }
+
+    /// Passes each snapshot's `thread_id` through `mapper` whenever a file name is
+    /// built from it (when saving, and in [`CodexLogSnapshot::to_mime_part`]), e.g.
+    /// to hash ids that should not appear on disk. The `thread_id` field itself keeps
+    /// the raw id.
+    pub fn with_thread_id_mapper(
+        self,
+        mapper: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        *self.inner.thread_id_mapper.lock().expect("mutex poisoned") = Some(Arc::new(mapper));
+        self
+    }
+
+    /// Sets the line written between a snapshot's prelude (the
+    /// [`CodexFeedback::with_snapshot_schema_version`] and
+    /// [`CodexFeedback::with_build_info`] lines) and the logs, so tools can split the
+    /// two. Defaults to [`DEFAULT_PRELUDE_SEPARATOR`]; snapshots without a prelude
+    /// get no separator either.
+    pub fn with_prelude_separator(self, separator: &str) -> Self {
+        *self.inner.prelude_separator.lock().expect("mutex poisoned") = separator.to_string();
+        self
+    }
+
+    /// Stamps snapshots with schema `version`: their text starts with a
+    /// `# codex-feedback-schema: <version>` line, followed by any build line and the
+    /// [`CodexFeedback::with_prelude_separator`] line, and
+    /// [`CodexLogSnapshot::schema_version`] (carried by JSON and frames) is set to
+    /// it. Offsets still count only the buffered bytes. Without this, snapshots
+    /// report [`SNAPSHOT_SCHEMA_VERSION`] and have no marker line.
+    pub fn with_snapshot_schema_version(self, version: u32) -> Self {
+        *self.inner.schema_version.lock().expect("mutex poisoned") = Some(version);
+        self
+    }
+
+    /// Records the build the process came from, so every snapshot carries it: a
+    /// `# codex-feedback-build: version <version>, commit <commit>` line at the top
+    /// (after any schema version line, before the prelude separator), and
+    /// [`CodexLogSnapshot::build_info`], which [`CodexLogSnapshot::to_json`] exports
+    /// as `build`.
+    pub fn with_build_info(self, commit: &str, version: &str) -> Self {
+        *self.inner.build_info.lock().expect("mutex poisoned") = Some(BuildInfo {
+            commit: commit.to_string(),
+            version: version.to_string(),
+        });
+        self
+    }
+
+    /// [`CodexFeedback::with_build_info`] with the values this crate was compiled
+    /// with: the `CODEX_BUILD_COMMIT` environment variable (or `unknown`), and
+    /// `CODEX_BUILD_VERSION` (or the crate version).
+    pub fn with_build_info_from_env(self) -> Self {
+        self.with_build_info(
+            option_env!("CODEX_BUILD_COMMIT").unwrap_or("unknown"),
+            option_env!("CODEX_BUILD_VERSION").unwrap_or(env!("CARGO_PKG_VERSION")),
+        )
+    }
+
+    /// Keeps `line` (e.g. a session header, or the command that was run) at the top
+    /// of every snapshot, after any schema version and build lines, however much
+    /// the buffer churns. Pins are held apart from the buffer, are redacted like
+    /// the rest of the snapshot, and do not count towards its offsets; at most
+    /// [`MAX_PINNED_LINES`] are kept, oldest first.
+    pub fn pin_line(&self, line: &str) {
+        let mut pinned = self.inner.pinned_lines.lock().expect("mutex poisoned");
+        if pinned.len() == MAX_PINNED_LINES {
+            pinned.pop_front();
         }
+        pinned.push_back(line.strip_suffix('\n').unwrap_or(line).to_string());
     }
 
-    fn len(&self) -> usize {
-        self.buf.len()
+    /// Replaces the clock used for time-dependent features; see [`Clock`].
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        self.inner.ring_write().set_clock(&clock);
+        for ring in self
+            .inner
+            .conversations
+            .write()
+            .expect("rwlock poisoned")
+            .values_mut()
+        {
+            ring.set_clock(&clock);
+        }
+        *self.inner.clock.lock().expect("mutex poisoned") = clock;
+        self
     }
 
-    fn push_bytes(&mut self, data: &[u8]) {
-        if data.is_empty() {
-            return;
+    /// Records when each line starts being buffered, so
+    /// [`CodexFeedback::snapshot_with_line_times`] can prefix lines with their time
+    /// relative to this call even if the fmt layer writes no timestamps. Markers are
+    /// dropped together with the bytes they point at, so memory stays bounded.
+    pub fn with_line_timestamps(self) -> Self {
+        let now = self.inner.clock.lock().expect("mutex poisoned").now();
+        self.with_relative_timestamps(now)
+    }
+
+    /// Like [`CodexFeedback::with_line_timestamps`], but line times are measured from
+    /// `base` (e.g. process start) instead of from this call. Times are read from the
+    /// injected [`Clock`], so `base` should come from the same clock.
+    pub fn with_relative_timestamps(self, base: Instant) -> Self {
+        let clock = self.inner.clock.lock().expect("mutex poisoned").clone();
+        self.inner
+            .ring_write()
+            .track_line_times(clock.clone(), base);
+        for ring in self
+            .inner
+            .conversations
+            .write()
+            .expect("rwlock poisoned")
+            .values_mut()
+        {
+            ring.track_line_times(clock.clone(), base);
         }
+        *self.inner.line_times_epoch.lock().expect("mutex poisoned") = Some(base);
+        self
+    }
 
-        // If the incoming chunk is larger than capacity, keep only the trailing bytes.
-        if data.len() >= self.max {
-            self.buf.clear();
-            let start = data.len() - self.max;
-            self.buf.extend(data[start..].iter().copied());
-            return;
+    /// Never evicts lines that started within the last `window` (measured with the
+    /// injected [`Clock`]) to stay within the byte capacity, e.g. where the most
+    /// recent logs must always be kept for compliance: the buffer instead grows
+    /// past its capacity, up to `hard_ceiling` bytes, past which the oldest bytes
+    /// are evicted regardless. Enables [`CodexFeedback::with_line_timestamps`] if it
+    /// is not on yet, since line times decide what is recent. Applies to the shared
+    /// buffer and to conversation buffers.
+    pub fn with_min_retention(self, window: Duration, hard_ceiling: impl Into<ByteSize>) -> Self {
+        let this = if self
+            .inner
+            .line_times_epoch
+            .lock()
+            .expect("mutex poisoned")
+            .is_some()
+        {
+            self
+        } else {
+            self.with_line_timestamps()
+        };
+        let retention = Some(Retention {
+            window,
+            ceiling: hard_ceiling.into().as_usize(),
+        });
+        this.inner.ring_write().set_retention(retention);
+        for ring in this
+            .inner
+            .conversations
+            .write()
+            .expect("rwlock poisoned")
+            .values_mut()
+        {
+            ring.set_retention(retention);
+        }
+        *this.inner.retention.lock().expect("mutex poisoned") = retention;
+        this
+    }
+
+    pub fn make_writer(&self) -> FeedbackMakeWriter {
+        FeedbackMakeWriter {
+            inner: self.inner.clone(),
+            conversation: None,
+            from_span: false,
         }
+    }
 
-        // Evict from the front if we would exceed capacity.
-        let needed = self.len() + data.len();
-        if needed > self.max {
-            let to_drop = needed - self.max;
-            for _ in 0..to_drop {
-                let _ = self.buf.pop_front();
-            }
+    /// Like [`CodexFeedback::make_writer`], but writes go to a buffer dedicated to
+    /// `conversation` (created on first write, with the same capacity as the shared
+    /// one) instead of the shared buffer.
+    pub fn make_writer_for(&self, conversation: ConversationId) -> FeedbackMakeWriter {
+        FeedbackMakeWriter {
+            inner: self.inner.clone(),
+            conversation: Some(conversation),
+            from_span: false,
         }
+    }
 
-        self.buf.extend(data.iter().copied());
+    /// Like [`CodexFeedback::make_writer`], but each writer is routed to the buffer
+    /// of the conversation named by the current span, or the innermost of its
+    /// ancestors, carrying a `conversation_id` field, as if made by
+    /// [`CodexFeedback::make_writer_for`]. Writes outside such a span go to the
+    /// shared buffer. Spans are only seen once [`CodexFeedback::layer`] is installed
+    /// in the same [`tracing_subscriber::Registry`]-based subscriber.
+    pub fn make_writer_from_span(&self) -> FeedbackMakeWriter {
+        FeedbackMakeWriter {
+            inner: self.inner.clone(),
+            conversation: None,
+            from_span: true,
+        }
     }
 
-    fn snapshot_bytes(&self) -> Vec<u8> {
-        self.buf.iter().copied().collect()
+    /// Snapshots the buffer for `session_id`: its per-conversation buffer if one has
+    /// been written through [`CodexFeedback::make_writer_for`], otherwise the shared
+    /// buffer.
+    ///
+    /// With [`CodexFeedback::with_min_snapshot_interval`], a call soon after an
+    /// earlier one for the same `session_id` returns a copy of that result instead.
+    pub fn snapshot(&self, session_id: Option<ConversationId>) -> CodexLogSnapshot {
+        let cache = self.inner.snapshot_cache.lock().expect("mutex poisoned");
+        let Some(interval) = cache.as_ref().map(|cache| cache.interval) else {
+            drop(cache);
+            return self.fresh_snapshot(session_id);
+        };
+        let now = self.inner.clock.lock().expect("mutex poisoned").now();
+        let cached = cache.as_ref().and_then(|cache| {
+            cache
+                .entries
+                .get(&session_id)
+                .filter(|(taken_at, _)| now.saturating_duration_since(*taken_at) < interval)
+                .map(|(_, snapshot)| Arc::clone(snapshot))
+        });
+        // Copying the buffer can take a while; other sessions' lookups must not wait.
+        drop(cache);
+        if let Some(snapshot) = cached {
+            return CodexLogSnapshot::clone(&snapshot);
+        }
+        let snapshot = Arc::new(self.fresh_snapshot(session_id));
+        if let Some(cache) = self
+            .inner
+            .snapshot_cache
+            .lock()
+            .expect("mutex poisoned")
+            .as_mut()
+        {
+            cache
+                .entries
+                .retain(|_, (taken_at, _)| now.saturating_duration_since(*taken_at) < interval);
+            cache
+                .entries
+                .insert(session_id, (now, Arc::clone(&snapshot)));
+        }
+        Arc::unwrap_or_clone(snapshot)
     }
-}
 
-pub struct CodexLogSnapshot {
-    bytes: Vec<u8>,
-    pub thread_id: String,
-}
+    /// Like [`CodexFeedback::snapshot`] for the shared buffer, but holding only
+    /// complete lines: a first line whose start was evicted and a last line
+    /// without its newline yet are both left out, and the offsets narrowed to
+    /// match. The cleanest form for feedback reports.
+    pub fn snapshot_whole_lines(&self) -> CodexLogSnapshot {
+        let ring = self.inner.ring_read();
+        let bytes = ring.snapshot_bytes();
+        let offsets = ring.offsets();
+        let truncated = ring.truncated();
+        let start = if ring.starts_mid_line() {
+            bytes
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(bytes.len(), |i| i + 1)
+        } else {
+            0
+        };
+        drop(ring);
+        let end = bytes
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1)
+            .max(start);
+        self.inner.snapshot_from(
+            bytes[start..end].to_vec(),
+            offsets.start + start as u64..offsets.start + end as u64,
+            truncated,
+            thread_id_for(None),
+        )
+    }
 
-impl CodexLogSnapshot {
-    pub(crate) fn as_bytes(&self) -> &[u8] {
-        &self.bytes
+    fn fresh_snapshot(&self, session_id: Option<ConversationId>) -> CodexLogSnapshot {
+        self.snapshot_many(&[session_id])
+            .pop()
+            .expect("one snapshot per id")
     }
 
-    pub fn save_to_temp_file(&self) -> io::Result<PathBuf> {
-        let dir = std::env::temp_dir();
-        let filename = format!("codex-feedback-{}.log", self.thread_id);
-        let path = dir.join(filename);
-        fs::write(&path, self.as_bytes())?;
-        Ok(path)
+    /// Makes [`CodexFeedback::snapshot`] return its previous result for the same
+    /// `session_id` when called again within `interval` (measured with the
+    /// injected [`Clock`]), so a misbehaving UI snapshotting in a tight loop does
+    /// not copy the whole buffer every time. The returned data can be up to
+    /// `interval` old; callers that need fresher data should pick a shorter one.
+    /// Other snapshot methods are not cached.
+    pub fn with_min_snapshot_interval(self, interval: Duration) -> Self {
+        *self.inner.snapshot_cache.lock().expect("mutex poisoned") = Some(SnapshotCache {
+            interval,
+            entries: BTreeMap::new(),
+        });
+        self
     }
 
-    /// No-op in this fork: feedback uploads are disabled.
+    /// Moves the buffered bytes for `session_id` (chosen as in
+    /// [`CodexFeedback::snapshot`]) out into a snapshot, leaving that buffer empty.
     ///
-    /// This method used to stream snapshots to Sentry over HTTPS, which pulled in
-    /// `native-tls`/OpenSSL. To keep this fork's binaries OpenSSL-free and avoid
-    /// sending logs to a remote service by default, the implementation is now a no-op
-    /// while preserving the public API for callers.
-    pub fn upload_feedback(
+    /// Unlike `snapshot`, the lock is only held long enough to swap in a fresh
+    /// buffer; the data is converted after it is released, so writers are not
+    /// blocked by copying a large ring.
+    pub fn drain_snapshot(&self, session_id: Option<ConversationId>) -> CodexLogSnapshot {
+        self.inner.version.fetch_add(1, Ordering::Relaxed);
+        let drained = session_id.and_then(|id| {
+            self.inner
+                .conversations
+                .write()
+                .expect("rwlock poisoned")
+                .get_mut(&id)
+                .map(|ring| (ring.truncated(), ring.drain()))
+        });
+        let (truncated, (buf, offsets)) = drained.unwrap_or_else(|| {
+            let mut ring = self.inner.ring_write();
+            let drained = (ring.truncated(), ring.drain());
+            drop(ring);
+            self.inner.rearm_callbacks();
+            drained
+        });
+        self.inner
+            .snapshot_from(buf, offsets, truncated, thread_id_for(session_id))
+    }
+
+    /// Frees the buffer dedicated to `id` (see [`CodexFeedback::make_writer_for`]),
+    /// first saving its contents to `dir` when a directory is given and the buffer
+    /// holds anything. Returns the saved path, if a file was written.
+    ///
+    /// The buffer is removed before the file is written, so it is freed even if
+    /// saving fails; later writes for `id` start a fresh buffer.
+    pub fn close_conversation(
         &self,
-        _classification: &str,
-        _reason: Option<&str>,
-        _include_logs: bool,
-        _rollout_path: Option<&std::path::Path>,
-    ) -> Result<()> {
-        Ok(())
+        id: ConversationId,
+        dir: Option<&Path>,
+    ) -> Result<Option<PathBuf>, FeedbackError> {
+        let ring = self
+            .inner
+            .conversations
+            .write()
+            .expect("rwlock poisoned")
+            .remove(&id);
+        self.inner.forget_conversations(&[id]);
+        let (Some(mut ring), Some(dir)) = (ring, dir) else {
+            return Ok(None);
+        };
+        if ring.len() == 0 {
+            return Ok(None);
+        }
+        let truncated = ring.truncated();
+        let (buf, offsets) = ring.drain();
+        self.inner
+            .snapshot_from(buf, offsets, truncated, id.to_string())
+            .save_to_dir(dir)
+            .map(Some)
     }
-}
 
-#[allow(dead_code)]
-fn display_classification(classification: &str) -> String {
-    match classification {
-        "bug" => "Bug".to_string(),
-        "bad_result" => "Bad result".to_string(),
-        "good_result" => "Good result".to_string(),
-        _ => "Other".to_string(),
+    /// Removes every complete line from the shared buffer and returns them (redacted,
+    /// without terminators), oldest first. A trailing partial line stays in the
+    /// buffer so the rest of it can still be written.
+    pub fn drain_lines(&self) -> Vec<String> {
+        let bytes = self.inner.ring_write().drain_complete_lines();
+        self.inner.redacted_lines(&bytes)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// The last `n` lines of the shared buffer (redacted, without terminators),
+    /// oldest first. Unlike [`CodexLogSnapshot::tail`], only those lines are copied.
+    pub fn tail(&self, n: usize) -> Vec<String> {
+        let bytes = self.inner.ring_read().tail_bytes(n);
+        self.inner.redacted_lines(&bytes)
+    }
 
-    #[test]
-    fn ring_buffer_drops_front_when_full() {
-        let fb = CodexFeedback::with_capacity(8);
+    /// The complete lines written to the shared buffer since `cursor` (redacted,
+    /// without terminators) and a cursor to pass next time, so a tailing UI can poll
+    /// without snapshotting everything. A trailing partial line is left for a later
+    /// call. Start from [`TailCursor::default`] for everything still buffered or
+    /// [`CodexFeedback::tail_cursor`] for only what comes next.
+    ///
+    /// If bytes after `cursor` were evicted in the meantime, the lines start with a
+    /// `[... N bytes evicted ...]` marker, and the first line after it may be the
+    /// end of one cut short.
+    pub fn tail_since(&self, cursor: TailCursor) -> (Vec<String>, TailCursor) {
+        let ring = self.inner.ring_read();
+        let offsets = ring.offsets();
+        let end = ring.line_starts.back().copied().unwrap_or(offsets.start);
+        let from = cursor.offset.clamp(offsets.start, offsets.end);
+        let bytes: Vec<u8> = if from < end {
+            ring.bytes_in((from - offsets.start) as usize..(end - offsets.start) as usize)
+        } else {
+            Vec::new()
+        };
+        drop(ring);
+
+        let mut lines = Vec::new();
+        if cursor.offset < offsets.start {
+            lines.push(format!(
+                "[... {} bytes evicted ...]",
+                offsets.start - cursor.offset
+            ));
+        }
+        lines.extend(self.inner.redacted_lines(&bytes));
+        (
+            lines,
+            TailCursor {
+                offset: from.max(end),
+            },
+        )
+    }
+
+    /// A cursor at the current end of the shared buffer, for
+    /// [`CodexFeedback::tail_since`] to return only lines written from now on.
+    pub fn tail_cursor(&self) -> TailCursor {
+        TailCursor {
+            offset: self.inner.ring_read().offsets().end,
+        }
+    }
+
+    /// Runs `f` and returns its result along with a snapshot of just what was
+    /// written to the shared buffer meanwhile, measured from a
+    /// [`CodexFeedback::tail_cursor`] taken before the call. That includes writes
+    /// made on other threads while `f` ran, not only its own. If some of them were
+    /// already evicted, the snapshot's `start_offset` is past the cursor.
+    pub fn capture<R>(&self, f: impl FnOnce() -> R) -> (R, CodexLogSnapshot) {
+        let cursor = self.tail_cursor();
+        let result = f();
+        let ring = self.inner.ring_read();
+        let offsets = ring.offsets();
+        let from = cursor.offset.clamp(offsets.start, offsets.end);
+        let bytes = ring.bytes_in((from - offsets.start) as usize..ring.len());
+        let truncated = from > cursor.offset && ring.truncated();
+        drop(ring);
+        let snapshot =
+            self.inner
+                .snapshot_from(bytes, from..offsets.end, truncated, thread_id_for(None));
+        (result, snapshot)
+    }
+
+    /// Empties the shared buffer except for its last `n_lines` complete lines (and
+    /// any partial line still being written), so a report taken after submitting
+    /// one still has some context.
+    pub fn clear_keeping_tail(&self, n_lines: usize) {
+        let mut ring = self.inner.ring_write();
+        let Some(keep) = ring
+            .line_starts
+            .len()
+            .checked_sub(n_lines.saturating_add(1))
+        else {
+            return;
+        };
+        let start = ring.line_starts[keep] - ring.offsets().start;
+        ring.drain_front(start as usize);
+        drop(ring);
+        self.inner.rearm_callbacks();
+    }
+
+    /// Removes every complete line of the shared buffer (matched without its
+    /// terminator, lossily decoded, before redaction) for which `keep` returns
+    /// false, e.g. to scrub a customer name before exporting. A trailing partial
+    /// line is kept as is, since the rest of it has yet to arrive. The buffer only
+    /// ever shrinks, so it stays within its byte capacity. Returns how many lines
+    /// were removed.
+    pub fn retain_lines(&self, keep: impl Fn(&str) -> bool) -> usize {
+        self.inner
+            .ring_write()
+            .retain_lines(|line| keep(&String::from_utf8_lossy(line)))
+    }
+
+    /// Number of lines in the shared buffer, counting a trailing partial line.
+    pub fn line_count(&self) -> usize {
+        self.inner.ring_read().line_count()
+    }
+
+    /// Splits the shared buffer at the last line containing `marker`, e.g. the line
+    /// logged when a new conversation starts: everything before that line is
+    /// removed and returned as a snapshot, and the buffer keeps the marker line and
+    /// what follows. Returns `None` if no line contains `marker` or nothing precedes
+    /// it.
+    pub fn rotate_on_marker(&self, marker: &str) -> Option<CodexLogSnapshot> {
+        let (truncated, (bytes, offsets)) = {
+            let mut ring = self.inner.ring_write();
+            let start = ring
+                .last_line_start_containing(marker.as_bytes())
+                .filter(|&start| start > 0)?;
+            (ring.truncated(), ring.drain_front(start))
+        };
+        Some(
+            self.inner
+                .snapshot_from(bytes, offsets, truncated, thread_id_for(None)),
+        )
+    }
+
+    /// Like [`CodexFeedback::snapshot`], but masking with the redaction rule set
+    /// `ruleset` instead of the default one. An unknown name applies only the
+    /// built-in secret patterns.
+    pub fn snapshot_redacted_with(
+        &self,
+        session_id: Option<ConversationId>,
+        ruleset: &str,
+    ) -> CodexLogSnapshot {
+        let (bytes, offsets, truncated) = self.raw_bytes_for(session_id);
+        self.inner.snapshot_redacted_from(
+            bytes,
+            offsets,
+            truncated,
+            thread_id_for(session_id),
+            ruleset,
+        )
+    }
+
+    /// Like [`CodexFeedback::snapshot`], but for anonymized corpora: the thread id is
+    /// [`ANONYMOUS_THREAD_ID`] instead of the conversation id, which also keeps the
+    /// id out of the saved file name and JSON, and UUIDs in the log text are
+    /// replaced with `<id>`.
+    pub fn snapshot_anonymous(&self, session_id: Option<ConversationId>) -> CodexLogSnapshot {
+        let (bytes, offsets, truncated) = self.raw_bytes_for(session_id);
+        let mut snapshot =
+            self.inner
+                .snapshot_from(bytes, offsets, truncated, ANONYMOUS_THREAD_ID.to_string());
+        if let Cow::Owned(text) =
+            uuid_regex().replace_all(&String::from_utf8_lossy(&snapshot.bytes), "<id>")
         {
-            let mut w = fb.make_writer().make_writer();
-            w.write_all(b"abcdefgh").unwrap();
-            w.write_all(b"ij").unwrap();
+            snapshot.bytes = text.into_bytes();
         }
-        let snap = fb.snapshot(None);
-        // Capacity 8: after writing 10 bytes, we should keep the last 8.
-        pretty_assertions::assert_eq!(std::str::from_utf8(snap.as_bytes()).unwrap(), "cdefghij");
+        snapshot
+    }
+
+    /// Like [`CodexFeedback::snapshot`], but first passes the whole (lossily
+    /// decoded) log through `scrub`, for custom masking that patterns cannot
+    /// express, such as looking up known internal hostnames. The built-in and
+    /// [`DEFAULT_REDACTION_RULESET`] redaction still runs on the scrubbed text.
+    pub fn snapshot_scrubbed(
+        &self,
+        session_id: Option<ConversationId>,
+        scrub: impl Fn(String) -> String,
+    ) -> CodexLogSnapshot {
+        let (bytes, offsets, truncated) = self.raw_bytes_for(session_id);
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+        self.inner.snapshot_from(
+            scrub(text).into_bytes(),
+            offsets,
+            truncated,
+            thread_id_for(session_id),
+        )
+    }
+
+    /// Like [`CodexFeedback::snapshot`], but starting at the most recent line logged
+    /// at ERROR level (inclusive), since that is usually where the interesting
+    /// context begins. Returns everything if no ERROR line is buffered.
+    pub fn snapshot_since_last_error(
+        &self,
+        session_id: Option<ConversationId>,
+    ) -> CodexLogSnapshot {
+        let (mut bytes, mut offsets, truncated) = self.raw_bytes_for(session_id);
+
+        let mut start = None;
+        let mut line_start = 0;
+        for line in bytes.split_inclusive(|&b| b == b'\n') {
+            if line_level(&String::from_utf8_lossy(line)) == Some(Level::ERROR) {
+                start = Some(line_start);
+            }
+            line_start += line.len();
+        }
+        if let Some(start) = start {
+            bytes.drain(..start);
+            offsets.start += start as u64;
+        }
+        self.inner
+            .snapshot_from(bytes, offsets, truncated, thread_id_for(session_id))
+    }
+
+    /// Like [`CodexFeedback::snapshot`], but only the region of one operation: from
+    /// the last line containing `begin` through the next line after it containing
+    /// `end` (both inclusive), or to the end of the buffer if no such line follows.
+    /// Returns `None` if no line contains `begin`.
+    pub fn snapshot_between_markers(
+        &self,
+        session_id: Option<ConversationId>,
+        begin: &str,
+        end: &str,
+    ) -> Option<CodexLogSnapshot> {
+        let (bytes, offsets, truncated) = self.raw_bytes_for(session_id);
+        let lines: Vec<(usize, &[u8])> = LineSplitter::new(TrailingNewline::Keep)
+            .split_bytes(&bytes)
+            .collect();
+        let contains = |line: &[u8], marker: &str| String::from_utf8_lossy(line).contains(marker);
+        let first = lines.iter().rposition(|&(_, line)| contains(line, begin))?;
+        let start = lines[first].0;
+        let stop = lines[first + 1..]
+            .iter()
+            .find(|&&(_, line)| contains(line, end))
+            .map_or(bytes.len(), |&(pos, line)| pos + line.len());
+        Some(self.inner.snapshot_from(
+            bytes[start..stop].to_vec(),
+            offsets.start + start as u64..offsets.start + stop as u64,
+            truncated,
+            thread_id_for(session_id),
+        ))
+    }
+
+    /// Like [`CodexFeedback::snapshot`], but keeps only as many of each level's most
+    /// recent lines as fit in `budgets[level]` bytes, e.g. 64 KiB of DEBUG and every
+    /// ERROR line. Levels without a budget, and lines whose level cannot be told,
+    /// are kept in full; continuation lines count towards the level of the line
+    /// they follow. Once a level's budget is spent, none of its older lines are
+    /// kept, so what remains of each level is a contiguous recent run.
+    ///
+    /// The result keeps the buffer's offsets even though lines in between may be
+    /// missing.
+    pub fn snapshot_budgeted(
+        &self,
+        session_id: Option<ConversationId>,
+        budgets: &BTreeMap<Level, usize>,
+    ) -> CodexLogSnapshot {
+        let (bytes, offsets, truncated) = self.raw_bytes_for(session_id);
+        let text = String::from_utf8_lossy(&bytes);
+        let mut level = None;
+        let lines: Vec<(Option<Level>, &str)> = LineSplitter::new(TrailingNewline::Keep)
+            .split(&text)
+            .map(|line| {
+                if let Some(found) = line_level(line) {
+                    level = Some(found);
+                }
+                (level, line)
+            })
+            .collect();
+
+        let mut remaining = budgets.clone();
+        let mut keep = vec![true; lines.len()];
+        for (keep, (level, line)) in keep.iter_mut().zip(&lines).rev() {
+            let Some(budget) = level.and_then(|level| remaining.get_mut(&level)) else {
+                continue;
+            };
+            if line.len() > *budget {
+                // Spend the rest so no older line of this level fits either.
+                *budget = 0;
+                *keep = false;
+            } else {
+                *budget -= line.len();
+            }
+        }
+        let selected: String = lines
+            .iter()
+            .zip(keep)
+            .filter_map(|((_, line), keep)| keep.then_some(*line))
+            .collect();
+        self.inner.snapshot_from(
+            selected.into_bytes(),
+            offsets,
+            truncated,
+            thread_id_for(session_id),
+        )
+    }
+
+    /// Like [`CodexFeedback::snapshot`], but collapsed for a quick overview: each
+    /// run of consecutive lines at the same level becomes one line, the run's first
+    /// line prefixed with its level and length, e.g. `[INFO x12] ...`. As in
+    /// [`CodexFeedback::snapshot_budgeted`], continuation lines count towards the
+    /// level of the line they follow; lines before the first one showing a level
+    /// are grouped as `[- xN]`.
+    pub fn snapshot_grouped(&self, session_id: Option<ConversationId>) -> CodexLogSnapshot {
+        let (bytes, offsets, truncated) = self.raw_bytes_for(session_id);
+        let text = String::from_utf8_lossy(&bytes);
+        let mut runs: Vec<(Option<Level>, usize, &str)> = Vec::new();
+        let mut level = None;
+        for line in LineSplitter::new(TrailingNewline::Keep).split(&text) {
+            if let Some(found) = line_level(line) {
+                level = Some(found);
+            }
+            match runs.last_mut() {
+                Some((run_level, count, _)) if *run_level == level => *count += 1,
+                _ => runs.push((level, 1, line)),
+            }
+        }
+        let grouped: String = runs
+            .into_iter()
+            .map(|(level, count, first)| {
+                let label = level.as_ref().map_or("-", Level::as_str);
+                format!("[{label} x{count}] {first}")
+            })
+            .collect();
+        self.inner.snapshot_from(
+            grouped.into_bytes(),
+            offsets,
+            truncated,
+            thread_id_for(session_id),
+        )
+    }
+
+    /// Like [`CodexFeedback::snapshot`], but for maximum privacy: every line that
+    /// matches none of `patterns` (tested without its terminator) is replaced by
+    /// `[hidden]`, so only known-safe lines such as specific error codes are
+    /// revealed. Lines pinned with [`CodexFeedback::pin_line`] are shown as is.
+    pub fn snapshot_allowlisted(
+        &self,
+        session_id: Option<ConversationId>,
+        patterns: &[Regex],
+    ) -> CodexLogSnapshot {
+        let (bytes, offsets, truncated) = self.raw_bytes_for(session_id);
+        let text = String::from_utf8_lossy(&bytes);
+        let masked: String = LineSplitter::new(TrailingNewline::Keep)
+            .split(&text)
+            .map(|line| {
+                let content = line.strip_suffix('\n').unwrap_or(line);
+                let content = content.strip_suffix('\r').unwrap_or(content);
+                if patterns.iter().any(|pattern| pattern.is_match(content)) {
+                    Cow::Borrowed(line)
+                } else {
+                    Cow::Owned(format!("{HIDDEN_LINE}{}", &line[content.len()..]))
+                }
+            })
+            .collect();
+        self.inner.snapshot_from(
+            masked.into_bytes(),
+            offsets,
+            truncated,
+            thread_id_for(session_id),
+        )
+    }
+
+    /// Like [`CodexFeedback::snapshot`], but drops every line that is an exact
+    /// duplicate of an earlier one, e.g. the same warning repeated throughout a
+    /// session, keeping first occurrences in order. The live buffer is untouched.
+    ///
+    /// At most [`MAX_DEDUP_LINES`] distinct lines are remembered; lines first seen
+    /// after that are always kept.
+    pub fn snapshot_deduped(&self, session_id: Option<ConversationId>) -> CodexLogSnapshot {
+        let (bytes, offsets, truncated) = self.raw_bytes_for(session_id);
+        let text = String::from_utf8_lossy(&bytes);
+        let mut seen = HashSet::new();
+        let deduped: String = LineSplitter::new(TrailingNewline::Keep)
+            .split(&text)
+            .filter(|line| {
+                let key = line.strip_suffix('\n').unwrap_or(line);
+                if seen.contains(key) {
+                    return false;
+                }
+                if seen.len() < MAX_DEDUP_LINES {
+                    seen.insert(key);
+                }
+                true
+            })
+            .collect();
+        self.inner.snapshot_from(
+            deduped.into_bytes(),
+            offsets,
+            truncated,
+            thread_id_for(session_id),
+        )
+    }
+
+    /// Copies the raw bytes and offsets that [`CodexFeedback::snapshot`] would use
+    /// for `session_id`, before redaction, and whether bytes before them were
+    /// evicted.
+    fn raw_bytes_for(&self, session_id: Option<ConversationId>) -> (Vec<u8>, Range<u64>, bool) {
+        let ring = self.inner.ring_read();
+        let conversations = self.inner.conversations.read().expect("rwlock poisoned");
+        let source = session_id
+            .and_then(|id| conversations.get(&id))
+            .unwrap_or(&ring);
+        source.snapshot_window()
+    }
+
+    /// How long until the shared buffer holds as many bytes as it can keep, if
+    /// writes continue at the average rate of the last ten seconds (measured on the
+    /// injected [`Clock`]). `None` when nothing has been written in that window;
+    /// zero when the buffer is already full.
+    pub fn estimated_time_to_full(&self) -> Option<Duration> {
+        let ring = self.inner.ring_read();
+        let rate = ring.write_rate.bytes_per_sec(ring.clock.now())?;
+        let remaining = ring.max.saturating_sub(ring.len());
+        Some(Duration::from_secs_f64(remaining as f64 / rate))
+    }
+
+    /// Whether the shared buffer currently holds no bytes.
+    pub fn is_empty(&self) -> bool {
+        self.inner.ring_read().len() == 0
+    }
+
+    /// Whether [`CodexFeedback::snapshot`] for `id` would return any bytes, checked
+    /// without copying. Uses the conversation's own buffer when it has one and
+    /// otherwise falls back to the shared buffer, like `snapshot` does.
+    pub fn has_logs_for(&self, id: &ConversationId) -> bool {
+        let conversations = self.inner.conversations.read().expect("rwlock poisoned");
+        match conversations.get(id) {
+            Some(ring) => ring.len() > 0,
+            None => {
+                drop(conversations);
+                !self.is_empty()
+            }
+        }
+    }
+
+    /// Approximate heap memory held by this instance: the allocated capacity of the
+    /// shared and per-conversation buffers (which can exceed their length), their
+    /// line-time markers, and the captured-event queue.
+    pub fn memory_footprint(&self) -> usize {
+        let ring = self.inner.ring_read().memory_footprint();
+        let conversations: usize = self
+            .inner
+            .conversations
+            .read()
+            .expect("rwlock poisoned")
+            .values()
+            .map(|ring| std::mem::size_of::<ConversationId>() + ring.memory_footprint())
+            .sum();
+        let events = self.inner.events.lock().expect("mutex poisoned").capacity()
+            * std::mem::size_of::<CapturedEvent>();
+        let staged = self.inner.staged.lock().expect("mutex poisoned").capacity();
+        ring + conversations + events + staged
+    }
+
+    /// Like [`CodexFeedback::snapshot`], but every line whose start time was recorded
+    /// (see [`CodexFeedback::with_line_timestamps`]) is prefixed with `[+1.234s] `.
+    pub fn snapshot_with_line_times(&self, session_id: Option<ConversationId>) -> CodexLogSnapshot {
+        let ring = self.inner.ring_read();
+        let conversations = self.inner.conversations.read().expect("rwlock poisoned");
+        let source = session_id
+            .and_then(|id| conversations.get(&id))
+            .unwrap_or(&ring);
+        let (bytes, offsets) = (source.snapshot_bytes_with_times(), source.offsets());
+        let truncated = source.truncated();
+        drop(conversations);
+        drop(ring);
+        self.inner
+            .snapshot_from(bytes, offsets, truncated, thread_id_for(session_id))
+    }
+
+    /// Takes one snapshot per id under a single lock acquisition, so every returned
+    /// snapshot reflects the same point in time.
+    pub fn snapshot_many(&self, ids: &[Option<ConversationId>]) -> Vec<CodexLogSnapshot> {
+        let ring = self.inner.ring_read();
+        let conversations = self.inner.conversations.read().expect("rwlock poisoned");
+        let mut shared = None;
+        ids.iter()
+            .map(|id| {
+                let (bytes, offsets, truncated) = match id.and_then(|id| conversations.get(&id)) {
+                    Some(conversation) => conversation.snapshot_window(),
+                    None => shared.get_or_insert_with(|| ring.snapshot_window()).clone(),
+                };
+                self.inner
+                    .snapshot_from(bytes, offsets, truncated, thread_id_for(*id))
+            })
+            .collect()
+    }
+
+    /// Snapshots every per-conversation buffer under a single lock acquisition.
+    ///
+    /// The result is sorted by [`ConversationId`], so exports built from it are
+    /// reproducible. The shared buffer is not included.
+    pub fn snapshot_all(&self) -> Vec<CodexLogSnapshot> {
+        let conversations = self.inner.conversations.read().expect("rwlock poisoned");
+        conversations
+            .iter()
+            .map(|(id, ring)| {
+                self.inner.snapshot_from(
+                    ring.snapshot_bytes(),
+                    ring.offsets(),
+                    ring.truncated(),
+                    id.to_string(),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the full snapshot together with its last `tail_bytes` bytes as text,
+    /// both taken from a single copy of the ring. The tail is trimmed forward to a
+    /// UTF-8 character boundary, so it may be slightly shorter than `tail_bytes`.
+    pub fn snapshot_with_tail(
+        &self,
+        session_id: Option<ConversationId>,
+        tail_bytes: impl Into<ByteSize>,
+    ) -> (CodexLogSnapshot, String) {
+        let snapshot = self.snapshot(session_id);
+        let tail_bytes = tail_bytes.into().as_usize();
+        let tail = String::from_utf8_lossy(utf8_tail(snapshot.as_bytes(), tail_bytes)).into_owned();
+        (snapshot, tail)
+    }
+
+    /// Copies the most recent bytes of the shared buffer that fit into `buf` and
+    /// returns how many were written, without allocating. As in
+    /// [`CodexFeedback::snapshot_with_tail`], the copy may be slightly shorter so
+    /// it starts at a UTF-8 character boundary. The bytes are raw: no redaction is
+    /// applied.
+    pub fn snapshot_to_slice(&self, buf: &mut [u8]) -> usize {
+        let ring = self.inner.ring_read();
+        let mut start = ring.len().saturating_sub(buf.len());
+        start += ring
+            .iter()
+            .skip(start)
+            .take_while(|&&b| (b & 0b1100_0000) == 0b1000_0000)
+            .count();
+        let len = ring.len() - start;
+        for (dst, &src) in buf.iter_mut().zip(ring.iter().skip(start)) {
+            *dst = src;
+        }
+        len
+    }
+
+    /// Saves a snapshot for `session_id` to the temp dir, unless the previous call
+    /// for the same `session_id` saved one less than `min_interval` ago (on the
+    /// injected [`Clock`]), in which case nothing is written and that earlier path
+    /// is returned. Meant for UI
+    /// bindings such as an auto-repeating key, so they cannot hammer the disk.
+    pub fn save_to_temp_file_throttled(
+        &self,
+        session_id: Option<ConversationId>,
+        min_interval: Duration,
+    ) -> Result<PathBuf, FeedbackError> {
+        self.save_to_dir_throttled(session_id, &std::env::temp_dir(), min_interval)
+    }
+
+    fn save_to_dir_throttled(
+        &self,
+        session_id: Option<ConversationId>,
+        dir: &Path,
+        min_interval: Duration,
+    ) -> Result<PathBuf, FeedbackError> {
+        let now = self.inner.clock.lock().expect("mutex poisoned").now();
+        let mut last = self
+            .inner
+            .last_throttled_save
+            .lock()
+            .expect("mutex poisoned");
+        let recent = |saved_at: &Instant| now.saturating_duration_since(*saved_at) < min_interval;
+        if let Some((_, path)) = last
+            .get(&session_id)
+            .filter(|(saved_at, _)| recent(saved_at))
+        {
+            return Ok(path.clone());
+        }
+        let path = self.snapshot(session_id).save_to_dir(dir)?;
+        last.retain(|_, (saved_at, _)| recent(saved_at));
+        last.insert(session_id, (now, path.clone()));
+        Ok(path)
+    }
+
+    /// Saves a snapshot for `session_id` into `dir` and reports it to the registered
+    /// event sink as an [`EventMsg::FeedbackSubmitted`].
+    pub fn submit(
+        &self,
+        session_id: Option<ConversationId>,
+        classification: &str,
+        dir: &Path,
+    ) -> Result<PathBuf, FeedbackError> {
+        let path = self.snapshot(session_id).save_to_dir(dir)?;
+        let sink = self
+            .inner
+            .event_sink
+            .lock()
+            .expect("mutex poisoned")
+            .clone();
+        if let Some(sink) = sink {
+            sink(EventMsg::FeedbackSubmitted(FeedbackSubmittedEvent {
+                conversation_id: session_id,
+                classification: classification.to_string(),
+                path: path.clone(),
+            }));
+        }
+        Ok(path)
+    }
+
+    /// Appends a delimited block of externally captured bytes (e.g. a failed
+    /// subprocess's stderr) to the buffer in a single write:
+    ///
+    /// ```text
+    /// --- begin {label} ---
+    /// {data}
+    /// --- end {label} ---
+    /// ```
+    ///
+    /// So that one huge blob cannot evict all earlier logs, `data` is cut to its last
+    /// [`CodexFeedback::with_ingest_cap`] bytes (half the capacity by default), with
+    /// a `[... N bytes truncated ...]` line in place of what was dropped.
+    pub fn ingest(&self, label: &str, data: &[u8]) {
+        if !self.inner.enabled || self.inner.shut_down.load(Ordering::SeqCst) {
+            return;
+        }
+        let mut guard = self.inner.ring_write();
+        let cap = self
+            .inner
+            .ingest_cap
+            .lock()
+            .expect("mutex poisoned")
+            .unwrap_or(guard.max / 2);
+        let mut evicted = guard.push_bytes(format!("--- begin {label} ---\n").as_bytes());
+        if data.len() > cap {
+            let kept = utf8_tail(data, cap);
+            let note = format!("[... {} bytes truncated ...]\n", data.len() - kept.len());
+            evicted |= guard.push_bytes(note.as_bytes());
+            evicted |= guard.push_bytes(kept);
+        } else {
+            evicted |= guard.push_bytes(data);
+        }
+        if !data.is_empty() && !data.ends_with(b"\n") {
+            evicted |= guard.push_bytes(b"\n");
+        }
+        evicted |= guard.push_bytes(format!("--- end {label} ---\n").as_bytes());
+        drop(guard);
+        if evicted {
+            self.inner.notify_first_wrap();
+        }
+    }
+
+    /// Returns a guard over the buffered bytes that can be iterated line by line
+    /// without first copying the ring into a contiguous buffer.
+    ///
+    /// The guard holds the ring's read lock, so writers block until it is dropped.
+    pub fn lines_guard(&self) -> LinesGuard<'_> {
+        LinesGuard {
+            ring: self.inner.ring_read(),
+        }
+    }
+
+    /// Calls `f` with the shared buffer's contents as the two halves of the ring
+    /// (see [`BufferStore::as_slices`]), oldest first, without copying them. The bytes
+    /// are raw: no redaction is applied.
+    ///
+    /// The ring's read lock is held while `f` runs, so writers block until it returns.
+    pub fn with_slices<R>(&self, f: impl FnOnce(&[u8], &[u8]) -> R) -> R {
+        let ring = self.inner.ring_read();
+        let (front, back) = ring.buf.as_slices();
+        f(front, back)
+    }
+
+    /// Number of lines in the shared buffer matching `pattern`, e.g. for a
+    /// "47 warnings, 3 errors" summary. Scans the raw (unredacted) ring once under
+    /// the lock without copying it; only a line split by the ring's wrap-around is
+    /// assembled.
+    pub fn count_matches(&self, pattern: &Regex) -> usize {
+        self.inner.ring_read().count_matching_lines(pattern)
+    }
+
+    /// Byte positions, within the shared buffer, of each line logged at ERROR level,
+    /// oldest first, for "next error" navigation. The index is kept up to date as
+    /// lines are written and evicted. Positions match
+    /// [`CodexFeedback::snapshot`]'s bytes unless redaction changed their length.
+    /// Always empty unless [`CodexFeedback::with_error_index`] is set.
+    pub fn error_offsets(&self) -> Vec<usize> {
+        self.inner.ring_read().error_offsets()
+    }
+
+    /// A counter that changes whenever buffered content may have changed: on every
+    /// write and on every operation that removes content. Comparing it against a
+    /// stored value lets a UI skip redrawing when nothing changed; reading the
+    /// buffer never changes it.
+    pub fn version(&self) -> u64 {
+        self.inner.version.load(Ordering::Relaxed)
+    }
+
+    /// CRC-32 (IEEE) of the shared buffer's raw bytes, maintained as they are written
+    /// and evicted, so integrity can be checked without rehashing the whole ring.
+    pub fn crc32(&self) -> u32 {
+        self.inner.ring_read().crc
+    }
+
+    /// Feeds the shared buffer's raw bytes into `hasher` as two slices, straight from
+    /// the ring under its read lock, without copying them. Streaming hashers give the
+    /// same result as hashing a copy of [`CodexFeedback::with_slices`] in one call;
+    /// no redaction is applied.
+    pub fn hash_into(&self, hasher: &mut impl std::hash::Hasher) {
+        let ring = self.inner.ring_read();
+        let (front, back) = ring.buf.as_slices();
+        hasher.write(front);
+        hasher.write(back);
+    }
+
+    /// Path of the most recent file written by saving one of this instance's
+    /// snapshots, if any.
+    pub fn last_saved_path(&self) -> Option<PathBuf> {
+        self.inner
+            .last_saved_path
+            .lock()
+            .expect("mutex poisoned")
+            .clone()
+    }
+
+    /// Installs a process-wide panic hook that saves the current buffer into `dir`
+    /// before delegating to the previously installed hook.
+    ///
+    /// The ring is only read if its lock is free: a panic raised while a writer holds
+    /// it would otherwise deadlock the hook.
+    pub fn install_panic_hook(&self, dir: &Path) {
+        let inner = self.inner.clone();
+        let dir = dir.to_path_buf();
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if let Ok(guard) = inner.ring.try_read() {
+                let (bytes, offsets) = (guard.snapshot_bytes(), guard.offsets());
+                let truncated = guard.truncated();
+                drop(guard);
+                let snapshot = inner.snapshot_from(
+                    bytes,
+                    offsets,
+                    truncated,
+                    format!("panic-{}", ConversationId::new()),
+                );
+                let _ = snapshot.save_to_dir(&dir);
+            }
+            previous(info);
+        }));
+    }
+}
+
+struct FeedbackInner {
+    /// Snapshots only need shared access, so they take the read lock and never block
+    /// each other; writers take the write lock.
+    ring: RwLock<RingBuffer>,
+    /// Buffers written through [`CodexFeedback::make_writer_for`]. Kept in one map
+    /// behind one lock so they can all be snapshotted at the same instant.
+    conversations: RwLock<BTreeMap<ConversationId, RingBuffer>>,
+    conversation_capacity: usize,
+    /// Line cap applied to conversation buffers created after
+    /// [`CodexFeedback::with_line_capacity`].
+    conversation_line_capacity: Mutex<Option<usize>>,
+    /// Set by [`CodexFeedback::with_max_conversations`].
+    max_conversations: Mutex<Option<usize>>,
+    /// Source of [`RingBuffer::last_write`].
+    write_tick: AtomicU64,
+    /// See [`CodexFeedback::version`].
+    version: AtomicU64,
+    enabled: bool,
+    event_sink: Mutex<Option<FeedbackEventSink>>,
+    strict_writes: AtomicBool,
+    /// Set by [`CodexFeedback::with_write_coalescing`]; 0 when off.
+    coalesce_limit: AtomicUsize,
+    redaction: Mutex<RedactionStyle>,
+    redaction_limit: Mutex<Option<usize>>,
+    redaction_rulesets: Mutex<BTreeMap<String, Vec<Regex>>>,
+    drop_pattern: Mutex<Option<Regex>>,
+    write_filter: Mutex<Option<WriteFilter>>,
+    write_rate_limit: Mutex<Option<WriteRateLimit>>,
+    /// Which optional write-path features are configured.
+    write_features: WriteFeatures,
+    ingest_cap: Mutex<Option<usize>>,
+    nul_replacement: Mutex<Option<u8>>,
+    thread_id_mapper: Mutex<Option<ThreadIdMapper>>,
+    schema_version: Mutex<Option<u32>>,
+    build_info: Mutex<Option<BuildInfo>>,
+    /// Set by [`CodexFeedback::with_prelude_separator`].
+    prelude_separator: Mutex<String>,
+    /// When [`CodexFeedback::save_to_temp_file_throttled`] last wrote for each
+    /// session, and where.
+    last_throttled_save: Mutex<BTreeMap<Option<ConversationId>, (Instant, PathBuf)>>,
+    pinned_lines: Mutex<VecDeque<String>>,
+    /// Set by [`CodexFeedback::with_buffer_store`] for conversation buffers created
+    /// afterwards.
+    buffer_store: Mutex<Option<StoreFactory>>,
+    /// Set by [`CodexFeedback::with_min_retention`] for conversation buffers created
+    /// afterwards.
+    retention: Mutex<Option<Retention>>,
+    snapshot_cache: Mutex<Option<SnapshotCache>>,
+    /// Set by [`CodexFeedback::with_tee`].
+    tee: Mutex<Option<Box<dyn Write + Send>>>,
+    transcoder: Mutex<encoding::Transcoder>,
+    autosave: Mutex<Option<autosave::AutosaveHandle>>,
+    /// Set by [`CodexFeedback::with_conversation_idle_timeout`].
+    idle_timeout: Mutex<Option<Duration>>,
+    idle_sweep: Mutex<Option<idle::IdleSweepHandle>>,
+    periodic_tee: Mutex<Option<tee::PeriodicTeeHandle>>,
+    clock: Mutex<Arc<dyn Clock>>,
+    /// Set by [`CodexFeedback::with_line_timestamps`]; conversation buffers created
+    /// afterwards record line times relative to it.
+    line_times_epoch: Mutex<Option<Instant>>,
+    /// Structured events recorded by [`CodexFeedbackLayer`].
+    events: Mutex<VecDeque<CapturedEvent>>,
+    /// Set by [`CodexFeedback::with_drop_policy`].
+    drop_policy: Mutex<DropPolicy>,
+    /// Whether any snapshot has been taken, for [`DropPolicy::Warn`].
+    snapshotted: AtomicBool,
+    /// Run by the first write that evicts anything; see
+    /// [`CodexFeedback::on_first_wrap`].
+    first_wrap: Mutex<Option<Rearmable>>,
+    level_threshold: Mutex<Option<LevelThreshold>>,
+    line_listener: Mutex<Option<LineListener>>,
+    /// Set by [`CodexFeedback::with_double_buffering`]: shared-buffer writes only
+    /// append to `staged`, which readers merge into `ring`.
+    double_buffered: AtomicBool,
+    /// Set by [`CodexFeedback::shutdown`].
+    shut_down: AtomicBool,
+    /// Set by [`CodexFeedback::with_preallocate`] for conversation buffers created
+    /// afterwards.
+    preallocate: AtomicBool,
+    staged: Mutex<Vec<u8>>,
+    bytes_threshold: Mutex<Option<BytesThreshold>>,
+    /// Number of snapshots taken so far; see [`CodexLogSnapshot::sequence`].
+    next_sequence: AtomicU64,
+    /// Shared with every snapshot taken from this instance so saving one records its path.
+    last_saved_path: SavedPathSlot,
+}
+
+impl FeedbackInner {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            ring: RwLock::new(RingBuffer::new(max_bytes).with_preallocate(true)),
+            conversations: RwLock::new(BTreeMap::new()),
+            conversation_capacity: max_bytes,
+            conversation_line_capacity: Mutex::new(None),
+            max_conversations: Mutex::new(None),
+            write_tick: AtomicU64::new(0),
+            version: AtomicU64::new(0),
+            enabled: true,
+            event_sink: Mutex::new(None),
+            strict_writes: AtomicBool::new(false),
+            coalesce_limit: AtomicUsize::new(0),
+            redaction: Mutex::new(RedactionStyle::default()),
+            redaction_limit: Mutex::new(None),
+            redaction_rulesets: Mutex::new(BTreeMap::new()),
+            drop_pattern: Mutex::new(None),
+            write_filter: Mutex::new(None),
+            write_rate_limit: Mutex::new(None),
+            write_features: WriteFeatures::default(),
+            ingest_cap: Mutex::new(None),
+            nul_replacement: Mutex::new(None),
+            thread_id_mapper: Mutex::new(None),
+            schema_version: Mutex::new(None),
+            build_info: Mutex::new(None),
+            prelude_separator: Mutex::new(DEFAULT_PRELUDE_SEPARATOR.to_string()),
+            last_throttled_save: Mutex::new(BTreeMap::new()),
+            pinned_lines: Mutex::new(VecDeque::new()),
+            buffer_store: Mutex::new(None),
+            retention: Mutex::new(None),
+            snapshot_cache: Mutex::new(None),
+            tee: Mutex::new(None),
+            transcoder: Mutex::new(encoding::Transcoder::default()),
+            autosave: Mutex::new(None),
+            idle_timeout: Mutex::new(None),
+            idle_sweep: Mutex::new(None),
+            periodic_tee: Mutex::new(None),
+            clock: Mutex::new(Arc::new(SystemClock)),
+            line_times_epoch: Mutex::new(None),
+            events: Mutex::new(VecDeque::new()),
+            drop_policy: Mutex::new(DropPolicy::default()),
+            snapshotted: AtomicBool::new(false),
+            first_wrap: Mutex::new(None),
+            level_threshold: Mutex::new(None),
+            line_listener: Mutex::new(None),
+            double_buffered: AtomicBool::new(false),
+            shut_down: AtomicBool::new(false),
+            preallocate: AtomicBool::new(true),
+            staged: Mutex::new(Vec::new()),
+            bytes_threshold: Mutex::new(None),
+            next_sequence: AtomicU64::new(0),
+            last_saved_path: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Wraps bytes copied from this instance's ring into a snapshot that reports back
+    /// to [`CodexFeedback::last_saved_path`] when saved, redacting secrets on the way.
+    fn snapshot_from(
+        &self,
+        bytes: Vec<u8>,
+        offsets: Range<u64>,
+        truncated: bool,
+        thread_id: String,
+    ) -> CodexLogSnapshot {
+        self.snapshot_redacted_from(
+            bytes,
+            offsets,
+            truncated,
+            thread_id,
+            DEFAULT_REDACTION_RULESET,
+        )
+    }
+
+    /// Like [`FeedbackInner::snapshot_from`], but applying the named rule set (see
+    /// [`CodexFeedback::add_redaction_ruleset`]) instead of the default one.
+    fn snapshot_redacted_from(
+        &self,
+        bytes: Vec<u8>,
+        offsets: Range<u64>,
+        truncated: bool,
+        thread_id: String,
+        ruleset: &str,
+    ) -> CodexLogSnapshot {
+        self.snapshotted.store(true, Ordering::Relaxed);
+        let pinned: String = self
+            .pinned_lines
+            .lock()
+            .expect("mutex poisoned")
+            .iter()
+            .map(|line| format!("{line}\n"))
+            .collect();
+        let bytes = if pinned.is_empty() {
+            bytes
+        } else {
+            [pinned.as_bytes(), &bytes].concat()
+        };
+        let bytes = match self.redact(&String::from_utf8_lossy(&bytes), ruleset) {
+            Cow::Owned(redacted) => redacted.into_bytes(),
+            Cow::Borrowed(_) => bytes,
+        };
+        let schema_version = *self.schema_version.lock().expect("mutex poisoned");
+        let build_info = self.build_info.lock().expect("mutex poisoned").clone();
+        let mut prelude = String::new();
+        if let Some(version) = schema_version {
+            prelude.push_str(&format!("{SCHEMA_VERSION_PREFIX}{version}\n"));
+        }
+        if let Some(build) = &build_info {
+            prelude.push_str(&format!(
+                "{BUILD_INFO_PREFIX}version {}, commit {}\n",
+                build.version, build.commit
+            ));
+        }
+        if !prelude.is_empty() {
+            let separator = self.prelude_separator.lock().expect("mutex poisoned");
+            prelude.push_str(&format!("{separator}\n"));
+        }
+        let bytes = if prelude.is_empty() {
+            bytes
+        } else {
+            [prelude.as_bytes(), &bytes].concat()
+        };
+        CodexLogSnapshot {
+            build_info,
+            schema_version: schema_version.unwrap_or(SNAPSHOT_SCHEMA_VERSION),
+            start_offset: offsets.start,
+            end_offset: offsets.end,
+            truncated,
+            sequence: self.next_sequence.fetch_add(1, Ordering::Relaxed) + 1,
+            saved_path: Some(self.last_saved_path.clone()),
+            thread_id_mapper: self
+                .thread_id_mapper
+                .lock()
+                .expect("mutex poisoned")
+                .clone(),
+            ..CodexLogSnapshot::new(bytes, thread_id)
+        }
+    }
+
+    /// Masks built-in secrets, then anything matched by the named rule set.
+    fn redact<'a>(&self, text: &'a str, ruleset: &str) -> Cow<'a, str> {
+        let style = *self.redaction.lock().expect("mutex poisoned");
+        let limit = *self.redaction_limit.lock().expect("mutex poisoned");
+        let text = redact::redact_secrets(text, style, limit);
+        let rulesets = self.redaction_rulesets.lock().expect("mutex poisoned");
+        let Some(patterns) = rulesets.get(ruleset) else {
+            return text;
+        };
+        match redact::redact_patterns(&text, patterns, style) {
+            Cow::Owned(redacted) => Cow::Owned(redacted),
+            Cow::Borrowed(_) => text,
+        }
+    }
+
+    /// Redacts `bytes` and splits them into lines without terminators.
+    fn redacted_lines(&self, bytes: &[u8]) -> Vec<String> {
+        let text = String::from_utf8_lossy(bytes);
+        LineSplitter::default()
+            .split(&self.redact(&text, DEFAULT_REDACTION_RULESET))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Pushes `buf` into `ring`, returning whether anything was evicted to make room.
+    fn push_checked(&self, ring: &mut RingBuffer, buf: &[u8]) -> io::Result<bool> {
+        if ring.max == 0 && !buf.is_empty() && self.strict_writes.load(Ordering::Relaxed) {
+            return Err(io::Error::other(
+                "feedback buffer has zero capacity; write discarded",
+            ));
+        }
+        let mut evicted = false;
+        let mut limit = self
+            .write_features
+            .has(WriteFeatures::RATE_LIMIT)
+            .then(|| write_path_lock(self.write_rate_limit.lock()).expect("mutex poisoned"));
+        if let Some(limit) = limit.as_mut().and_then(|limit| limit.as_mut()) {
+            let now = ring.clock.now();
+            match limit.admit(now, buf.len()) {
+                None => return Ok(false),
+                Some(0) => {}
+                Some(dropped) => {
+                    evicted = ring.push_bytes(
+                        format!("[rate limited: dropped {dropped} bytes]\n").as_bytes(),
+                    );
+                }
+            }
+        }
+        drop(limit);
+        if !self.write_features.has(WriteFeatures::DROP_PATTERN) {
+            return Ok(ring.push_bytes(buf) || evicted);
+        }
+        let drop_pattern = write_path_lock(self.drop_pattern.lock()).expect("mutex poisoned");
+        let Some(pattern) = drop_pattern.as_ref() else {
+            return Ok(ring.push_bytes(buf) || evicted);
+        };
+        let kept = ring
+            .filter_complete_lines(buf, |line| pattern.is_match(&String::from_utf8_lossy(line)));
+        Ok(ring.push_bytes(&kept) || evicted)
+    }
+
+    /// Once [`CodexFeedback::on_bytes_threshold`]'s threshold of new bytes has been
+    /// written to `ring`, re-arms it and returns the callback with the bytes to
+    /// snapshot, so the caller can run it after releasing the lock.
+    fn check_bytes_threshold(
+        &self,
+        ring: &RingBuffer,
+    ) -> Option<(BytesThresholdCallback, Vec<u8>, Range<u64>, bool)> {
+        if !self.write_features.has(WriteFeatures::BYTES_THRESHOLD) {
+            return None;
+        }
+        let mut guard = write_path_lock(self.bytes_threshold.lock()).expect("mutex poisoned");
+        let threshold = guard.as_mut()?;
+        if ring.total_written < threshold.next_at {
+            return None;
+        }
+        threshold.next_at = ring.total_written.saturating_add(threshold.every);
+        Some((
+            threshold.callback.clone(),
+            ring.snapshot_bytes(),
+            ring.offsets(),
+            ring.truncated(),
+        ))
+    }
+
+    /// Appends `buf` to the [`CodexFeedback::on_line`] listener's partial line for
+    /// `conversation`, returning the callback and the lines it completed.
+    fn complete_lines(
+        &self,
+        conversation: Option<ConversationId>,
+        buf: &[u8],
+    ) -> Option<(LineCallback, Vec<String>)> {
+        if !self.write_features.has(WriteFeatures::LINE_LISTENER) {
+            return None;
+        }
+        let mut guard = write_path_lock(self.line_listener.lock()).expect("mutex poisoned");
+        let listener = guard.as_mut()?;
+        let pending = listener.pending.entry(conversation).or_default();
+        pending.extend_from_slice(buf);
+        let Some(end) = pending.iter().rposition(|&b| b == b'\n') else {
+            let excess = pending.len().saturating_sub(self.conversation_capacity);
+            pending.drain(..excess);
+            return None;
+        };
+        let complete: Vec<u8> = pending.drain(..=end).collect();
+        let splitter = LineSplitter::default();
+        let lines = complete
+            .split_inclusive(|&b| b == b'\n')
+            .map(|line| {
+                String::from_utf8_lossy(splitter.finish_line(&line[..line.len() - 1])).into_owned()
+            })
+            .collect();
+        Some((listener.callback.clone(), lines))
+    }
+
+    /// Fires the [`CodexFeedback::on_level_threshold`] callback once `ring` is full
+    /// enough, returning it so the caller can run it after releasing the lock.
+    fn check_level_threshold(&self, ring: &RingBuffer) -> Option<RearmableCallback> {
+        if !self.write_features.has(WriteFeatures::LEVEL_THRESHOLD) {
+            return None;
+        }
+        let mut guard = write_path_lock(self.level_threshold.lock()).expect("mutex poisoned");
+        let threshold = guard
+            .as_mut()
+            .filter(|threshold| threshold.at <= ring.len())?;
+        threshold.callback.fire()
+    }
+
+    /// Copies `raw` to the [`CodexFeedback::with_tee`] sink, best effort: a sink
+    /// that fails (e.g. a closed stderr) is dropped, and later writes skip it.
+    fn mirror(&self, raw: &[u8]) {
+        if !self.write_features.has(WriteFeatures::TEE) {
+            return;
+        }
+        let mut tee = write_path_lock(self.tee.lock()).expect("mutex poisoned");
+        if tee
+            .as_mut()
+            .is_some_and(|sink| sink.write_all(raw).is_err())
+        {
+            *tee = None;
+        }
+    }
+
+    /// Re-arms the [`CodexFeedback::on_first_wrap`] and
+    /// [`CodexFeedback::on_level_threshold`] callbacks after the shared buffer was
+    /// emptied.
+    fn rearm_callbacks(&self) {
+        if let Some(first_wrap) = self.first_wrap.lock().expect("mutex poisoned").as_mut() {
+            first_wrap.armed = true;
+        }
+        if let Some(threshold) = self
+            .level_threshold
+            .lock()
+            .expect("mutex poisoned")
+            .as_mut()
+        {
+            threshold.callback.armed = true;
+        }
+    }
+
+    /// Drops per-conversation state kept outside the conversation buffers once
+    /// `ids` are closed or swept.
+    fn forget_conversations(&self, ids: &[ConversationId]) {
+        if let Some(listener) = self.line_listener.lock().expect("mutex poisoned").as_mut() {
+            for id in ids {
+                listener.pending.remove(&Some(*id));
+            }
+        }
+        if let Some(cache) = self.snapshot_cache.lock().expect("mutex poisoned").as_mut() {
+            for id in ids {
+                cache.entries.remove(&Some(*id));
+            }
+        }
+        let mut throttled = self.last_throttled_save.lock().expect("mutex poisoned");
+        for id in ids {
+            throttled.remove(&Some(*id));
+        }
+    }
+
+    fn run_bytes_threshold(
+        &self,
+        reached: Option<(BytesThresholdCallback, Vec<u8>, Range<u64>, bool)>,
+    ) {
+        if let Some((callback, bytes, offsets, truncated)) = reached {
+            callback(&self.snapshot_from(bytes, offsets, truncated, thread_id_for(None)));
+        }
+    }
+
+    /// Moves bytes staged by double-buffered writers (see
+    /// [`CodexFeedback::with_double_buffering`]) into the shared ring. The staging
+    /// buffer is swapped out while the ring's write lock is held, so concurrent
+    /// merges cannot reorder chunks.
+    fn merge_staged(&self) {
+        if !self.double_buffered.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut ring = write_path_lock(self.ring.write()).expect("rwlock poisoned");
+        let staged =
+            std::mem::take(&mut *write_path_lock(self.staged.lock()).expect("mutex poisoned"));
+        if staged.is_empty() {
+            return;
+        }
+        let now = ring.clock.now();
+        ring.write_rate.record(now, staged.len());
+        // The writers already reported success, so a strict-mode rejection is moot.
+        let evicted = self.push_checked(&mut ring, &staged).unwrap_or(false);
+        let reached = self.check_bytes_threshold(&ring);
+        let level_reached = self.check_level_threshold(&ring);
+        drop(ring);
+        if evicted {
+            self.notify_first_wrap();
+        }
+        if let Some(callback) = level_reached {
+            callback();
+        }
+        self.run_bytes_threshold(reached);
+    }
+
+    /// A buffer for a conversation's first write, configured by the builders set so
+    /// far; see [`CodexFeedback::make_writer_for`].
+    fn new_conversation_ring(&self) -> RingBuffer {
+        let max_lines =
+            *write_path_lock(self.conversation_line_capacity.lock()).expect("mutex poisoned");
+        let store = write_path_lock(self.buffer_store.lock())
+            .expect("mutex poisoned")
+            .as_ref()
+            .map(|make| make());
+        let clock = write_path_lock(self.clock.lock())
+            .expect("mutex poisoned")
+            .clone();
+        let mut ring = RingBuffer::new(self.conversation_capacity)
+            .with_store(store)
+            .with_max_lines(max_lines)
+            .with_preallocate(self.preallocate.load(Ordering::Relaxed));
+        ring.set_clock(&clock);
+        if let Some(epoch) = *write_path_lock(self.line_times_epoch.lock()).expect("mutex poisoned")
+        {
+            ring.track_line_times(clock, epoch);
+        }
+        ring.set_retention(*write_path_lock(self.retention.lock()).expect("mutex poisoned"));
+        ring
+    }
+
+    /// Read access to the shared ring, with any staged bytes merged in first.
+    fn ring_read(&self) -> RwLockReadGuard<'_, RingBuffer> {
+        self.merge_staged();
+        self.ring.read().expect("rwlock poisoned")
+    }
+
+    /// Write access to the shared ring, with any staged bytes merged in first.
+    fn ring_write(&self) -> RwLockWriteGuard<'_, RingBuffer> {
+        self.merge_staged();
+        self.version.fetch_add(1, Ordering::Relaxed);
+        self.ring.write().expect("rwlock poisoned")
+    }
+
+    /// Runs the [`CodexFeedback::on_first_wrap`] callback, if it is armed.
+    /// Must be called without any buffer lock held, since the callback may log.
+    fn notify_first_wrap(&self) {
+        if !self.write_features.has(WriteFeatures::FIRST_WRAP) {
+            return;
+        }
+        let callback = write_path_lock(self.first_wrap.lock())
+            .expect("mutex poisoned")
+            .as_mut()
+            .and_then(Rearmable::fire);
+        if let Some(callback) = callback {
+            callback();
+        }
+    }
+}
+
+/// Drops the least recently written buffers until at most `max` remain.
+fn evict_least_recently_written(
+    conversations: &mut BTreeMap<ConversationId, RingBuffer>,
+    max: usize,
+) {
+    while conversations.len() > max {
+        let Some(oldest) = conversations
+            .iter()
+            .min_by_key(|(_, ring)| ring.last_write)
+            .map(|(id, _)| *id)
+        else {
+            break;
+        };
+        conversations.remove(&oldest);
+    }
+}
+
+type SavedPathSlot = Arc<Mutex<Option<PathBuf>>>;
+
+type StoreFactory = Arc<dyn Fn() -> Box<dyn BufferStore> + Send + Sync>;
+
+/// Set by [`CodexFeedback::with_thread_id_mapper`].
+type ThreadIdMapper = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Set by [`CodexFeedback::with_write_filter`].
+type WriteFilter = Arc<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+/// Returns at most the last `max` bytes of `bytes`, skipping any leading UTF-8
+/// continuation bytes so the result does not start in the middle of a character.
+fn utf8_tail(bytes: &[u8], max: usize) -> &[u8] {
+    let mut start = bytes.len().saturating_sub(max);
+    while start < bytes.len() && (bytes[start] & 0b1100_0000) == 0b1000_0000 {
+        start += 1;
+    }
+    &bytes[start..]
+}
+
+fn thread_id_for(session_id: Option<ConversationId>) -> String {
+    session_id
+        .map(|id| id.to_string())
+        .unwrap_or("no-active-thread-".to_string() + &ConversationId::new().to_string())
+}
+
+fn feedback_disabled_by_env() -> bool {
+    std::env::var(FEEDBACK_DISABLED_ENV_VAR)
+        .is_ok_and(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+}
+
+#[derive(Clone)]
+pub struct FeedbackMakeWriter {
+    inner: Arc<FeedbackInner>,
+    conversation: Option<ConversationId>,
+    /// Set by [`CodexFeedback::make_writer_from_span`].
+    from_span: bool,
+}
+
+impl<'a> MakeWriter<'a> for FeedbackMakeWriter {
+    type Writer = FeedbackWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        let conversation = if self.from_span {
+            layer::current_conversation()
+        } else {
+            self.conversation
+        };
+        FeedbackWriter {
+            coalesce_limit: self.inner.coalesce_limit.load(Ordering::Relaxed),
+            inner: self.inner.clone(),
+            conversation,
+            pending: Vec::new(),
+        }
+    }
+}
+
+pub struct FeedbackWriter {
+    inner: Arc<FeedbackInner>,
+    conversation: Option<ConversationId>,
+    /// See [`CodexFeedback::with_write_coalescing`]; 0 when off.
+    coalesce_limit: usize,
+    /// Bytes held back until they complete a line or reach `coalesce_limit`.
+    pending: Vec<u8>,
+}
+
+impl FeedbackWriter {
+    /// Stores everything held back by [`CodexFeedback::with_write_coalescing`].
+    fn store_pending(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let pending = std::mem::take(&mut self.pending);
+        self.store(&pending)
+    }
+
+    /// Buffers one chunk. Each optional feature's lock is only taken once the
+    /// feature is configured (see [`WriteFeatures`]), so with none configured a write
+    /// takes just the buffer's own lock.
+    fn store(&mut self, raw: &[u8]) -> io::Result<()> {
+        if !self.inner.enabled || self.inner.shut_down.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let buffered = self.buffer(raw);
+        self.inner.mirror(raw);
+        buffered
+    }
+
+    fn buffer(&mut self, raw: &[u8]) -> io::Result<()> {
+        let features = &self.inner.write_features;
+        if features.has(WriteFeatures::WRITE_FILTER) {
+            let filter = write_path_lock(self.inner.write_filter.lock())
+                .map_err(|_| io::ErrorKind::Other)?
+                .clone();
+            if filter.is_some_and(|filter| !filter(raw)) {
+                return Ok(());
+            }
+        }
+        let transcoded = if features.has(WriteFeatures::TRANSCODE) {
+            write_path_lock(self.inner.transcoder.lock())
+                .map_err(|_| io::ErrorKind::Other)?
+                .transcode(raw)
+        } else {
+            Cow::Borrowed(raw)
+        };
+        let nul_replacement = if features.has(WriteFeatures::NUL_REPLACEMENT) {
+            *write_path_lock(self.inner.nul_replacement.lock()).map_err(|_| io::ErrorKind::Other)?
+        } else {
+            None
+        };
+        let transcoded = match nul_replacement {
+            Some(replacement) if transcoded.contains(&0) => Cow::Owned(
+                transcoded
+                    .iter()
+                    .map(|&b| if b == 0 { replacement } else { b })
+                    .collect(),
+            ),
+            _ => transcoded,
+        };
+        let buf = transcoded.as_ref();
+        let mut threshold_reached = None;
+        let mut level_reached = None;
+        let evicted = match self.conversation {
+            None if self.inner.double_buffered.load(Ordering::Relaxed) => {
+                if self.inner.conversation_capacity == 0
+                    && !buf.is_empty()
+                    && self.inner.strict_writes.load(Ordering::Relaxed)
+                {
+                    return Err(io::Error::other(
+                        "feedback buffer has zero capacity; write discarded",
+                    ));
+                }
+                let mut staged =
+                    write_path_lock(self.inner.staged.lock()).map_err(|_| io::ErrorKind::Other)?;
+                staged.extend_from_slice(buf);
+                let full = staged.len() >= self.inner.conversation_capacity;
+                drop(staged);
+                if full {
+                    // Bound the staging buffer by merging it ourselves.
+                    self.inner.merge_staged();
+                }
+                false
+            }
+            None => {
+                let mut ring =
+                    write_path_lock(self.inner.ring.write()).map_err(|_| io::ErrorKind::Other)?;
+                let now = ring.clock.now();
+                ring.write_rate.record(now, buf.len());
+                let evicted = self.inner.push_checked(&mut ring, buf)?;
+                threshold_reached = self.inner.check_bytes_threshold(&ring);
+                level_reached = self.inner.check_level_threshold(&ring);
+                evicted
+            }
+            Some(id) => {
+                let mut conversations = write_path_lock(self.inner.conversations.write())
+                    .map_err(|_| io::ErrorKind::Other)?;
+                let ring = conversations
+                    .entry(id)
+                    .or_insert_with(|| self.inner.new_conversation_ring());
+                ring.last_write = self.inner.write_tick.fetch_add(1, Ordering::Relaxed);
+                if features.has(WriteFeatures::IDLE_TIMEOUT) {
+                    ring.last_write_at = Some(ring.clock.now());
+                }
+                let evicted = self.inner.push_checked(ring, buf)?;
+                let max_conversations = features
+                    .has(WriteFeatures::MAX_CONVERSATIONS)
+                    .then(|| {
+                        *write_path_lock(self.inner.max_conversations.lock())
+                            .expect("mutex poisoned")
+                    })
+                    .flatten();
+                if let Some(max) = max_conversations {
+                    evict_least_recently_written(&mut conversations, max);
+                }
+                evicted
+            }
+        };
+        if evicted {
+            self.inner.notify_first_wrap();
+        }
+        if let Some(callback) = level_reached {
+            callback();
+        }
+        self.inner.version.fetch_add(1, Ordering::Relaxed);
+        self.inner.run_bytes_threshold(threshold_reached);
+        if let Some((callback, lines)) = self.inner.complete_lines(self.conversation, buf) {
+            for line in &lines {
+                callback(line);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for FeedbackWriter {
+    fn write(&mut self, raw: &[u8]) -> io::Result<usize> {
+        if self.coalesce_limit == 0 {
+            self.store(raw)?;
+            return Ok(raw.len());
+        }
+        self.pending.extend_from_slice(raw);
+        if self.pending.len() >= self.coalesce_limit {
+            self.store_pending()?;
+        } else if let Some(end) = self.pending.iter().rposition(|&b| b == b'\n') {
+            let rest = self.pending.split_off(end + 1);
+            let complete = std::mem::replace(&mut self.pending, rest);
+            self.store(&complete)?;
+        }
+        Ok(raw.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.store_pending()?;
+        if !self.inner.write_features.has(WriteFeatures::TEE) {
+            return Ok(());
+        }
+        match write_path_lock(self.inner.tee.lock())
+            .expect("mutex poisoned")
+            .as_mut()
+        {
+            Some(tee) => tee.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for FeedbackWriter {
+    fn drop(&mut self) {
+        let _ = self.store_pending();
+    }
+}
+
+/// Read access to the ring for line iteration; see [`CodexFeedback::lines_guard`].
+pub struct LinesGuard<'a> {
+    ring: RwLockReadGuard<'a, RingBuffer>,
+}
+
+impl<'g> IntoIterator for &'g LinesGuard<'_> {
+    type Item = Cow<'g, str>;
+    type IntoIter = BufferedLines<'g>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let (head, tail) = self.ring.buf.as_slices();
+        BufferedLines { head, tail }
+    }
+}
+
+/// Iterator over the complete (newline-terminated) lines in the ring, without the
+/// trailing `\n` or `\r\n`. A trailing partial line is not yielded.
+///
+/// Lines are borrowed from the ring and only allocated when they straddle the
+/// store's wrap-around point or contain invalid UTF-8.
+pub struct BufferedLines<'a> {
+    head: &'a [u8],
+    tail: &'a [u8],
+}
+
+impl<'a> Iterator for BufferedLines<'a> {
+    type Item = Cow<'a, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.head.is_empty() {
+            self.head = std::mem::take(&mut self.tail);
+        }
+        let splitter = LineSplitter::default();
+        if let Some(pos) = self.head.iter().position(|&b| b == b'\n') {
+            let line = splitter.finish_line(&self.head[..pos]);
+            self.head = &self.head[pos + 1..];
+            return Some(String::from_utf8_lossy(line));
+        }
+
+        let pos = self.tail.iter().position(|&b| b == b'\n')?;
+        let mut joined = Vec::with_capacity(self.head.len() + pos);
+        joined.extend_from_slice(self.head);
+        joined.extend_from_slice(&self.tail[..pos]);
+        self.head = &[];
+        self.tail = &self.tail[pos + 1..];
+        Some(Cow::Owned(
+            String::from_utf8_lossy(splitter.finish_line(&joined)).into_owned(),
+        ))
+    }
+}
+
+struct RingBuffer {
+    max: usize,
+    buf: Box<dyn BufferStore>,
+    /// When set, whole lines are evicted from the front once more than this many
+    /// newline-terminated lines are buffered; `max` still caps the byte size.
+    max_lines: Option<usize>,
+    /// Offset just past each `\n` currently in `buf`, oldest first, so the start of
+    /// any recent line can be found without scanning.
+    line_starts: VecDeque<u64>,
+    /// Offsets of the complete lines in `buf` logged at ERROR level, oldest first.
+    /// Only kept while `index_errors` is set.
+    error_starts: VecDeque<u64>,
+    index_errors: bool,
+    /// Bytes ever pushed, including ones since evicted.
+    total_written: u64,
+    /// Offset just past the bytes last removed from the front on purpose (by a drain
+    /// or clear rather than eviction); a buffer starting later has lost output.
+    removed_through: u64,
+    line_times: Option<LineTimes>,
+    /// CRC-32 of `buf`, kept up to date as bytes are appended and evicted.
+    crc: u32,
+    /// CRC-32 of the bytes evicted by the current [`RingBuffer::push_bytes`] call.
+    evicted_crc: u32,
+    /// The byte just before the oldest one in `buf`, once any have been removed
+    /// from the front, so a snapshot can tell whether it starts mid-line.
+    before_front: Option<u8>,
+    /// Tick of the most recent write to a conversation buffer, for LRU eviction.
+    last_write: u64,
+    /// Time of that write, recorded only with
+    /// [`CodexFeedback::with_conversation_idle_timeout`].
+    last_write_at: Option<Instant>,
+    /// Trailing partial line held back by [`CodexFeedback::with_drop_pattern`].
+    pending_line: Vec<u8>,
+    retention: Option<Retention>,
+    /// Set by [`CodexFeedback::with_in_memory_compression`].
+    history: Option<CompressedHistory>,
+    /// The injected [`Clock`], so writes can be timed under the ring's own lock.
+    clock: Arc<dyn Clock>,
+    /// Recent writes to the shared buffer, recorded by its writers.
+    write_rate: WriteRate,
+}
+
+/// Set by [`CodexFeedback::with_min_retention`].
+#[derive(Debug, Clone, Copy)]
+struct Retention {
+    window: Duration,
+    /// At least the buffer's `max`.
+    ceiling: usize,
+}
+
+/// When each buffered line started; see [`CodexFeedback::with_line_timestamps`].
+struct LineTimes {
+    clock: Arc<dyn Clock>,
+    epoch: Instant,
+    /// `(time, absolute write offset)` of each line start, oldest first.
+    markers: VecDeque<(Instant, u64)>,
+    /// Whether the next byte written begins a new line.
+    at_line_start: bool,
+}
+
+impl LineTimes {
+    /// Records a marker for every line that starts within `data`, which is about to
+    /// be written at absolute offset `base`. A trailing newline defers the next
+    /// marker until that line's first byte actually arrives.
+    fn record(&mut self, base: u64, data: &[u8]) {
+        let now = self.clock.now();
+        if self.at_line_start {
+            self.markers.push_back((now, base));
+        }
+        for (i, _) in data.iter().enumerate().filter(|&(_, &b)| b == b'\n') {
+            if i + 1 < data.len() {
+                self.markers.push_back((now, base + i as u64 + 1));
+            }
+        }
+        self.at_line_start = data.ends_with(b"\n");
+    }
+}
+
+impl RingBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            max: capacity,
+            buf: Box::new(VecDequeStore::default()),
+            max_lines: None,
+            line_starts: VecDeque::new(),
+            error_starts: VecDeque::new(),
+            index_errors: false,
+            total_written: 0,
+            removed_through: 0,
+            line_times: None,
+            crc: 0,
+            evicted_crc: 0,
+            before_front: None,
+            last_write: 0,
+            last_write_at: None,
+            pending_line: Vec::new(),
+            retention: None,
+            history: None,
+            clock: Arc::new(SystemClock),
+            write_rate: WriteRate::default(),
+        }
+    }
+
+    fn with_store(mut self, store: Option<Box<dyn BufferStore>>) -> Self {
+        if let Some(store) = store {
+            self.set_store(store);
+        }
+        self
+    }
+
+    /// Moves the buffered bytes into `store` and keeps using that from now on.
+    fn set_store(&mut self, mut store: Box<dyn BufferStore>) {
+        let (front, back) = self.buf.as_slices();
+        store.push(front);
+        store.push(back);
+        self.buf = store;
+    }
+
+    fn with_preallocate(mut self, preallocate: bool) -> Self {
+        self.set_preallocate(preallocate);
+        self
+    }
+
+    /// Allocates the full capacity now, or releases whatever is not in use.
+    fn set_preallocate(&mut self, preallocate: bool) {
+        if preallocate {
+            self.buf.reserve(self.max.saturating_sub(self.buf.len()));
+        } else {
+            self.buf.shrink_to_fit();
+        }
+    }
+
+    fn with_max_lines(mut self, max_lines: Option<usize>) -> Self {
+        self.set_max_lines(max_lines);
+        self
+    }
+
+    fn set_max(&mut self, max: usize) {
+        self.max = max;
+        let excess = self.len().saturating_sub(max);
+        if excess > 0 {
+            self.evict_front(excess);
+            self.prune_evicted();
+            self.recompute_crc();
+        }
+    }
+
+    fn set_max_lines(&mut self, max_lines: Option<usize>) {
+        self.max_lines = max_lines;
+        self.trim_lines();
+        self.prune_evicted();
+        self.recompute_crc();
+    }
+
+    fn recompute_crc(&mut self) {
+        let (front, back) = self.buf.as_slices();
+        self.crc = crc::update(crc::update(0, front), back);
+    }
+
+    fn track_line_times(&mut self, clock: Arc<dyn Clock>, epoch: Instant) {
+        self.line_times = Some(LineTimes {
+            clock,
+            epoch,
+            markers: VecDeque::new(),
+            at_line_start: self.last_byte().is_none_or(|b| b == b'\n'),
+        });
+    }
+
+    fn set_clock(&mut self, clock: &Arc<dyn Clock>) {
+        self.clock = clock.clone();
+        if let Some(times) = &mut self.line_times {
+            times.clock = clock.clone();
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Bytes allocated for this buffer, counting capacity rather than length.
+    fn memory_footprint(&self) -> usize {
+        let markers = self.line_times.as_ref().map_or(0, |times| {
+            times.markers.capacity() * std::mem::size_of::<(Instant, u64)>()
+        });
+        std::mem::size_of::<Self>()
+            + self.buf.capacity()
+            + (self.line_starts.capacity() + self.error_starts.capacity())
+                * std::mem::size_of::<u64>()
+            + self.pending_line.capacity()
+            + markers
+            + self
+                .history
+                .as_ref()
+                .map_or(0, CompressedHistory::memory_footprint)
+    }
+
+    /// Appends `data` to the held-back partial line and returns the complete lines
+    /// (terminators included) for which `drop` is false. `drop` sees each line
+    /// without its terminator. The held-back part never exceeds the capacity.
+    fn filter_complete_lines(&mut self, data: &[u8], drop: impl Fn(&[u8]) -> bool) -> Vec<u8> {
+        self.pending_line.extend_from_slice(data);
+        let Some(end) = self.pending_line.iter().rposition(|&b| b == b'\n') else {
+            let excess = self.pending_line.len().saturating_sub(self.max);
+            self.pending_line.drain(..excess);
+            return Vec::new();
+        };
+        let complete: Vec<u8> = self.pending_line.drain(..=end).collect();
+        let splitter = LineSplitter::default();
+        complete
+            .split_inclusive(|&b| b == b'\n')
+            .filter(|line| !drop(splitter.finish_line(&line[..line.len() - 1])))
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    /// Appends `data`, returning whether any bytes (old or new) had to be dropped.
+    fn push_bytes(&mut self, data: &[u8]) -> bool {
+        if data.is_empty() {
+            return false;
+        }
+        let kept_before = self.len() + data.len();
+        self.evicted_crc = 0;
+        if let Some(times) = &mut self.line_times {
+            times.record(self.total_written, data);
+        }
+        let evictable = self.evictable_len();
+
+        let cap = self.hard_capacity();
+        if data.len() >= cap {
+            // If the incoming chunk is larger than capacity, keep only the trailing bytes.
+            // Readers hold the ring's lock, so none can see it between the clear and
+            // the refill below.
+            let start = data.len() - cap;
+            if self.history.is_some() {
+                let mut evicted = self.snapshot_bytes();
+                evicted.extend_from_slice(&data[..start]);
+                self.archive(self.offsets().start, &evicted);
+            }
+            self.before_front = match start {
+                0 => self.last_byte().or(self.before_front),
+                start => Some(data[start - 1]),
+            };
+            self.buf.clear();
+            self.line_starts.clear();
+            self.error_starts.clear();
+            self.total_written += data.len() as u64;
+            self.extend(&data[start..]);
+        } else {
+            // Evict from the front if we would exceed capacity.
+            let needed = self.len() + data.len();
+            if needed > self.max {
+                let mut to_drop = needed - self.max;
+                if self.retention.is_some() {
+                    to_drop = to_drop.min(evictable).max(needed.saturating_sub(cap));
+                }
+                self.evict_front(to_drop);
+            }
+            self.total_written += data.len() as u64;
+            self.extend(data);
+        }
+        self.trim_lines();
+        self.prune_evicted();
+        if data.len() >= cap {
+            self.recompute_crc();
+        } else {
+            let appended = crc::update(self.crc, data);
+            self.crc = crc::remove_prefix(appended, self.evicted_crc, self.len());
+        }
+        self.len() < kept_before
+    }
+
+    /// Size the buffer may grow to: `max`, or the ceiling set by
+    /// [`CodexFeedback::with_min_retention`].
+    fn hard_capacity(&self) -> usize {
+        self.retention
+            .map_or(self.max, |retention| retention.ceiling)
+    }
+
+    /// How many bytes at the front are older than the retention window and may be
+    /// evicted; everything without [`CodexFeedback::with_min_retention`].
+    fn evictable_len(&self) -> usize {
+        let (Some(retention), Some(times)) = (self.retention, &self.line_times) else {
+            return self.len();
+        };
+        let Some(cutoff) = times.clock.now().checked_sub(retention.window) else {
+            return 0;
+        };
+        let protected_start = times
+            .markers
+            .iter()
+            .find(|&&(at, _)| at >= cutoff)
+            .map_or(self.total_written, |&(_, offset)| offset);
+        (protected_start.saturating_sub(self.offsets().start) as usize).min(self.len())
+    }
+
+    fn set_retention(&mut self, retention: Option<Retention>) {
+        self.retention = retention.map(|retention| Retention {
+            ceiling: retention.ceiling.max(self.max),
+            ..retention
+        });
+    }
+
+    /// Drops line-time markers and error line starts that have been evicted.
+    fn prune_evicted(&mut self) {
+        let start = self.offsets().start;
+        while self
+            .error_starts
+            .front()
+            .is_some_and(|&offset| offset < start)
+        {
+            self.error_starts.pop_front();
+        }
+        if let Some(times) = &mut self.line_times {
+            while times
+                .markers
+                .front()
+                .is_some_and(|&(_, offset)| offset < start)
+            {
+                times.markers.pop_front();
+            }
+        }
+    }
+
+    fn extend(&mut self, data: &[u8]) {
+        // Only ever called with the most recently written bytes.
+        let base = self.total_written - data.len() as u64;
+        self.buf.push(data);
+        for (i, _) in data.iter().enumerate().filter(|&(_, &b)| b == b'\n') {
+            if self.index_errors {
+                let start = self.last_line_start();
+                if self.is_error_line_at(start) {
+                    self.error_starts.push_back(start);
+                }
+            }
+            self.line_starts.push_back(base + i as u64 + 1);
+        }
+    }
+
+    /// Turns the ERROR line index on or off, rebuilding it from the complete lines
+    /// already buffered.
+    fn set_error_index(&mut self, enabled: bool) {
+        self.index_errors = enabled;
+        self.error_starts.clear();
+        if !enabled {
+            return;
+        }
+        let complete = self.line_starts.len();
+        let starts: Vec<u64> = std::iter::once(self.offsets().start)
+            .chain(self.line_starts.iter().copied())
+            .take(complete)
+            .filter(|&start| self.is_error_line_at(start))
+            .collect();
+        self.error_starts.extend(starts);
+    }
+
+    /// Offset of the start of the last (possibly incomplete) line.
+    fn last_line_start(&self) -> u64 {
+        self.line_starts
+            .back()
+            .copied()
+            .unwrap_or(self.offsets().start)
+    }
+
+    /// Whether the line starting at `start` is logged at ERROR level, judging by its
+    /// first [`LEVEL_PREFIX_LEN`] bytes.
+    fn is_error_line_at(&self, start: u64) -> bool {
+        let from = (start - self.offsets().start) as usize;
+        let (front, back) = self.buf.as_slices();
+        let mut wrapped = [0; LEVEL_PREFIX_LEN];
+        let prefix = if from >= front.len() {
+            let from = from - front.len();
+            &back[from..back.len().min(from + LEVEL_PREFIX_LEN)]
+        } else if front.len() - from >= LEVEL_PREFIX_LEN || back.is_empty() {
+            &front[from..front.len().min(from + LEVEL_PREFIX_LEN)]
+        } else {
+            // The prefix runs across the ring's wrap-around; join it on the stack.
+            let head = &front[from..];
+            let tail = &back[..back.len().min(LEVEL_PREFIX_LEN - head.len())];
+            wrapped[..head.len()].copy_from_slice(head);
+            wrapped[head.len()..head.len() + tail.len()].copy_from_slice(tail);
+            &wrapped[..head.len() + tail.len()]
+        };
+        let prefix = prefix.split(|&b| b == b'\n').next().unwrap_or_default();
+        // Cheap prefilter: most lines are not errors.
+        prefix.windows(5).any(|window| window == b"ERROR")
+            && line_level(&String::from_utf8_lossy(prefix)) == Some(Level::ERROR)
+    }
+
+    /// Positions of ERROR lines relative to the start of the buffer, including a
+    /// trailing incomplete one.
+    fn error_offsets(&self) -> Vec<usize> {
+        let start = self.offsets().start;
+        let mut offsets: Vec<usize> = self
+            .error_starts
+            .iter()
+            .map(|&offset| (offset - start) as usize)
+            .collect();
+        let last = self.last_line_start();
+        if self.index_errors
+            && self.last_byte().is_some_and(|b| b != b'\n')
+            && self.is_error_line_at(last)
+        {
+            offsets.push((last - start) as usize);
+        }
+        offsets
+    }
+
+    /// Evicts the oldest `n` bytes, handing them to the compressed history if any.
+    fn evict_front(&mut self, n: usize) {
+        let n = n.min(self.len());
+        let start = self.offsets().start;
+        let (front, back) = self.buf.as_slices();
+        let front = &front[..n.min(front.len())];
+        let back = &back[..n - front.len()];
+        let newlines = front.iter().chain(back).filter(|&&b| b == b'\n').count();
+        if n > 0 {
+            self.before_front = front.iter().chain(back).last().copied();
+        }
+        self.evicted_crc = crc::update(crc::update(self.evicted_crc, front), back);
+        if let Some(history) = &mut self.history {
+            history.archive(start, front);
+            history.archive(start + front.len() as u64, back);
+        }
+        self.line_starts.drain(..newlines);
+        self.buf.evict(n);
+    }
+
+    /// The buffered bytes, oldest first.
+    fn iter(&self) -> impl Iterator<Item = &u8> {
+        let (front, back) = self.buf.as_slices();
+        front.iter().chain(back)
+    }
+
+    fn last_byte(&self) -> Option<u8> {
+        let (front, back) = self.buf.as_slices();
+        back.last().or(front.last()).copied()
+    }
+
+    /// Copies the bytes at positions `range` relative to the start of the buffer.
+    fn bytes_in(&self, range: Range<usize>) -> Vec<u8> {
+        self.iter()
+            .skip(range.start)
+            .take(range.len())
+            .copied()
+            .collect()
+    }
+
+    /// Drops the oldest lines until at most `max_lines` remain.
+    fn trim_lines(&mut self) {
+        let Some(max_lines) = self.max_lines else {
+            return;
+        };
+        let excess = self.line_starts.len().saturating_sub(max_lines);
+        if excess > 0 {
+            let end = self.line_starts[excess - 1];
+            self.evict_front((end - self.offsets().start) as usize);
+        }
+    }
+
+    /// Hands bytes evicted from offset `start` on to the compressed history, if any.
+    fn archive(&mut self, start: u64, evicted: &[u8]) {
+        if let Some(history) = &mut self.history {
+            history.archive(start, evicted);
+        }
+    }
+
+    /// The buffered bytes preceded by the compressed history when it reaches up to
+    /// them, with their offsets and whether output before them was evicted.
+    fn snapshot_window(&self) -> (Vec<u8>, Range<u64>, bool) {
+        let offsets = self.offsets();
+        let history = self
+            .history
+            .as_ref()
+            .filter(|history| history.offsets().end == offsets.start)
+            .and_then(|history| Some((history.offsets().start, history.bytes().ok()?)));
+        let (bytes, offsets) = match history {
+            Some((start, mut bytes)) => {
+                bytes.extend(self.iter());
+                (bytes, start..offsets.end)
+            }
+            None => (self.snapshot_bytes(), offsets),
+        };
+        let truncated = offsets.start > self.removed_through;
+        (bytes, offsets, truncated)
+    }
+
+    /// Whether bytes were evicted from the front to make room since the buffer was
+    /// last drained or cleared.
+    fn truncated(&self) -> bool {
+        self.offsets().start > self.removed_through
+    }
+
+    fn snapshot_bytes(&self) -> Vec<u8> {
+        self.buf.snapshot()
+    }
+
+    /// Swaps out the buffered bytes, returning them with their offsets. The write
+    /// counter keeps running, so later snapshots start where this one ended.
+    fn drain(&mut self) -> (Vec<u8>, Range<u64>) {
+        let offsets = self.offsets();
+        self.before_front = self.last_byte().or(self.before_front);
+        self.removed_through = offsets.end;
+        self.line_starts.clear();
+        self.error_starts.clear();
+        self.crc = 0;
+        if let Some(times) = &mut self.line_times {
+            times.markers.clear();
+        }
+        (self.buf.take(), offsets)
+    }
+
+    /// Removes and returns everything up to and including the last newline.
+    fn drain_complete_lines(&mut self) -> Vec<u8> {
+        let (front, back) = self.buf.as_slices();
+        let Some(end) = back
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map(|i| front.len() + i)
+            .or_else(|| front.iter().rposition(|&b| b == b'\n'))
+        else {
+            return Vec::new();
+        };
+        self.drain_front(end + 1).0
+    }
+
+    /// Removes and returns the oldest `len` bytes with their offsets.
+    fn drain_front(&mut self, len: usize) -> (Vec<u8>, Range<u64>) {
+        let start = self.offsets().start;
+        let front = self.bytes_in(0..len);
+        self.before_front = front.last().copied().or(self.before_front);
+        self.buf.evict(len);
+        let end = start + len as u64;
+        self.removed_through = end;
+        while self.line_starts.front().is_some_and(|&line| line <= end) {
+            self.line_starts.pop_front();
+        }
+        self.prune_evicted();
+        self.recompute_crc();
+        (front, start..end)
+    }
+
+    /// Rebuilds the buffer from the complete lines `keep` accepts plus any trailing
+    /// partial line. Kept lines move up to end at the current write offset, as if
+    /// the removed bytes had been evicted from the front, and keep their line
+    /// times.
+    fn retain_lines(&mut self, keep: impl Fn(&[u8]) -> bool) -> usize {
+        let bytes = self.snapshot_bytes();
+        let complete = bytes.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        let mut kept = Vec::with_capacity(bytes.len());
+        // Old absolute start of each kept line -> its position in `kept`.
+        let mut moved = BTreeMap::new();
+        let mut removed = 0;
+        let mut offset = self.offsets().start;
+        for line in bytes[..complete].split_inclusive(|&b| b == b'\n') {
+            if keep(&line[..line.len() - 1]) {
+                moved.insert(offset, kept.len());
+                kept.extend_from_slice(line);
+            } else {
+                removed += 1;
+            }
+            offset += line.len() as u64;
+        }
+        if removed == 0 {
+            return 0;
+        }
+        if !moved.contains_key(&self.offsets().start) {
+            // The first line was removed, so the buffer now starts on a line.
+            self.before_front = Some(b'\n');
+        }
+        moved.insert(offset, kept.len());
+        kept.extend_from_slice(&bytes[complete..]);
+
+        let truncated = self.truncated();
+        self.buf.clear();
+        self.line_starts.clear();
+        self.error_starts.clear();
+        self.extend(&kept);
+        let start = self.offsets().start;
+        if !truncated {
+            self.removed_through = start;
+        }
+        if let Some(times) = &mut self.line_times {
+            times.markers = times
+                .markers
+                .iter()
+                .filter_map(|&(time, offset)| {
+                    moved.get(&offset).map(|&pos| (time, start + pos as u64))
+                })
+                .collect();
+        }
+        self.recompute_crc();
+        removed
+    }
+
+    fn count_matching_lines(&self, pattern: &Regex) -> usize {
+        let is_match = |line: &[u8]| match std::str::from_utf8(line) {
+            Ok(line) => pattern.is_match(line),
+            Err(_) => pattern.is_match(&String::from_utf8_lossy(line)),
+        };
+        let (front, back) = self.buf.as_slices();
+        let mut front_lines = front.split(|&b| b == b'\n');
+        let mut back_lines = back.split(|&b| b == b'\n');
+        let front_last = front_lines.next_back().unwrap_or_default();
+        let back_first = back_lines.next().unwrap_or_default();
+        let wrapped: Cow<'_, [u8]> = if front_last.is_empty() || back_first.is_empty() {
+            Cow::Borrowed(if front_last.is_empty() {
+                back_first
+            } else {
+                front_last
+            })
+        } else {
+            Cow::Owned([front_last, back_first].concat())
+        };
+
+        let mut lines = front_lines
+            .chain(std::iter::once(wrapped.as_ref()))
+            .chain(back_lines)
+            .peekable();
+        let mut count = 0;
+        while let Some(line) = lines.next() {
+            // The empty piece after a final newline is not a line.
+            let is_line = lines.peek().is_some() || !line.is_empty();
+            if is_line && is_match(line) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Number of lines, counting a trailing partial line, split the same way as
+    /// [`CodexLogSnapshot::lines`].
+    fn line_count(&self) -> usize {
+        let partial = self.last_byte().is_some_and(|b| b != b'\n');
+        self.line_starts.len() + usize::from(partial)
+    }
+
+    /// Whether the oldest buffered byte is in the middle of a line whose start was
+    /// removed.
+    fn starts_mid_line(&self) -> bool {
+        self.before_front.is_some_and(|b| b != b'\n')
+    }
+
+    /// Copies the last `n` lines using the line-start index.
+    fn tail_bytes(&self, n: usize) -> Vec<u8> {
+        if n == 0 {
+            return Vec::new();
+        }
+        let lines = self.line_count();
+        let start = match lines.checked_sub(n) {
+            Some(skip) if skip > 0 => self.line_starts[skip - 1] - self.offsets().start,
+            _ => 0,
+        };
+        self.bytes_in(start as usize..self.len())
+    }
+
+    /// Start of the last line containing `needle`, if any.
+    fn last_line_start_containing(&mut self, needle: &[u8]) -> Option<usize> {
+        if needle.is_empty() {
+            return None;
+        }
+        let bytes = self.buf.make_contiguous();
+        let at = bytes.windows(needle.len()).rposition(|w| w == needle)?;
+        Some(
+            bytes[..at]
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(0, |nl| nl + 1),
+        )
+    }
+
+    /// Like [`RingBuffer::snapshot_bytes`], but with a `[+1.234s] ` prefix before
+    /// every line that has a recorded start time.
+    fn snapshot_bytes_with_times(&self) -> Vec<u8> {
+        let Some(times) = &self.line_times else {
+            return self.snapshot_bytes();
+        };
+        let start = self.offsets().start;
+        let mut markers = times.markers.iter().peekable();
+        let mut out = Vec::with_capacity(self.len() + times.markers.len() * 12);
+        for (offset, &byte) in (start..).zip(self.iter()) {
+            if let Some((at, _)) = markers.next_if(|&&(_, marker)| marker == offset) {
+                let elapsed = at.saturating_duration_since(times.epoch).as_secs_f64();
+                out.extend_from_slice(format!("[+{elapsed:.3}s] ").as_bytes());
+            }
+            out.push(byte);
+        }
+        out
+    }
+
+    /// Absolute write offsets of the first and one-past-the-last buffered byte.
+    fn offsets(&self) -> Range<u64> {
+        self.total_written - self.len() as u64..self.total_written
+    }
+}
+
+/// Position in the shared buffer's write stream up to which
+/// [`CodexFeedback::tail_since`] has returned lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TailCursor {
+    offset: u64,
+}
+
+/// The build a snapshot came from; see [`CodexFeedback::with_build_info`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BuildInfo {
+    pub commit: String,
+    pub version: String,
+}
+
+/// Identifies a snapshot in error messages; see [`CodexLogSnapshot::summary`].
+/// Displays as `feedback snapshot #3 for thread <id> (1024 bytes, truncated)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotSummary {
+    pub thread_id: String,
+    pub bytes: usize,
+    /// Whether older bytes had been evicted from the buffer before the snapshot.
+    pub truncated: bool,
+    pub sequence: u64,
+}
+
+impl fmt::Display for SnapshotSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "feedback snapshot #{} for thread {} ({} bytes",
+            self.sequence, self.thread_id, self.bytes
+        )?;
+        if self.truncated {
+            f.write_str(", truncated")?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Size figures for a snapshot; see [`CodexLogSnapshot::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SnapshotStats {
+    pub bytes: usize,
+    /// Number of lines, counting a trailing line without a newline.
+    pub lines: usize,
+    /// Number of whitespace-delimited tokens.
+    pub words: usize,
+    pub chars: usize,
+}
+
+impl fmt::Display for SnapshotStats {
+    /// E.g. `3.7 MiB, 41210 lines, 502113 words, 3881470 chars`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, {} lines, {} words, {} chars",
+            format_bytes(self.bytes),
+            self.lines,
+            self.words,
+            self.chars
+        )
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CodexLogSnapshot {
+    #[cfg_attr(feature = "serde", serde(with = "serde_base64"))]
+    bytes: Vec<u8>,
+    pub thread_id: String,
+    /// Position of the first captured byte in the stream of everything ever written
+    /// to the buffer; bytes before it were evicted.
+    pub start_offset: u64,
+    /// Total bytes written to the buffer when the snapshot was taken. Offsets count
+    /// raw written bytes, so they are unaffected by redaction.
+    pub end_offset: u64,
+    /// Whether the buffer had evicted older output to make room since it was last
+    /// drained or cleared. Unlike a nonzero `start_offset`, removals by
+    /// [`CodexFeedback::drain_snapshot`] and the like do not count.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub truncated: bool,
+    /// Increases with every snapshot taken from the same [`CodexFeedback`], starting
+    /// at 1, so a saved file can be matched to a submission; 0 for snapshots built
+    /// by hand.
+    pub sequence: u64,
+    /// Why the snapshot is being submitted; see [`CodexLogSnapshot::with_classification`].
+    pub classification: Option<String>,
+    pub note: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+    /// Structured context; see [`CodexLogSnapshot::with_context_json`]. Formats that
+    /// are not self-describing, such as bincode, can only read it back when unset.
+    pub context: Option<serde_json::Value>,
+    /// See [`CodexFeedback::with_snapshot_schema_version`].
+    #[cfg_attr(feature = "serde", serde(default = "default_snapshot_schema_version"))]
+    pub schema_version: u32,
+    /// See [`CodexFeedback::with_build_info`].
+    pub build_info: Option<BuildInfo>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    saved_path: Option<SavedPathSlot>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    thread_id_mapper: Option<ThreadIdMapper>,
+}
+
+#[cfg(feature = "serde")]
+fn default_snapshot_schema_version() -> u32 {
+    SNAPSHOT_SCHEMA_VERSION
+}
+
+impl CodexLogSnapshot {
+    pub(crate) fn new(bytes: Vec<u8>, thread_id: String) -> Self {
+        Self {
+            end_offset: bytes.len() as u64,
+            bytes,
+            thread_id,
+            start_offset: 0,
+            truncated: false,
+            sequence: 0,
+            classification: None,
+            note: None,
+            metadata: BTreeMap::new(),
+            context: None,
+            schema_version: SNAPSHOT_SCHEMA_VERSION,
+            build_info: None,
+            saved_path: None,
+            thread_id_mapper: None,
+        }
+    }
+
+    /// Combines `self` (e.g. a parent process's log) and `other` (e.g. a subprocess's)
+    /// into one snapshot: `self`'s bytes, then `separator`, then `other`'s bytes.
+    ///
+    /// Metadata comes from `self` only: the merged snapshot keeps its `thread_id`,
+    /// offsets, and saved-path tracking, while `other`'s are discarded.
+    pub fn merge(mut self, other: CodexLogSnapshot, separator: &str) -> CodexLogSnapshot {
+        self.bytes.extend_from_slice(separator.as_bytes());
+        self.bytes.extend_from_slice(other.as_bytes());
+        self
+    }
+
+    /// Splits the snapshot at every line that is exactly `delimiter` (ignoring its
+    /// `\n` or `\r\n`), dropping those lines and any sections left empty. Each
+    /// part keeps this snapshot's metadata, with `-{index}` (from 0) appended to
+    /// its `thread_id` and offsets narrowed to its bytes; parts do not track the
+    /// saved path.
+    pub fn split_on(&self, delimiter: &str) -> Vec<CodexLogSnapshot> {
+        let mut sections: Vec<Range<usize>> = Vec::new();
+        let mut start = 0;
+        let mut pos = 0;
+        for line in self.bytes.split_inclusive(|&b| b == b'\n') {
+            let content = line.strip_suffix(b"\n").unwrap_or(line);
+            let content = content.strip_suffix(b"\r").unwrap_or(content);
+            if content == delimiter.as_bytes() {
+                sections.push(start..pos);
+                start = pos + line.len();
+            }
+            pos += line.len();
+        }
+        sections.push(start..pos);
+        sections
+            .into_iter()
+            .filter(|range| !range.is_empty())
+            .enumerate()
+            .map(|(index, range)| CodexLogSnapshot {
+                start_offset: self.start_offset + range.start as u64,
+                end_offset: self.start_offset + range.end as u64,
+                truncated: self.truncated,
+                sequence: self.sequence,
+                classification: self.classification.clone(),
+                note: self.note.clone(),
+                metadata: self.metadata.clone(),
+                context: self.context.clone(),
+                schema_version: self.schema_version,
+                build_info: self.build_info.clone(),
+                thread_id_mapper: self.thread_id_mapper.clone(),
+                ..CodexLogSnapshot::new(
+                    self.bytes[range].to_vec(),
+                    format!("{}-{index}", self.thread_id),
+                )
+            })
+            .collect()
+    }
+
+    /// Describes the snapshot as JSON: `schema_version`, `thread_id`,
+    /// `start_offset`, `end_offset`, `sequence`, and the log itself as (lossily
+    /// decoded) `text`, plus `context` when [`CodexLogSnapshot::with_context_json`]
+    /// was used and `build` (`commit` and `version`) when
+    /// [`CodexFeedback::with_build_info`] was.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "schema_version": self.schema_version,
+            "thread_id": self.thread_id,
+            "start_offset": self.start_offset,
+            "end_offset": self.end_offset,
+            "sequence": self.sequence,
+            "text": String::from_utf8_lossy(self.as_bytes()),
+        });
+        if let Some(context) = &self.context {
+            json["context"] = context.clone();
+        }
+        if let Some(build) = &self.build_info {
+            json["build"] = serde_json::json!({
+                "commit": build.commit,
+                "version": build.version,
+            });
+        }
+        json
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the snapshot text with volatile content removed, for comparing against
+    /// golden files: RFC 3339 timestamps at the start of a line are stripped and
+    /// UUID-like tokens are replaced with `<id>`.
+    pub fn normalized(&self) -> String {
+        let text = String::from_utf8_lossy(self.as_bytes());
+        let text = timestamp_prefix_regex().replace_all(&text, "");
+        uuid_regex().replace_all(&text, "<id>").into_owned()
+    }
+
+    /// Returns the buffered text with ANSI escape sequences (colors, cursor movement)
+    /// stripped, suitable for writing to a file. Invalid UTF-8 is decoded lossily.
+    pub fn plain(&self) -> String {
+        ansi_escape_regex()
+            .replace_all(&String::from_utf8_lossy(self.as_bytes()), "")
+            .into_owned()
+    }
+
+    /// Returns the buffered text as written, including any ANSI color codes, for live
+    /// display in a terminal. Invalid UTF-8 is decoded lossily.
+    pub fn colored(&self) -> String {
+        String::from_utf8_lossy(self.as_bytes()).into_owned()
+    }
+
+    /// A short description of the snapshot for error context, e.g.
+    /// `.with_context(|| snapshot.summary())` when saving or uploading it fails.
+    pub fn summary(&self) -> SnapshotSummary {
+        SnapshotSummary {
+            thread_id: self.thread_id.clone(),
+            bytes: self.as_bytes().len(),
+            truncated: self.truncated,
+            sequence: self.sequence,
+        }
+    }
+
+    /// A single line describing the snapshot for operators scanning logs, e.g.
+    /// `feedback thread=abc bytes=1024 lines=12 truncated=false seq=3`. `lines`
+    /// counts a trailing line without a newline, like [`CodexLogSnapshot::stats`].
+    pub fn one_line_summary(&self) -> String {
+        format!(
+            "feedback thread={} bytes={} lines={} truncated={} seq={}",
+            self.thread_id,
+            self.as_bytes().len(),
+            self.stats().lines,
+            self.truncated,
+            self.sequence
+        )
+    }
+
+    /// The level most lines were logged at, judged from the level the fmt layer
+    /// writes at the start of each line, for a quick health signal. Ties go to the
+    /// more severe level. `None` if no line shows a level.
+    pub fn dominant_level(&self) -> Option<Level> {
+        let mut counts: BTreeMap<Level, usize> = BTreeMap::new();
+        for line in LineSplitter::default().split(&String::from_utf8_lossy(self.as_bytes())) {
+            if let Some(level) = line_level(line) {
+                *counts.entry(level).or_default() += 1;
+            }
+        }
+        // ERROR sorts first and `max_by_key` keeps the last maximum, so iterate in
+        // reverse for ties to favor severity.
+        counts
+            .into_iter()
+            .rev()
+            .max_by_key(|&(_, count)| count)
+            .map(|(level, _)| level)
+    }
+
+    /// Renders each non-empty line as an RFC 5424 syslog message under `facility`
+    /// (0-23; larger values are clamped) and `app_name`. Severity comes from the
+    /// level the fmt layer wrote on the line (lines without one are
+    /// informational), and the timestamp from its RFC 3339 prefix, or `-` if it has
+    /// none. Hostname, message id, and structured data are left as `-`; the
+    /// process id is this process's.
+    pub fn to_syslog(&self, facility: u8, app_name: &str) -> String {
+        let facility = u16::from(facility.min(23));
+        let app_name: String = app_name
+            .chars()
+            .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+            .take(48)
+            .collect();
+        let app_name = if app_name.is_empty() { "-" } else { &app_name };
+        let pid = std::process::id();
+        let mut out = String::new();
+        for line in self.plain().lines().filter(|line| !line.trim().is_empty()) {
+            let severity = match line_level(line) {
+                Some(Level::ERROR) => 3,
+                Some(Level::WARN) => 4,
+                Some(Level::INFO) | None => 6,
+                Some(_) => 7,
+            };
+            let (timestamp, message) = match timestamp_prefix_regex().find(line) {
+                Some(found) => (found.as_str().trim_end(), &line[found.end()..]),
+                None => ("-", line),
+            };
+            out.push_str(&format!(
+                "<{}>1 {timestamp} - {app_name} {pid} - - {message}\n",
+                facility * 8 + severity
+            ));
+        }
+        out
+    }
+
+    /// Computes byte, line, word, and character counts in one pass. Invalid UTF-8 is
+    /// decoded lossily for the character and word counts.
+    pub fn stats(&self) -> SnapshotStats {
+        let text = String::from_utf8_lossy(self.as_bytes());
+        let mut stats = SnapshotStats {
+            bytes: self.as_bytes().len(),
+            ..SnapshotStats::default()
+        };
+        let mut in_word = false;
+        let mut last = None;
+        for c in text.chars() {
+            stats.chars += 1;
+            if c == '\n' {
+                stats.lines += 1;
+            }
+            if c.is_whitespace() {
+                in_word = false;
+            } else if !in_word {
+                in_word = true;
+                stats.words += 1;
+            }
+            last = Some(c);
+        }
+        if last.is_some_and(|c| c != '\n') {
+            stats.lines += 1;
+        }
+        stats
+    }
+
+    /// Returns the snapshot text with blank (empty or whitespace-only) lines removed,
+    /// so a run of newlines collapses into the single newline ending the previous line.
+    /// The buffer itself is not modified.
+    pub fn without_blank_lines(&self) -> String {
+        LineSplitter::new(TrailingNewline::Keep)
+            .split(&String::from_utf8_lossy(self.as_bytes()))
+            .filter(|line| !line.trim().is_empty())
+            .collect()
+    }
+
+    /// Returns every line, without `\n` or `\r\n` terminators. A final line without
+    /// a terminator is included.
+    pub fn lines(&self) -> Vec<String> {
+        LineSplitter::default()
+            .split(&String::from_utf8_lossy(self.as_bytes()))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Like [`CodexLogSnapshot::lines`], but borrowed and paired with the position
+    /// of each line's first byte in the snapshot, e.g. to map rows of a log view
+    /// back to offsets. A line that is not valid UTF-8 is cut where the invalid
+    /// bytes begin.
+    pub fn enumerate_lines(&self) -> impl Iterator<Item = (usize, &str)> {
+        LineSplitter::default()
+            .split_bytes(&self.bytes)
+            .map(|(offset, line)| {
+                let text = std::str::from_utf8(line).unwrap_or_else(|err| {
+                    // The prefix up to `valid_up_to` is valid UTF-8 by definition.
+                    std::str::from_utf8(&line[..err.valid_up_to()]).unwrap_or_default()
+                });
+                (offset, text)
+            })
+    }
+
+    /// Returns the last `n` lines, split the same way as [`CodexLogSnapshot::lines`].
+    pub fn tail(&self, n: usize) -> Vec<String> {
+        let mut lines = self.lines();
+        lines.drain(..lines.len().saturating_sub(n));
+        lines
+    }
+
+    /// Returns the text made safe to print to a terminal: like
+    /// [`CodexLogSnapshot::plain`], ANSI CSI sequences (colors, cursor movement)
+    /// are removed, and every other control character that could drive the
+    /// terminal (escape, bell, backspace, C1 controls, a `\r` not ending a line)
+    /// or reorder the displayed text (bidirectional overrides) is shown escaped,
+    /// e.g. `\u{1b}`. `\n`, `\r\n` and `\t` are kept.
+    pub fn terminal_safe(&self) -> String {
+        let plain = self.plain();
+        let mut out = String::with_capacity(plain.len());
+        let mut chars = plain.chars().peekable();
+        while let Some(c) = chars.next() {
+            let keep = match c {
+                '\n' | '\t' => true,
+                '\r' => chars.peek() == Some(&'\n'),
+                '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' => false,
+                c => !c.is_control(),
+            };
+            if keep {
+                out.push(c);
+            } else {
+                out.extend(c.escape_default());
+            }
+        }
+        out
+    }
+
+    /// Returns the lines added since `baseline`, an earlier snapshot of the same
+    /// buffer, assuming it only grew at the end. The longest run of `baseline`'s last
+    /// lines that `self` starts with is taken as the overlap, allowing for the first
+    /// of them to have been cut by eviction and the last to have been completed
+    /// since (it is then reported as added). If none of `baseline` is left, all of
+    /// `self` is returned after a `[baseline not found; showing all lines]` line.
+    pub fn added_lines_since(&self, baseline: &CodexLogSnapshot) -> Vec<String> {
+        let old = baseline.lines();
+        let new = self.lines();
+        if old.is_empty() {
+            return new;
+        }
+        let partial = !baseline.as_bytes().ends_with(b"\n");
+        for overlap in (1..=old.len().min(new.len())).rev() {
+            let tail = &old[old.len() - overlap..];
+            let head = &new[..overlap];
+            let last = overlap - 1;
+            let fits = |i: usize, old: &str, new: &str| {
+                new == old
+                    || (i == 0 && !new.is_empty() && old.ends_with(new))
+                    || (partial && i == last && new.starts_with(old))
+            };
+            if tail
+                .iter()
+                .zip(head)
+                .enumerate()
+                .all(|(i, (old, new))| fits(i, old, new))
+            {
+                let skip = if head[last] == tail[last] {
+                    overlap
+                } else {
+                    last
+                };
+                return new[skip..].to_vec();
+            }
+        }
+        let mut lines = vec![BASELINE_NOT_FOUND_NOTE.to_string()];
+        lines.extend(new);
+        lines
+    }
+
+    /// Returns the marker lines left where content was elided, with their line
+    /// index (as in [`CodexLogSnapshot::lines`]), e.g. to highlight them in a UI.
+    /// Every such marker is a whole line in brackets:
+    ///
+    /// - `[rate limited: dropped N bytes]` from [`CodexFeedback::with_write_rate_limit`]
+    /// - `[... N bytes truncated ...]` from [`CodexFeedback::ingest`]
+    /// - `[truncated to fit]` from [`CodexLogSnapshot::save_to_temp_file_limited`]
+    /// - `[redaction limit reached]` from [`CodexFeedback::with_redaction_limit`]
+    pub fn markers(&self) -> Vec<(usize, String)> {
+        self.lines()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, line)| elision_marker_regex().is_match(line))
+            .collect()
+    }
+
+    /// Returns the snapshot text with every line longer than `width` display columns
+    /// (as measured by `unicode-width`, so wide CJK characters count twice) broken
+    /// onto further lines, for pasting into width-limited contexts. Lines never
+    /// break inside a character; shorter lines and line terminators are unchanged.
+    pub fn wrapped(&self, width: usize) -> String {
+        let width = width.max(1);
+        let text = String::from_utf8_lossy(self.as_bytes());
+        let mut out = String::with_capacity(text.len());
+        for line in LineSplitter::new(TrailingNewline::Keep).split(&text) {
+            let (content, terminator) = lines::split_terminator(line);
+            out.push_str(&wrap_to_width(content, width).join("\n"));
+            out.push_str(terminator);
+        }
+        out
+    }
+
+    /// Returns the lines containing `needle`, split the same way as
+    /// [`CodexLogSnapshot::lines`].
+    pub fn grep(&self, needle: &str) -> Vec<String> {
+        LineSplitter::default()
+            .split(&String::from_utf8_lossy(self.as_bytes()))
+            .filter(|line| line.contains(needle))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Byte offset of the start of the first line matching the regex `pattern`, e.g.
+    /// to jump to the first error. Lines are matched without their terminator. A
+    /// `pattern` that is not a valid regex is searched for as literal text instead.
+    pub fn find(&self, pattern: &str) -> Option<usize> {
+        let is_match: Box<dyn Fn(&str) -> bool> = match Regex::new(pattern) {
+            Ok(re) => Box::new(move |line| re.is_match(line)),
+            Err(_) => Box::new(|line| line.contains(pattern)),
+        };
+        LineSplitter::default()
+            .split_bytes(self.as_bytes())
+            .find(|(_, line)| is_match(&String::from_utf8_lossy(line)))
+            .map(|(offset, _)| offset)
+    }
+
+    /// Writes the snapshot to stdout, e.g. for piping into `grep` or `less`. A reader
+    /// that goes away early (`EPIPE`) is not an error.
+    pub fn write_to_stdout(&self) -> io::Result<()> {
+        self.write_to(io::stdout().lock())
+    }
+
+    /// Like [`CodexLogSnapshot::write_to_stdout`], but writes to `out`.
+    pub fn write_to(&self, mut out: impl Write) -> io::Result<()> {
+        match out.write_all(self.as_bytes()).and_then(|()| out.flush()) {
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Renders the snapshot as a base64-encoded `text/plain` MIME attachment
+    /// (headers, blank line, body wrapped at 76 columns, CRLF line endings) for
+    /// callers that email or attach logs to tickets.
+    pub fn to_mime_part(&self) -> String {
+        let encoded = STANDARD.encode(self.as_bytes());
+        let mut part = format!(
+            "Content-Type: text/plain; charset=utf-8\r\n\
+             Content-Disposition: attachment; filename=\"{}\"\r\n\
+             Content-Transfer-Encoding: base64\r\n\
+             \r\n",
+            self.file_name()
+        );
+        for line in encoded.as_bytes().chunks(MIME_LINE_LEN) {
+            // Base64 output is ASCII, so every chunk is valid UTF-8.
+            part.push_str(std::str::from_utf8(line).unwrap_or_default());
+            part.push_str("\r\n");
+        }
+        part
+    }
+
+    /// Saves into the system temp dir, creating it if it is missing; see
+    /// [`CodexLogSnapshot::save_to_temp_file_with_note`].
+    pub fn save_to_temp_file(&self) -> Result<PathBuf, FeedbackError> {
+        self.save_to_temp_file_with_note().map(|(path, _)| path)
+    }
+
+    /// Like [`CodexLogSnapshot::save_to_temp_file`], but also returns a note when
+    /// the temp dir could not be created (as on some minimal containers) and the
+    /// file was saved to the current working directory instead.
+    pub fn save_to_temp_file_with_note(&self) -> Result<(PathBuf, Option<String>), FeedbackError> {
+        self.save_to_dir_or_else(&std::env::temp_dir(), std::env::current_dir)
+    }
+
+    /// Saves into `dir`, creating it first; if that fails, saves into the
+    /// directory returned by `fallback` and explains why in the returned note.
+    fn save_to_dir_or_else(
+        &self,
+        dir: &Path,
+        fallback: impl FnOnce() -> io::Result<PathBuf>,
+    ) -> Result<(PathBuf, Option<String>), FeedbackError> {
+        match fs::create_dir_all(dir) {
+            Ok(()) => Ok((self.save_to_dir(dir)?, None)),
+            Err(err) => {
+                let fallback = fallback()?;
+                let note = format!(
+                    "temp dir {} is unavailable ({err}); saved to {} instead",
+                    dir.display(),
+                    fallback.display()
+                );
+                Ok((self.save_to_dir(&fallback)?, Some(note)))
+            }
+        }
+    }
+
+    pub fn save_to_dir(&self, dir: &Path) -> Result<PathBuf, FeedbackError> {
+        self.write_file(dir, self.as_bytes())
+    }
+
+    /// Like [`CodexLogSnapshot::save_to_temp_file`], but calls `File::sync_all` on the
+    /// written file and then on its directory, so both the log and its name survive a
+    /// power failure, returning any sync error. This waits for the device to persist
+    /// the data, which can take milliseconds or more, so it is best kept off hot
+    /// paths.
+    pub fn save_to_temp_file_synced(&self) -> Result<PathBuf, FeedbackError> {
+        self.save_to_dir_synced(&std::env::temp_dir())
+    }
+
+    /// Like [`CodexLogSnapshot::save_to_temp_file_synced`], but writes into `dir`.
+    pub fn save_to_dir_synced(&self, dir: &Path) -> Result<PathBuf, FeedbackError> {
+        let path = dir.join(self.file_name());
+        let contents = self.as_bytes();
+        let write = || -> io::Result<()> {
+            let mut file = fs::File::create(&path)?;
+            file.write_all(contents)?;
+            file.sync_all()
+        };
+        write().map_err(|err| FeedbackError::from_write(err, path.clone(), contents.len()))?;
+        sync_dir(dir)?;
+        self.remember_saved_path(&path);
+        Ok(path)
+    }
+
+    /// Like [`CodexLogSnapshot::save_to_temp_file`], but returns `Ok(None)` without
+    /// touching the disk when the snapshot is empty.
+    pub fn save_to_temp_file_if_nonempty(&self) -> Result<Option<PathBuf>, FeedbackError> {
+        self.save_to_dir_if_nonempty(&std::env::temp_dir())
+    }
+
+    /// Like [`CodexLogSnapshot::save_to_temp_file_if_nonempty`], but writes into `dir`.
+    pub fn save_to_dir_if_nonempty(&self, dir: &Path) -> Result<Option<PathBuf>, FeedbackError> {
+        if self.as_bytes().is_empty() {
+            return Ok(None);
+        }
+        self.save_to_dir(dir).map(Some)
+    }
+
+    /// Like [`CodexLogSnapshot::save_to_temp_file`], but writes at most the last
+    /// `max_bytes` of the log followed by a `[truncated to fit]` line when the
+    /// snapshot is larger, so constrained disks still get a best-effort artifact.
+    pub fn save_to_temp_file_limited(
+        &self,
+        max_bytes: impl Into<ByteSize>,
+    ) -> Result<PathBuf, FeedbackError> {
+        self.save_to_dir_limited(&std::env::temp_dir(), max_bytes)
+    }
+
+    /// Like [`CodexLogSnapshot::save_to_temp_file_limited`], but writes into `dir`.
+    pub fn save_to_dir_limited(
+        &self,
+        dir: &Path,
+        max_bytes: impl Into<ByteSize>,
+    ) -> Result<PathBuf, FeedbackError> {
+        let max_bytes = max_bytes.into().as_usize();
+        let bytes = self.as_bytes();
+        if bytes.len() <= max_bytes {
+            return self.write_file(dir, bytes);
+        }
+        let mut contents = utf8_tail(bytes, max_bytes).to_vec();
+        if !contents.is_empty() && !contents.ends_with(b"\n") {
+            contents.push(b'\n');
+        }
+        contents.extend_from_slice(TRUNCATED_FOOTER.as_bytes());
+        self.write_file(dir, &contents)
+    }
+
+    /// Like [`CodexLogSnapshot::save_to_temp_file`], but prefixes the log with a
+    /// `# codex-feedback sha256=<hex> len=<n>` header line so
+    /// [`CodexLogSnapshot::verify_file`] can later detect truncation or corruption.
+    pub fn save_to_temp_file_with_checksum(&self) -> Result<PathBuf, FeedbackError> {
+        self.save_to_dir_with_checksum(&std::env::temp_dir())
+    }
+
+    /// Like [`CodexLogSnapshot::save_to_temp_file_with_checksum`], but writes into `dir`.
+    pub fn save_to_dir_with_checksum(&self, dir: &Path) -> Result<PathBuf, FeedbackError> {
+        let bytes = self.as_bytes();
+        let mut contents = format!(
+            "{CHECKSUM_HEADER_PREFIX}sha256={:x} len={}\n",
+            Sha256::digest(bytes),
+            bytes.len()
+        )
+        .into_bytes();
+        contents.extend_from_slice(bytes);
+        self.write_file(dir, &contents)
+    }
+
+    /// Checks a file written with a checksum header (see
+    /// [`CodexLogSnapshot::save_to_dir_with_checksum`]) against that header.
+    ///
+    /// Returns `Ok(false)` if the body no longer matches, and an
+    /// [`io::ErrorKind::InvalidData`] error if the file has no header and therefore
+    /// cannot be verified.
+    pub fn verify_file(path: &Path) -> io::Result<bool> {
+        let contents = fs::read(path)?;
+        let unverifiable = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has no codex-feedback checksum header", path.display()),
+            )
+        };
+        let newline = contents
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(unverifiable)?;
+        let header = std::str::from_utf8(&contents[..newline]).map_err(|_| unverifiable())?;
+        let fields = header
+            .strip_prefix(CHECKSUM_HEADER_PREFIX)
+            .ok_or_else(unverifiable)?;
+        let mut sha256 = None;
+        let mut len = None;
+        for field in fields.split_whitespace() {
+            if let Some(value) = field.strip_prefix("sha256=") {
+                sha256 = Some(value);
+            } else if let Some(value) = field.strip_prefix("len=") {
+                len = value.parse::<usize>().ok();
+            }
+        }
+        let (Some(sha256), Some(len)) = (sha256, len) else {
+            return Err(unverifiable());
+        };
+
+        let body = &contents[newline + 1..];
+        Ok(body.len() == len && format!("{:x}", Sha256::digest(body)) == sha256)
+    }
+
+    fn write_file(&self, dir: &Path, contents: &[u8]) -> Result<PathBuf, FeedbackError> {
+        self.write_file_at(dir.join(self.file_name()), contents)
+    }
+
+    /// The `codex-feedback-{thread_id}.log` name this snapshot is saved under,
+    /// after any [`CodexFeedback::with_thread_id_mapper`].
+    pub(crate) fn file_name(&self) -> String {
+        match &self.thread_id_mapper {
+            Some(mapper) => log_file_name(&mapper(&self.thread_id)),
+            None => log_file_name(&self.thread_id),
+        }
+    }
+
+    /// Writes `contents` to `path`, remembering it as the last saved path.
+    fn write_file_at(&self, path: PathBuf, contents: &[u8]) -> Result<PathBuf, FeedbackError> {
+        write_contents(&path, contents)?;
+        self.remember_saved_path(&path);
+        Ok(path)
+    }
+
+    fn remember_saved_path(&self, path: &Path) {
+        if let Some(slot) = &self.saved_path {
+            *slot.lock().expect("mutex poisoned") = Some(path.to_path_buf());
+        }
+    }
+
+    /// No-op in this fork: feedback uploads are disabled.
+    ///
+    /// This method used to stream snapshots to Sentry over HTTPS, which pulled in
+    /// `native-tls`/OpenSSL. To keep this fork's binaries OpenSSL-free and avoid
+    /// sending logs to a remote service by default, the implementation is now a no-op
+    /// while preserving the public API for callers.
+    pub fn upload_feedback(
+        &self,
+        _classification: &str,
+        _reason: Option<&str>,
+        _include_logs: bool,
+        _rollout_path: Option<&std::path::Path>,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl From<CodexLogSnapshot> for Vec<u8> {
+    fn from(snapshot: CodexLogSnapshot) -> Self {
+        snapshot.bytes
+    }
+}
+
+/// Fails if the log is not valid UTF-8; the error gives the bytes back.
+impl TryFrom<CodexLogSnapshot> for String {
+    type Error = std::string::FromUtf8Error;
+
+    fn try_from(snapshot: CodexLogSnapshot) -> Result<Self, Self::Error> {
+        String::from_utf8(snapshot.bytes)
+    }
+}
+
+/// Maximum encoded line length in a MIME body (RFC 2045).
+const MIME_LINE_LEN: usize = 76;
+
+/// Thread ids longer than this many bytes are shortened in file names.
+const MAX_FILE_NAME_THREAD_ID_LEN: usize = 64;
+
+/// `codex-feedback-{thread_id}.log`, with an overly long `thread_id` replaced by its
+/// first 32 characters plus a hash of the whole id, so the name stays well under the
+/// usual 255-byte file name limit while remaining deterministic. Every character
+/// outside `[A-Za-z0-9._-]` (path separators, NULs, anything a file system might
+/// reject) becomes `_`, as do ids of only dots, so whatever the id came from (a
+/// mapper, [`CodexLogSnapshot::from_frame`], deserialization) the name stays one
+/// component inside the target directory.
+fn log_file_name(thread_id: &str) -> String {
+    let mut sanitized: String = thread_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if sanitized.chars().all(|c| c == '.') {
+        sanitized = "_".repeat(sanitized.len());
+    }
+    if sanitized.len() <= MAX_FILE_NAME_THREAD_ID_LEN {
+        return format!("codex-feedback-{sanitized}.log");
+    }
+    let prefix: String = sanitized.chars().take(32).collect();
+    let digest = format!("{:x}", Sha256::digest(thread_id.as_bytes()));
+    format!("codex-feedback-{prefix}-{}.log", &digest[..16])
+}
+
+fn write_contents(path: &Path, contents: &[u8]) -> Result<(), FeedbackError> {
+    fs::write(path, contents)
+        .map_err(|err| FeedbackError::from_write(err, path.to_path_buf(), contents.len()))
+}
+
+/// Splits `text` into rows of at most `width` display columns (as measured by
+/// `unicode-width`), never inside a character; a character wider than `width`
+/// gets a row of its own. Empty `text` is one empty row.
+fn wrap_to_width(text: &str, width: usize) -> Vec<&str> {
+    let mut rows = Vec::new();
+    let mut row_start = 0;
+    let mut used = 0;
+    for (i, c) in text.char_indices() {
+        let char_width = UnicodeWidthChar::width(c).unwrap_or(0);
+        if used > 0 && used + char_width > width {
+            rows.push(&text[row_start..i]);
+            row_start = i;
+            used = 0;
+        }
+        used += char_width;
+    }
+    rows.push(&text[row_start..]);
+    rows
+}
+
+/// Flushes `dir`'s entries to disk, so a file just created in it keeps its name
+/// after a power failure. Directories cannot be opened for syncing on Windows, so
+/// there only the file itself is synced.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    if cfg!(unix) {
+        fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+fn timestamp_prefix_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?m)^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d+)?(?:Z|[+-]\d{2}:\d{2})[ \t]*")
+            .expect("valid timestamp regex")
+    })
+}
+
+/// Matches a whole line left by one of the features listed under
+/// [`CodexLogSnapshot::markers`].
+fn elision_marker_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"^\[(?:rate limited: dropped \d+ bytes|\.\.\. \d+ bytes truncated \.\.\.|truncated to fit|redaction limit reached)\]$",
+        )
+        .expect("valid elision marker regex")
+    })
+}
+
+fn uuid_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?i)\b[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\b")
+            .expect("valid uuid regex")
+    })
+}
+
+/// Level of a line as written by the fmt layer: the first or second token (after
+/// an optional timestamp), ignoring ANSI styling.
+fn line_level(line: &str) -> Option<Level> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| {
+        Regex::new(
+            r"^(?:\x1b\[[0-9;]*m)*(?:\S+[ \t]+)?(?:\x1b\[[0-9;]*m)*(TRACE|DEBUG|INFO|WARN|ERROR)\b",
+        )
+        .expect("valid regex")
+    });
+    re.captures(line)?.get(1)?.as_str().parse().ok()
+}
+
+/// Matches CSI escape sequences such as `\x1b[1;31m` (SGR colors) and `\x1b[2K`.
+fn ansi_escape_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]").expect("valid ansi escape regex"))
+}
+
+#[allow(dead_code)]
+fn display_classification(classification: &str) -> String {
+    match classification {
+        "bug" => "Bug".to_string(),
+        "bad_result" => "Bad result".to_string(),
+        "good_result" => "Good result".to_string(),
+        _ => "Other".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_buffer_drops_front_when_full() {
+        let fb = CodexFeedback::with_capacity(8);
+        {
+            let mut w = fb.make_writer().make_writer();
+            w.write_all(b"abcdefgh").unwrap();
+            w.write_all(b"ij").unwrap();
+        }
+        let snap = fb.snapshot(None);
+        // Capacity 8: after writing 10 bytes, we should keep the last 8.
+        pretty_assertions::assert_eq!(std::str::from_utf8(snap.as_bytes()).unwrap(), "cdefghij");
+    }
+
+    /// Held by tests that replace the process-wide panic hook.
+    static PANIC_HOOK_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn panic_hook_saves_buffer_to_dir() {
+        let _serial = PANIC_HOOK_LOCK
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let dir = tempfile::tempdir().unwrap();
+        let fb = CodexFeedback::with_capacity(64);
+        // Chain to a no-op rather than `original`, so `original` stays ours to restore.
+        let original = std::panic::take_hook();
+        std::panic::set_hook(Box::new(|_| {}));
+        fb.install_panic_hook(dir.path());
+        {
+            let mut w = fb.make_writer().make_writer();
+            w.write_all(b"before panic\n").unwrap();
+        }
+
+        let result = std::thread::spawn(|| panic!("boom")).join();
+        // Drops our hook, and with it `fb`'s buffer, before anything can fail.
+        drop(std::panic::take_hook());
+        std::panic::set_hook(original);
+        assert!(result.is_err());
+
+        let saved: Vec<PathBuf> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        pretty_assertions::assert_eq!(saved.len(), 1);
+        pretty_assertions::assert_eq!(fs::read(&saved[0]).unwrap(), b"before panic\n");
+    }
+
+    #[test]
+    fn concurrent_snapshots_and_writes_do_not_deadlock() {
+        const WRITERS: usize = 4;
+        const LINES_PER_WRITER: usize = 500;
+        let line_len = "writer-0 line-000\n".len();
+        let fb = CodexFeedback::with_capacity(WRITERS * LINES_PER_WRITER * line_len);
+
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|writer| {
+                let fb = fb.clone();
+                std::thread::spawn(move || {
+                    for line in 0..LINES_PER_WRITER {
+                        let mut w = fb.make_writer().make_writer();
+                        w.write_all(format!("writer-{writer} line-{line:03}\n").as_bytes())
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let fb = fb.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..200 {
+                        let snap = fb.snapshot(None);
+                        // Each write is a whole line, so a snapshot must never tear one.
+                        assert_eq!(snap.as_bytes().len() % line_len, 0);
+                    }
+                })
+            })
+            .collect();
+        for handle in writers.into_iter().chain(readers) {
+            handle.join().unwrap();
+        }
+
+        let snap = fb.snapshot(None);
+        let text = std::str::from_utf8(snap.as_bytes()).unwrap();
+        for writer in 0..WRITERS {
+            let prefix = format!("writer-{writer} ");
+            let lines: Vec<&str> = text.lines().filter(|l| l.starts_with(&prefix)).collect();
+            pretty_assertions::assert_eq!(lines.len(), LINES_PER_WRITER);
+            // Writes from a single thread keep their order.
+            let expected: Vec<String> = (0..LINES_PER_WRITER)
+                .map(|line| format!("writer-{writer} line-{line:03}"))
+                .collect();
+            pretty_assertions::assert_eq!(lines, expected);
+        }
+    }
+
+    #[test]
+    fn lines_guard_composes_with_iterator_adapters() {
+        let fb = CodexFeedback::with_capacity(24);
+        {
+            let mut w = fb.make_writer().make_writer();
+            for line in ["alpha\n", "bravo\n", "charlie\n", "delta\n", "foxtrot\n"] {
+                w.write_all(line.as_bytes()).unwrap();
+            }
+            w.write_all(b"partial").unwrap();
+        }
+
+        let guard = fb.lines_guard();
+        let all: Vec<String> = guard.into_iter().map(Cow::into_owned).collect();
+        // The oldest line was evicted mid-way; the partial tail is not a complete line.
+        pretty_assertions::assert_eq!(all, vec!["ie", "delta", "foxtrot"]);
+
+        let long: Vec<Cow<'_, str>> = guard.into_iter().filter(|l| l.len() > 2).take(1).collect();
+        pretty_assertions::assert_eq!(long, vec![Cow::Borrowed("delta")]);
+    }
+
+    #[test]
+    fn env_var_disables_feedback() {
+        // SAFETY: no other test in this crate reads or writes this variable.
+        unsafe { std::env::set_var(FEEDBACK_DISABLED_ENV_VAR, "1") };
+        let disabled = CodexFeedback::new();
+        unsafe { std::env::remove_var(FEEDBACK_DISABLED_ENV_VAR) };
+        let enabled = CodexFeedback::new();
+
+        assert!(!disabled.is_enabled());
+        assert!(enabled.is_enabled());
+        for fb in [&disabled, &enabled] {
+            let mut w = fb.make_writer().make_writer();
+            pretty_assertions::assert_eq!(w.write(b"hello").unwrap(), 5);
+        }
+        pretty_assertions::assert_eq!(disabled.snapshot(None).as_bytes(), b"");
+        pretty_assertions::assert_eq!(enabled.snapshot(None).as_bytes(), b"hello");
+    }
+
+    #[test]
+    fn normalized_ignores_timestamps_and_ids() {
+        let first = CodexLogSnapshot::new(b"2025-01-02T03:04:05.123456Z  INFO session 0199a1e2-1234-7abc-9def-0123456789ab started\n2025-01-02T03:04:06Z DEBUG done\n".to_vec(), ConversationId::new().to_string());
+        let second = CodexLogSnapshot::new(b"2025-11-12T13:14:15.9+02:00  INFO session 0199B9F0-5678-7DEF-8ABC-FEDCBA987654 started\n2025-11-12T13:14:16Z DEBUG done\n".to_vec(), ConversationId::new().to_string());
+
+        pretty_assertions::assert_eq!(
+            first.normalized(),
+            "INFO session <id> started\nDEBUG done\n"
+        );
+        pretty_assertions::assert_eq!(first.normalized(), second.normalized());
+    }
+
+    #[test]
+    fn ingest_appends_delimited_block() {
+        let fb = CodexFeedback::with_capacity(256);
+        {
+            let mut w = fb.make_writer().make_writer();
+            w.write_all(b"running tool\n").unwrap();
+        }
+        fb.ingest("rg stderr", b"error: unrecognized flag\nusage: rg");
+
+        let snap = fb.snapshot(None);
+        pretty_assertions::assert_eq!(
+            std::str::from_utf8(snap.as_bytes()).unwrap(),
+            "running tool\n--- begin rg stderr ---\nerror: unrecognized flag\nusage: rg\n--- end rg stderr ---\n"
+        );
+    }
+
+    #[test]
+    fn from_config_uses_parsed_capacity() {
+        let fb = CodexFeedback::from_config(&FeedbackConfig {
+            capacity: "4".to_string(),
+        })
+        .unwrap();
+        {
+            let mut w = fb.make_writer().make_writer();
+            w.write_all(b"abcdef").unwrap();
+        }
+        pretty_assertions::assert_eq!(fb.snapshot(None).as_bytes(), b"cdef");
+
+        let invalid = CodexFeedback::from_config(&FeedbackConfig {
+            capacity: "lots".to_string(),
+        });
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn snapshot_many_shares_one_point_in_time() {
+        use std::sync::atomic::AtomicBool;
+        use std::sync::atomic::Ordering;
+
+        let fb = CodexFeedback::with_capacity(4096);
+        let stop = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let fb = fb.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut n = 0;
+                while !stop.load(Ordering::Relaxed) {
+                    let mut w = fb.make_writer().make_writer();
+                    w.write_all(format!("line {n}\n").as_bytes()).unwrap();
+                    n += 1;
+                }
+            })
+        };
+
+        let first = ConversationId::new();
+        let second = ConversationId::new();
+        for _ in 0..100 {
+            let snaps = fb.snapshot_many(&[Some(first), Some(second), None]);
+            pretty_assertions::assert_eq!(snaps[0].thread_id, first.to_string());
+            pretty_assertions::assert_eq!(snaps[1].thread_id, second.to_string());
+            assert!(snaps[2].thread_id.starts_with("no-active-thread-"));
+            assert!(
+                snaps
+                    .iter()
+                    .all(|snap| snap.as_bytes() == snaps[0].as_bytes())
+            );
+        }
+
+        stop.store(true, Ordering::Relaxed);
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn without_blank_lines_collapses_runs() {
+        let snap = CodexLogSnapshot::new(
+            b"\nfirst\n\n\n  \nsecond\n\nthird".to_vec(),
+            "thread".to_string(),
+        );
+        pretty_assertions::assert_eq!(snap.without_blank_lines(), "first\nsecond\nthird");
+        pretty_assertions::assert_eq!(snap.as_bytes(), b"\nfirst\n\n\n  \nsecond\n\nthird");
+    }
+
+    #[test]
+    fn submit_emits_feedback_submitted_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let fb = CodexFeedback::with_capacity(64).with_event_sink({
+            let events = events.clone();
+            move |event| events.lock().unwrap().push(event)
+        });
+        {
+            let mut w = fb.make_writer().make_writer();
+            w.write_all(b"something broke\n").unwrap();
+        }
+
+        let id = ConversationId::new();
+        let path = fb.submit(Some(id), "bug", dir.path()).unwrap();
+
+        pretty_assertions::assert_eq!(fs::read(&path).unwrap(), b"something broke\n");
+        let events = events.lock().unwrap();
+        pretty_assertions::assert_eq!(events.len(), 1);
+        let EventMsg::FeedbackSubmitted(event) = &events[0] else {
+            panic!("unexpected event: {:?}", events[0]);
+        };
+        pretty_assertions::assert_eq!(
+            event,
+            &FeedbackSubmittedEvent {
+                conversation_id: Some(id),
+                classification: "bug".to_string(),
+                path,
+            }
+        );
+    }
+
+    #[test]
+    fn snapshot_with_tail_returns_utf8_suffix() {
+        let fb = CodexFeedback::with_capacity(64);
+        {
+            let mut w = fb.make_writer().make_writer();
+            w.write_all("first line\nsecond: héllo\n".as_bytes())
+                .unwrap();
+        }
+
+        // "é" is two bytes; a 5-byte tail would start inside it.
+        let (snap, tail) = fb.snapshot_with_tail(None, 5);
+        let full = std::str::from_utf8(snap.as_bytes()).unwrap();
+        pretty_assertions::assert_eq!(tail, "llo\n");
+        assert!(full.ends_with(&tail));
+
+        let (_, everything) = fb.snapshot_with_tail(None, 1024);
+        pretty_assertions::assert_eq!(everything, full);
+    }
+
+    #[test]
+    fn strict_writes_fail_when_data_is_discarded() {
+        let lenient = CodexFeedback::with_capacity(0);
+        let strict = CodexFeedback::with_capacity(0).with_strict_writes();
+
+        pretty_assertions::assert_eq!(
+            lenient.make_writer().make_writer().write(b"lost").unwrap(),
+            4
+        );
+        let err = strict
+            .make_writer()
+            .make_writer()
+            .write(b"lost")
+            .unwrap_err();
+        pretty_assertions::assert_eq!(err.kind(), io::ErrorKind::Other);
+
+        let roomy = CodexFeedback::with_capacity(8).with_strict_writes();
+        pretty_assertions::assert_eq!(roomy.make_writer().make_writer().write(b"kept").unwrap(), 4);
+    }
+
+    #[test]
+    fn stats_counts_bytes_lines_words_and_chars() {
+        let snap = CodexLogSnapshot::new(
+            "INFO  started  ok\nWARN naïve retry\n\npartial"
+                .as_bytes()
+                .to_vec(),
+            "thread".to_string(),
+        );
+        pretty_assertions::assert_eq!(
+            snap.stats(),
+            SnapshotStats {
+                bytes: 44,
+                lines: 4,
+                words: 7,
+                chars: 43,
+            }
+        );
+        pretty_assertions::assert_eq!(snap.stats().to_string(), "44 B, 4 lines, 7 words, 43 chars");
+    }
+
+    #[test]
+    fn remembers_last_saved_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let fb = CodexFeedback::with_capacity(64);
+        pretty_assertions::assert_eq!(fb.last_saved_path(), None);
+
+        let first = fb.snapshot(None).save_to_dir(dir.path()).unwrap();
+        pretty_assertions::assert_eq!(fb.last_saved_path(), Some(first));
+
+        let id = ConversationId::new();
+        let second = fb.snapshot(Some(id)).save_to_dir(dir.path()).unwrap();
+        pretty_assertions::assert_eq!(fb.last_saved_path(), Some(second));
+
+        // Snapshots that did not come from this instance do not touch it.
+        CodexLogSnapshot::new(Vec::new(), "other".to_string())
+            .save_to_dir(dir.path())
+            .unwrap();
+        pretty_assertions::assert_eq!(
+            fb.last_saved_path(),
+            Some(dir.path().join(format!("codex-feedback-{id}.log")))
+        );
+    }
+
+    #[test]
+    fn snapshot_all_is_sorted_by_conversation_id() {
+        let fb = CodexFeedback::with_capacity(64);
+        let mut ids: Vec<ConversationId> = (0..4).map(|_| ConversationId::new()).collect();
+        for id in ids.iter().rev() {
+            let mut w = fb.make_writer_for(*id).make_writer();
+            w.write_all(format!("log for {id}\n").as_bytes()).unwrap();
+        }
+        {
+            let mut w = fb.make_writer().make_writer();
+            w.write_all(b"shared\n").unwrap();
+        }
+
+        ids.sort();
+        let snapshots = fb.snapshot_all();
+        let actual: Vec<(String, Vec<u8>)> = snapshots
+            .iter()
+            .map(|snap| (snap.thread_id.clone(), snap.as_bytes().to_vec()))
+            .collect();
+        let expected: Vec<(String, Vec<u8>)> = ids
+            .iter()
+            .map(|id| (id.to_string(), format!("log for {id}\n").into_bytes()))
+            .collect();
+        pretty_assertions::assert_eq!(actual, expected);
+
+        // Per-conversation snapshots come from the conversation's own buffer.
+        pretty_assertions::assert_eq!(fb.snapshot(Some(ids[0])).as_bytes(), expected[0].1);
+        pretty_assertions::assert_eq!(fb.snapshot(None).as_bytes(), b"shared\n");
+    }
+
+    #[test]
+    fn limited_save_keeps_tail_and_appends_footer() {
+        let dir = tempfile::tempdir().unwrap();
+        let snap = CodexLogSnapshot::new(b"old line\nnew line\n".to_vec(), "limited".to_string());
+
+        let path = snap.save_to_dir_limited(dir.path(), 9).unwrap();
+        pretty_assertions::assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "new line\n[truncated to fit]\n"
+        );
+
+        let path = snap.save_to_dir_limited(dir.path(), 1024).unwrap();
+        pretty_assertions::assert_eq!(fs::read(&path).unwrap(), b"old line\nnew line\n");
+    }
+
+    #[test]
+    fn checksum_header_detects_corruption() {
+        let dir = tempfile::tempdir().unwrap();
+        let snap = CodexLogSnapshot::new(b"line one\nline two\n".to_vec(), "checked".to_string());
+
+        let path = snap.save_to_dir_with_checksum(dir.path()).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        pretty_assertions::assert_eq!(
+            contents.lines().next().unwrap(),
+            format!(
+                "# codex-feedback sha256={:x} len=18",
+                Sha256::digest(b"line one\nline two\n")
+            )
+        );
+        assert!(CodexLogSnapshot::verify_file(&path).unwrap());
+
+        // Truncated on disk.
+        fs::write(&path, &contents[..contents.len() - 4]).unwrap();
+        assert!(!CodexLogSnapshot::verify_file(&path).unwrap());
+
+        // Same length, different bytes.
+        fs::write(&path, contents.replace("two", "TWO")).unwrap();
+        assert!(!CodexLogSnapshot::verify_file(&path).unwrap());
+
+        let plain = snap.save_to_dir(dir.path()).unwrap();
+        let err = CodexLogSnapshot::verify_file(&plain).unwrap_err();
+        pretty_assertions::assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn plain_strips_ansi_and_colored_keeps_it() {
+        let colored = "\x1b[2m2025-01-01T00:00:00Z\x1b[0m \x1b[1;31mERROR\x1b[0m boom\n";
+        let snap = CodexLogSnapshot::new(colored.as_bytes().to_vec(), "ansi".to_string());
+
+        pretty_assertions::assert_eq!(snap.colored(), colored);
+        pretty_assertions::assert_eq!(snap.plain(), "2025-01-01T00:00:00Z ERROR boom\n");
+    }
+
+    #[test]
+    fn terminal_safe_neutralizes_control_sequences() {
+        let raw = "\x1b[1;31mERROR\x1b[0m\tboom\r\n\
+                   title \x1b]0;pwned\x07 bell\n\
+                   fake\rreal \x08\u{9b}2J \u{202e}txt.exe\n";
+        let snap = CodexLogSnapshot::new(raw.as_bytes().to_vec(), "t".to_string());
+
+        let safe = snap.terminal_safe();
+        pretty_assertions::assert_eq!(
+            safe,
+            "ERROR\tboom\r\n\
+             title \\u{1b}]0;pwned\\u{7} bell\n\
+             fake\\rreal \\u{8}\\u{9b}2J \\u{202e}txt.exe\n"
+        );
+        assert!(!safe.contains('\x1b'));
+    }
+
+    #[test]
+    fn line_capacity_keeps_only_the_newest_lines() {
+        let fb = CodexFeedback::with_capacity(1024).with_line_capacity(3);
+        let mut w = fb.make_writer().make_writer();
+        for i in 0..10 {
+            writeln!(w, "line {i} {}", "x".repeat(i)).unwrap();
+        }
+        pretty_assertions::assert_eq!(
+            String::from_utf8_lossy(fb.snapshot(None).as_bytes()),
+            "line 7 xxxxxxx\nline 8 xxxxxxxx\nline 9 xxxxxxxxx\n"
+        );
+
+        let id = ConversationId::new();
+        let mut w = fb.make_writer_for(id).make_writer();
+        w.write_all(b"a\nb\nc\nd\ne\n").unwrap();
+        pretty_assertions::assert_eq!(fb.snapshot(Some(id)).as_bytes(), b"c\nd\ne\n");
+    }
+
+    #[test]
+    fn line_capacity_still_honors_the_byte_ceiling() {
+        let fb = CodexFeedback::with_capacity(8).with_line_capacity(100);
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"one\ntwo\nthree\n").unwrap();
+        pretty_assertions::assert_eq!(fb.snapshot(None).as_bytes(), b"o\nthree\n");
+    }
+
+    #[test]
+    fn has_logs_for_checks_conversation_or_shared_buffer() {
+        let id = ConversationId::new();
+
+        let shared = CodexFeedback::with_capacity(64);
+        assert!(!shared.has_logs_for(&id));
+        shared
+            .make_writer()
+            .make_writer()
+            .write_all(b"hi\n")
+            .unwrap();
+        assert!(shared.has_logs_for(&id));
+
+        let per_conversation = CodexFeedback::with_capacity(64);
+        let other = ConversationId::new();
+        per_conversation
+            .make_writer_for(other)
+            .make_writer()
+            .write_all(b"other\n")
+            .unwrap();
+        assert!(per_conversation.has_logs_for(&other));
+        assert!(!per_conversation.has_logs_for(&id));
+        per_conversation
+            .make_writer_for(id)
+            .make_writer()
+            .write_all(b"mine\n")
+            .unwrap();
+        assert!(per_conversation.has_logs_for(&id));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn full_volume_reports_out_of_space() {
+        // Every write to /dev/full fails with ENOSPC.
+        let err = write_contents(Path::new("/dev/full"), b"0123456789").unwrap_err();
+        match err {
+            FeedbackError::OutOfSpace { path, needed } => {
+                pretty_assertions::assert_eq!(path, PathBuf::from("/dev/full"));
+                pretty_assertions::assert_eq!(needed, 10);
+            }
+            other => panic!("expected OutOfSpace, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn other_write_failures_stay_io_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let snap = CodexLogSnapshot::new(b"x".to_vec(), "missing".to_string());
+        let err = snap
+            .save_to_dir(&dir.path().join("does-not-exist"))
+            .unwrap_err();
+        assert!(
+            matches!(&err, FeedbackError::Io(io) if io.kind() == io::ErrorKind::NotFound),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn snapshots_redact_secrets() {
+        let fb = CodexFeedback::with_capacity(1024);
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"using sk-abcdefghijklmnopxyz\n").unwrap();
+
+        pretty_assertions::assert_eq!(fb.snapshot(None).as_bytes(), b"using sk-[REDACTED]\n");
+        let fb = fb.with_redaction_style(RedactionStyle::Partial { keep: 3 });
+        pretty_assertions::assert_eq!(
+            fb.snapshot(None).as_bytes(),
+            "using sk-abc…xyz\n".as_bytes()
+        );
+
+        let fb = fb.with_redaction_limit(0);
+        pretty_assertions::assert_eq!(
+            fb.snapshot(None).as_bytes(),
+            b"using sk-abcdefghijklmnopxyz\n[redaction limit reached]\n"
+        );
+    }
+
+    #[test]
+    fn snapshot_offsets_track_evicted_bytes() {
+        let fb = CodexFeedback::with_capacity(10);
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"0123456789").unwrap();
+        w.write_all(b"abcdefghij").unwrap();
+        w.write_all(b"ABCDE").unwrap();
+
+        let snap = fb.snapshot(None);
+        pretty_assertions::assert_eq!(snap.as_bytes(), b"fghijABCDE");
+        pretty_assertions::assert_eq!((snap.start_offset, snap.end_offset), (15, 25));
+        pretty_assertions::assert_eq!(
+            snap.to_json(),
+            serde_json::json!({
+                "schema_version": 1,
+                "thread_id": snap.thread_id,
+                "start_offset": 15,
+                "end_offset": 25,
+                "sequence": 1,
+                "text": "fghijABCDE",
+            })
+        );
+    }
+
+    #[test]
+    fn merge_concatenates_with_separator_and_keeps_primary_metadata() {
+        let parent = CodexLogSnapshot::new(b"parent line\n".to_vec(), "parent".to_string());
+        let child = CodexLogSnapshot::new(b"child line\n".to_vec(), "child".to_string());
+
+        let merged = parent.merge(child, "--- subprocess ---\n");
+        pretty_assertions::assert_eq!(merged.thread_id, "parent");
+        pretty_assertions::assert_eq!(
+            merged.as_bytes(),
+            b"parent line\n--- subprocess ---\nchild line\n"
+        );
+        pretty_assertions::assert_eq!((merged.start_offset, merged.end_offset), (0, 12));
+    }
+
+    #[test]
+    fn line_times_prefix_lines_relative_to_enabling() {
+        use crate::clock::MockClock;
+        use std::time::Duration;
+
+        let clock = Arc::new(MockClock::new());
+        let fb = CodexFeedback::with_capacity(30)
+            .with_clock(clock.clone())
+            .with_line_timestamps();
+        let mut w = fb.make_writer().make_writer();
+
+        clock.advance(Duration::from_millis(1234));
+        w.write_all(b"first\nsec").unwrap();
+        clock.advance(Duration::from_millis(500));
+        w.write_all(b"ond\n").unwrap();
+        clock.advance(Duration::from_secs(2));
+        w.write_all(b"third\n").unwrap();
+        pretty_assertions::assert_eq!(
+            String::from_utf8_lossy(fb.snapshot_with_line_times(None).as_bytes()),
+            "[+1.234s] first\n[+1.234s] second\n[+3.734s] third\n"
+        );
+        pretty_assertions::assert_eq!(fb.snapshot(None).as_bytes(), b"first\nsecond\nthird\n");
+
+        // Evicting "first\n" and the start of "second\n" drops their markers too.
+        w.write_all(b"fourth line is long\n").unwrap();
+        pretty_assertions::assert_eq!(
+            String::from_utf8_lossy(fb.snapshot_with_line_times(None).as_bytes()),
+            "ond\n[+3.734s] third\n[+3.734s] fourth line is long\n"
+        );
+        let ring = fb.inner.ring.read().unwrap();
+        pretty_assertions::assert_eq!(ring.line_times.as_ref().unwrap().markers.len(), 2);
+    }
+
+    #[test]
+    fn drain_snapshot_takes_everything_and_empties_the_buffer() {
+        let fb = CodexFeedback::with_capacity(64);
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"one\ntwo\n").unwrap();
+
+        let drained = fb.drain_snapshot(None);
+        pretty_assertions::assert_eq!(drained.as_bytes(), b"one\ntwo\n");
+        pretty_assertions::assert_eq!((drained.start_offset, drained.end_offset), (0, 8));
+        assert!(fb.is_empty());
+        pretty_assertions::assert_eq!(fb.snapshot(None).as_bytes(), b"");
+
+        w.write_all(b"three\n").unwrap();
+        let next = fb.drain_snapshot(None);
+        pretty_assertions::assert_eq!(next.as_bytes(), b"three\n");
+        pretty_assertions::assert_eq!((next.start_offset, next.end_offset), (8, 14));
+
+        let id = ConversationId::new();
+        fb.make_writer_for(id)
+            .make_writer()
+            .write_all(b"mine\n")
+            .unwrap();
+        pretty_assertions::assert_eq!(fb.drain_snapshot(Some(id)).as_bytes(), b"mine\n");
+        assert!(!fb.has_logs_for(&id));
+    }
+
+    #[test]
+    fn line_helpers_agree_on_mixed_line_endings() {
+        let fb = CodexFeedback::with_capacity(256);
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"alpha\r\nbeta\n\r\ngamma\r\ndelta").unwrap();
+        let snap = fb.snapshot(None);
+
+        pretty_assertions::assert_eq!(snap.lines(), vec!["alpha", "beta", "", "gamma", "delta"]);
+        pretty_assertions::assert_eq!(snap.tail(2), vec!["gamma", "delta"]);
+        pretty_assertions::assert_eq!(snap.tail(10).len(), 5);
+        pretty_assertions::assert_eq!(snap.grep("a\r"), Vec::<String>::new());
+        pretty_assertions::assert_eq!(snap.grep("mm"), vec!["gamma"]);
+        pretty_assertions::assert_eq!(snap.without_blank_lines(), "alpha\r\nbeta\ngamma\r\ndelta");
+        pretty_assertions::assert_eq!(
+            snap.enumerate_lines().collect::<Vec<_>>(),
+            vec![
+                (0, "alpha"),
+                (7, "beta"),
+                (12, ""),
+                (14, "gamma"),
+                (21, "delta")
+            ]
+        );
+        pretty_assertions::assert_eq!(snap.find("^$"), Some(12));
+        pretty_assertions::assert_eq!(snap.wrapped(80), "alpha\r\nbeta\n\r\ngamma\r\ndelta");
+        pretty_assertions::assert_eq!(snap.wrapped(3), "alp\nha\r\nbet\na\n\r\ngam\nma\r\ndel\nta");
+        let leveled = CodexLogSnapshot::new(b"WARN a\r\nWARN b\r\nINFO c".to_vec(), "t".into());
+        pretty_assertions::assert_eq!(leveled.dominant_level(), Some(Level::WARN));
+        let grouped = CodexFeedback::with_capacity(256);
+        grouped
+            .make_writer()
+            .make_writer()
+            .write_all(b"alpha\r\nbeta\nWARN x\r\nWARN y")
+            .unwrap();
+        pretty_assertions::assert_eq!(
+            String::from_utf8_lossy(grouped.snapshot_grouped(None).as_bytes()),
+            "[- x2] alpha\r\n[WARN x2] WARN x\r\n"
+        );
+        // The guard only yields terminated lines, but strips `\r\n` the same way.
+        let guard = fb.lines_guard();
+        pretty_assertions::assert_eq!(
+            (&guard).into_iter().collect::<Vec<_>>(),
+            vec!["alpha", "beta", "", "gamma"]
+        );
+    }
+
+    #[test]
+    fn first_wrap_callback_fires_once() {
+        let fb = CodexFeedback::with_capacity(8);
+        let fired = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        fb.on_first_wrap({
+            let fired = fired.clone();
+            move || {
+                fired.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        let mut writer = fb.make_writer().make_writer();
+        writer.write_all(b"12345678").unwrap();
+        assert_eq!(fired.load(Ordering::SeqCst), 0);
+        writer.write_all(b"abcdefgh").unwrap();
+        writer.write_all(b"ijklmnop").unwrap();
+        fb.ingest("late", b"more data");
+        assert_eq!(fired.load(Ordering::SeqCst), 1);
+
+        // Emptying the buffer re-arms it for the next overflow.
+        let _ = fb.drain_snapshot(None);
+        assert_eq!(fired.load(Ordering::SeqCst), 1);
+        writer.write_all(b"12345678").unwrap();
+        writer.write_all(b"overflow").unwrap();
+        assert_eq!(fired.load(Ordering::SeqCst), 2);
+        writer.write_all(b"\nx\n").unwrap();
+        fb.clear_keeping_tail(1);
+        writer.write_all(b"overflow").unwrap();
+        writer.write_all(b"overflow").unwrap();
+        assert_eq!(fired.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn preallocated_buffers_do_not_grow_while_filling() {
+        let fb = CodexFeedback::with_capacity(4096).with_preallocate(true);
+        let capacity = fb.inner.ring_read().buf.capacity();
+        assert!(capacity >= 4096, "{capacity}");
+
+        let mut writer = fb.make_writer().make_writer();
+        for _ in 0..64 {
+            writer.write_all(&[b'x'; 63]).unwrap();
+            writer.write_all(b"\n").unwrap();
+        }
+        assert_eq!(fb.inner.ring_read().len(), 4096);
+        assert_eq!(fb.inner.ring_read().buf.capacity(), capacity);
+
+        let fb = CodexFeedback::with_capacity(4096).with_preallocate(false);
+        assert_eq!(fb.inner.ring_read().buf.capacity(), 0);
+        let id = ConversationId::new();
+        fb.make_writer_for(id)
+            .make_writer()
+            .write_all(b"short\n")
+            .unwrap();
+        let conversations = fb.inner.conversations.read().unwrap();
+        assert!(conversations[&id].buf.capacity() < 4096);
+    }
+
+    #[test]
+    fn memory_footprint_covers_every_buffer() {
+        let fb = CodexFeedback::with_capacity(256);
+        let mut writer = fb.make_writer().make_writer();
+        writer.write_all(b"shared line\n").unwrap();
+        let shared_only = fb.memory_footprint();
+        assert!(shared_only >= fb.snapshot(None).as_bytes().len());
+
+        let mut previous = shared_only;
+        for _ in 0..3 {
+            let mut writer = fb.make_writer_for(ConversationId::new()).make_writer();
+            writer.write_all(b"conversation line\n").unwrap();
+            let footprint = fb.memory_footprint();
+            assert!(footprint > previous + 256, "{footprint} vs {previous}");
+            previous = footprint;
+        }
+    }
+
+    #[test]
+    fn relative_timestamps_count_from_the_given_base() {
+        use crate::clock::MockClock;
+        use std::time::Duration;
+
+        let clock = Arc::new(MockClock::new());
+        let base = clock.now();
+        clock.advance(Duration::from_millis(1500));
+        let fb = CodexFeedback::with_capacity(256)
+            .with_clock(clock.clone())
+            .with_relative_timestamps(base);
+        let mut w = fb.make_writer().make_writer();
+
+        w.write_all(b"boot\n").unwrap();
+        clock.advance(Duration::from_millis(123));
+        w.write_all(b"ready\n").unwrap();
+        clock.advance(Duration::from_secs(2));
+        w.write_all(b"done\n").unwrap();
+
+        let text =
+            String::from_utf8_lossy(fb.snapshot_with_line_times(None).as_bytes()).into_owned();
+        assert_eq!(text, "[+1.500s] boot\n[+1.623s] ready\n[+3.623s] done\n");
+        let times: Vec<f64> = text
+            .lines()
+            .map(|line| {
+                let secs = line.strip_prefix("[+").unwrap().split_once("s]").unwrap().0;
+                secs.parse().unwrap()
+            })
+            .collect();
+        assert!(times.windows(2).all(|pair| pair[0] <= pair[1]), "{times:?}");
+    }
+
+    #[test]
+    fn with_slices_exposes_both_halves_of_a_wrapped_ring() {
+        let fb = CodexFeedback::with_capacity(8);
+        let mut writer = fb.make_writer().make_writer();
+        writer.write_all(b"abcdef").unwrap();
+        writer.write_all(b"ghij").unwrap();
+
+        let (front, back) = fb.with_slices(|front, back| (front.to_vec(), back.to_vec()));
+        assert!(!back.is_empty(), "expected the ring to wrap: {front:?}");
+        assert_eq!([front, back].concat(), b"cdefghij");
+    }
+
+    #[test]
+    fn close_conversation_saves_then_frees_the_buffer() {
+        let fb = CodexFeedback::with_capacity(256);
+        let id = ConversationId::new();
+        let mut writer = fb.make_writer_for(id).make_writer();
+        writer.write_all(b"conversation log\n").unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = fb
+            .close_conversation(id, Some(dir.path()))
+            .unwrap()
+            .expect("non-empty buffer is saved");
+        assert_eq!(fs::read(&path).unwrap(), b"conversation log\n");
+        assert!(!fb.inner.conversations.read().unwrap().contains_key(&id));
+
+        // Closing again, or closing without a directory, writes nothing.
+        assert_eq!(fb.close_conversation(id, Some(dir.path())).unwrap(), None);
+        writer.write_all(b"more\n").unwrap();
+        assert_eq!(fb.close_conversation(id, None).unwrap(), None);
+        assert!(!fb.inner.conversations.read().unwrap().contains_key(&id));
+    }
+
+    #[test]
+    fn drop_pattern_discards_matching_lines_once_complete() {
+        let fb = CodexFeedback::with_capacity(256)
+            .with_drop_pattern(Regex::new(r"^heartbeat \d+$").unwrap());
+        let mut writer = fb.make_writer().make_writer();
+        writer.write_all(b"start\nheartbeat 1\n").unwrap();
+        writer.write_all(b"heart").unwrap();
+        writer.write_all(b"beat 2\r\nwork ").unwrap();
+        assert_eq!(fb.snapshot(None).as_bytes(), b"start\n");
+        writer
+            .write_all(b"done\nheartbeat 3 is not periodic\n")
+            .unwrap();
+        writer.write_all(b"heartbeat 4\nend\n").unwrap();
+
+        assert_eq!(
+            String::from_utf8_lossy(fb.snapshot(None).as_bytes()),
+            "start\nwork done\nheartbeat 3 is not periodic\nend\n"
+        );
+    }
+
+    #[test]
+    fn snapshots_carry_increasing_sequence_numbers() {
+        let fb = CodexFeedback::with_capacity(64);
+        let mut writer = fb.make_writer().make_writer();
+        writer.write_all(b"line\n").unwrap();
+
+        let first = fb.snapshot(None);
+        let many = fb.snapshot_many(&[None, Some(ConversationId::new())]);
+        let drained = fb.drain_snapshot(None);
+        let sequences: Vec<u64> = std::iter::once(&first)
+            .chain(&many)
+            .chain([&drained])
+            .map(|snapshot| snapshot.sequence)
+            .collect();
+        assert_eq!(sequences, vec![1, 2, 3, 4]);
+        assert_eq!(drained.to_json()["sequence"], serde_json::json!(4));
+        assert_eq!(
+            CodexLogSnapshot::new(Vec::new(), "t".to_string()).sequence,
+            0
+        );
+    }
+
+    #[test]
+    fn drain_lines_keeps_the_partial_tail() {
+        let fb = CodexFeedback::with_capacity(256);
+        let mut writer = fb.make_writer().make_writer();
+        writer.write_all(b"one\ntwo\r\nthr").unwrap();
+
+        assert_eq!(fb.drain_lines(), vec!["one", "two"]);
+        assert_eq!(fb.snapshot(None).as_bytes(), b"thr");
+        assert_eq!(fb.drain_lines(), Vec::<String>::new());
+
+        writer.write_all(b"ee\n").unwrap();
+        assert_eq!(fb.drain_lines(), vec!["three"]);
+        assert!(fb.is_empty());
+    }
+
+    #[test]
+    fn nul_bytes_are_replaced_in_writes() {
+        let fb = CodexFeedback::with_capacity(64).with_nul_replacement(b'?');
+        let mut writer = fb.make_writer().make_writer();
+        writer.write_all(b"bin\0ary\0\0 line\n").unwrap();
+        assert_eq!(fb.snapshot(None).as_bytes(), b"bin?ary?? line\n");
+
+        let fb = CodexFeedback::with_capacity(64);
+        fb.make_writer().make_writer().write_all(b"a\0b").unwrap();
+        assert_eq!(fb.snapshot(None).as_bytes(), b"a\0b");
+    }
+
+    #[test]
+    fn nul_bytes_never_reach_file_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = CodexLogSnapshot::new(b"log\n".to_vec(), "thread\0id".to_string())
+            .save_to_dir(dir.path())
+            .unwrap();
+        assert_eq!(path, dir.path().join("codex-feedback-thread_id.log"));
+
+        let long = format!("{}\0", "x".repeat(1000));
+        assert!(!log_file_name(&long).contains('\0'));
+    }
+
+    #[test]
+    fn find_returns_the_start_of_the_first_matching_line() {
+        let snapshot = CodexLogSnapshot::new(
+            "INFO start\r\nWARN caf\u{e9} slow\nERROR boom (1)\nERROR boom (2)\n"
+                .as_bytes()
+                .to_vec(),
+            "t".to_string(),
+        );
+        let line_at = |offset: usize| {
+            let rest = &snapshot.as_bytes()[offset..];
+            let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+            String::from_utf8_lossy(&rest[..end]).into_owned()
+        };
+
+        let first_error = snapshot.find(r"^ERROR").unwrap();
+        assert_eq!(first_error, 28);
+        assert_eq!(line_at(first_error), "ERROR boom (1)");
+        assert_eq!(snapshot.find(r"start$"), Some(0));
+        // Not a valid regex, so matched literally.
+        assert_eq!(snapshot.find("boom (2"), Some(43));
+        assert_eq!(snapshot.find("missing"), None);
+    }
+
+    #[test]
+    fn snapshot_to_slice_copies_the_tail_that_fits() {
+        let fb = CodexFeedback::with_capacity(1024);
+        fb.make_writer()
+            .make_writer()
+            .write_all("first line\nsecond line\ncaf\u{e9} au lait\n".as_bytes())
+            .expect("write");
+
+        let mut small = [0u8; 16];
+        let len = fb.snapshot_to_slice(&mut small);
+        assert_eq!(len, 16);
+        assert_eq!(&small[..len], b"e\ncaf\xc3\xa9 au lait\n");
+
+        // Cutting into the two-byte "\u{e9}" skips its continuation byte.
+        let mut cut = [0u8; 10];
+        let len = fb.snapshot_to_slice(&mut cut);
+        assert_eq!(&cut[..len], b" au lait\n");
+
+        let mut large = [0u8; 64];
+        let len = fb.snapshot_to_slice(&mut large);
+        assert_eq!(&large[..len], fb.snapshot(None).as_bytes());
+        assert_eq!(fb.snapshot_to_slice(&mut []), 0);
+    }
+
+    #[test]
+    fn plain_writes_take_one_lock_each() {
+        fn locks_per_write(fb: &CodexFeedback) -> usize {
+            let mut writer = fb.make_writer().make_writer();
+            WRITE_PATH_LOCKS.with(|locks| locks.set(0));
+            writer.write_all(b"INFO plain\n").expect("write");
+            WRITE_PATH_LOCKS.with(Cell::get)
+        }
+
+        assert_eq!(locks_per_write(&CodexFeedback::new()), 1);
+        // Each enabled feature adds only its own lock.
+        let teed = CodexFeedback::new().with_tee(std::io::sink());
+        assert_eq!(locks_per_write(&teed), 2);
+        let filtered = CodexFeedback::new()
+            .with_tee(std::io::sink())
+            .with_write_filter(|_| true);
+        assert_eq!(locks_per_write(&filtered), 3);
+    }
+
+    #[test]
+    fn write_coalescing_stores_whole_lines_at_once() {
+        let fragmented = |fb: &CodexFeedback| {
+            let make_writer = fb.make_writer();
+            std::thread::scope(|scope| {
+                for thread in 0..4 {
+                    let make_writer = &make_writer;
+                    scope.spawn(move || {
+                        for event in 0..25 {
+                            let mut writer = make_writer.make_writer();
+                            for part in ["INFO ", "thread ", &thread.to_string(), " event "] {
+                                writer.write_all(part.as_bytes()).expect("write");
+                            }
+                            writer
+                                .write_all(format!("{event}\n").as_bytes())
+                                .expect("write");
+                        }
+                    });
+                }
+            });
+        };
+        let sorted_lines = |fb: &CodexFeedback| {
+            let mut lines = fb.snapshot(None).lines();
+            lines.sort();
+            lines
+        };
+
+        let plain = CodexFeedback::new();
+        fragmented(&plain);
+        let coalesced = CodexFeedback::new().with_write_coalescing(1024);
+        fragmented(&coalesced);
+
+        let mut expected: Vec<String> = (0..4)
+            .flat_map(|thread| {
+                (0..25).map(move |event| format!("INFO thread {thread} event {event}"))
+            })
+            .collect();
+        expected.sort();
+        assert_eq!(sorted_lines(&coalesced), expected);
+        // One ring push per write without coalescing, one per line with it.
+        assert_eq!(plain.version(), 500);
+        assert_eq!(coalesced.version(), 100);
+
+        // Partial lines are stored on flush, on drop, or once the limit is reached.
+        let fb = CodexFeedback::new().with_write_coalescing(8);
+        let mut writer = fb.make_writer().make_writer();
+        writer.write_all(b"part").expect("write");
+        assert_eq!(fb.snapshot(None).as_bytes(), b"");
+        writer.flush().expect("flush");
+        assert_eq!(fb.snapshot(None).as_bytes(), b"part");
+        writer.write_all(b"ial").expect("write");
+        drop(writer);
+        assert_eq!(fb.snapshot(None).as_bytes(), b"partial");
+        fb.make_writer()
+            .make_writer()
+            .write_all(b" and a long tail")
+            .expect("write");
+        assert_eq!(fb.version(), 3);
+    }
+
+    #[test]
+    fn hash_into_matches_hashing_a_copy() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::Hasher;
+
+        let fb = CodexFeedback::with_capacity(64);
+        for i in 0..20 {
+            fb.make_writer()
+                .make_writer()
+                .write_all(format!("line {i}\n").as_bytes())
+                .expect("write");
+        }
+        let halves = fb.with_slices(|front, back| (front.len(), back.len()));
+        assert!(halves.0 > 0 && halves.1 > 0, "{halves:?}");
+
+        let mut streamed = DefaultHasher::new();
+        fb.hash_into(&mut streamed);
+        let mut copied = DefaultHasher::new();
+        copied.write(fb.snapshot(None).as_bytes());
+        assert_eq!(streamed.finish(), copied.finish());
+    }
+
+    #[test]
+    fn schema_version_marks_text_json_and_frames() {
+        let fb = CodexFeedback::new().with_snapshot_schema_version(3);
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"INFO hello\n")
+            .expect("write");
+
+        let snapshot = fb.snapshot(None);
+        assert_eq!(
+            snapshot.as_bytes(),
+            b"# codex-feedback-schema: 3\n--- logs ---\nINFO hello\n"
+        );
+        assert_eq!(snapshot.end_offset, 11);
+        assert_eq!(snapshot.to_json()["schema_version"], serde_json::json!(3));
+        let decoded = CodexLogSnapshot::from_frame(&snapshot.to_frame()).expect("frame");
+        assert_eq!(decoded.schema_version, 3);
+        assert_eq!(decoded.as_bytes(), snapshot.as_bytes());
+
+        let unversioned = CodexFeedback::new().snapshot(None);
+        assert_eq!(unversioned.schema_version, SNAPSHOT_SCHEMA_VERSION);
+        assert_eq!(unversioned.as_bytes(), b"");
+    }
+
+    #[test]
+    fn pinned_lines_survive_eviction() {
+        let fb = CodexFeedback::with_capacity(45);
+        fb.pin_line("session started: codex exec ls\n");
+        let mut writer = fb.make_writer().make_writer();
+        for i in 0..100 {
+            writer
+                .write_all(format!("INFO churn {i:03}\n").as_bytes())
+                .expect("write");
+        }
+
+        let snapshot = fb.snapshot(None);
+        assert_eq!(
+            String::from_utf8_lossy(snapshot.as_bytes()),
+            "session started: codex exec ls\nINFO churn 097\nINFO churn 098\nINFO churn 099\n"
+        );
+        assert_eq!(snapshot.end_offset, 1500);
+
+        for i in 0..MAX_PINNED_LINES {
+            fb.pin_line(&format!("pin {i}"));
+        }
+        let snapshot = fb.snapshot(None);
+        let lines = snapshot.lines();
+        assert_eq!(lines[0], "pin 0");
+        assert_eq!(
+            lines[MAX_PINNED_LINES - 1],
+            format!("pin {}", MAX_PINNED_LINES - 1)
+        );
+        assert_eq!(lines[MAX_PINNED_LINES], "INFO churn 097");
+    }
+
+    #[test]
+    fn missing_temp_dir_is_created_or_falls_back() {
+        let root = tempfile::tempdir().expect("tempdir");
+        let snapshot = CodexLogSnapshot::new(b"log\n".to_vec(), "t".to_string());
+
+        let missing = root.path().join("tmp").join("nested");
+        let (path, note) = snapshot
+            .save_to_dir_or_else(&missing, || unreachable!("temp dir can be created"))
+            .expect("save");
+        assert_eq!(path.parent(), Some(missing.as_path()));
+        assert_eq!(note, None);
+
+        let blocker = root.path().join("not-a-dir");
+        fs::write(&blocker, b"").expect("write blocker");
+        let fallback = root.path().join("cwd");
+        fs::create_dir(&fallback).expect("create fallback");
+        let (path, note) = snapshot
+            .save_to_dir_or_else(&blocker.join("tmp"), || Ok(fallback.clone()))
+            .expect("save");
+        assert_eq!(path.parent(), Some(fallback.as_path()));
+        assert_eq!(fs::read(&path).expect("read"), b"log\n");
+        let note = note.expect("fallback note");
+        assert!(note.contains("is unavailable"), "{note}");
+        assert!(note.contains(&fallback.display().to_string()), "{note}");
+    }
+
+    #[test]
+    fn syslog_lines_follow_rfc_5424() {
+        let snapshot = CodexLogSnapshot::new(
+            b"2025-01-02T03:04:05.678901Z ERROR codex_core: boom\n\n\x1b[32mINFO\x1b[0m no timestamp\n"
+                .to_vec(),
+            "t".to_string(),
+        );
+        let syslog = snapshot.to_syslog(1, "codex cli");
+        let header =
+            Regex::new(r"^<(\d{1,3})>1 (\S+) (\S+) (\S{1,48}) (\d+) (\S+) (\S+) (.*)$").unwrap();
+        let fields: Vec<Vec<String>> = syslog
+            .lines()
+            .map(|line| {
+                let captures = header.captures(line).unwrap_or_else(|| panic!("{line:?}"));
+                (1..=8).map(|i| captures[i].to_string()).collect()
+            })
+            .collect();
+        let pid = std::process::id().to_string();
+        assert_eq!(
+            fields,
+            vec![
+                vec![
+                    "11",
+                    "2025-01-02T03:04:05.678901Z",
+                    "-",
+                    "codex_cli",
+                    &pid,
+                    "-",
+                    "-",
+                    "ERROR codex_core: boom"
+                ],
+                vec![
+                    "14",
+                    "-",
+                    "-",
+                    "codex_cli",
+                    &pid,
+                    "-",
+                    "-",
+                    "INFO no timestamp"
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn time_to_full_follows_a_steady_write_rate() {
+        use crate::clock::MockClock;
+
+        let clock = Arc::new(MockClock::new());
+        let fb = CodexFeedback::with_capacity(10_000).with_clock(clock.clone());
+        assert_eq!(fb.estimated_time_to_full(), None);
+
+        let mut writer = fb.make_writer().make_writer();
+        for i in 0..10 {
+            if i > 0 {
+                clock.advance(Duration::from_secs(1));
+            }
+            writer.write_all(&[b'x'; 100]).expect("write");
+        }
+        // 100 bytes/s with 9,000 bytes left.
+        let estimate = fb.estimated_time_to_full().expect("estimate");
+        assert!(
+            estimate.abs_diff(Duration::from_secs(90)) < Duration::from_millis(1),
+            "{estimate:?}"
+        );
+
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(fb.estimated_time_to_full(), None);
+    }
+
+    #[test]
+    fn split_on_separates_delimited_sections() {
+        let snapshot = CodexLogSnapshot {
+            start_offset: 100,
+            ..CodexLogSnapshot::new(
+                b"=====\nbegin rg\nno matches\n=====\r\n=====\nbegin ls\nsrc\n=====\n".to_vec(),
+                "thread".to_string(),
+            )
+        }
+        .with_classification("bug");
+
+        let parts = snapshot.split_on("=====");
+        let summary: Vec<(&str, &[u8], u64, u64)> = parts
+            .iter()
+            .map(|part| {
+                (
+                    part.thread_id.as_str(),
+                    part.as_bytes(),
+                    part.start_offset,
+                    part.end_offset,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("thread-0", &b"begin rg\nno matches\n"[..], 106, 126),
+                ("thread-1", &b"begin ls\nsrc\n"[..], 139, 152),
+            ]
+        );
+        assert_eq!(parts[1].classification.as_deref(), Some("bug"));
+        assert_eq!(snapshot.split_on("missing").len(), 1);
+    }
+
+    #[test]
+    fn write_filter_vetoes_matching_writes() {
+        let fb = CodexFeedback::new()
+            .with_write_filter(|data| !String::from_utf8_lossy(data).contains("secret"));
+        for line in ["INFO kept\n", "INFO a secret token\n", "INFO also kept\n"] {
+            fb.make_writer()
+                .make_writer()
+                .write_all(line.as_bytes())
+                .expect("write");
+        }
+        assert_eq!(fb.snapshot(None).as_bytes(), b"INFO kept\nINFO also kept\n");
+    }
+
+    #[test]
+    fn snapshot_whole_lines_trims_partial_ends() {
+        let fb = CodexFeedback::with_capacity(21);
+        let mut writer = fb.make_writer().make_writer();
+        writer
+            .write_all(b"alpha\nbravo\ncharlie\ndelta\nech")
+            .expect("write");
+
+        let snapshot = fb.snapshot_whole_lines();
+        assert_eq!(fb.snapshot(None).as_bytes(), b"avo\ncharlie\ndelta\nech");
+        assert_eq!(snapshot.as_bytes(), b"charlie\ndelta\n");
+        assert_eq!((snapshot.start_offset, snapshot.end_offset), (12, 26));
+
+        // Evicting exactly up to a newline leaves a complete first line.
+        let fb = CodexFeedback::with_capacity(14);
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"alpha\nbravo\ncharlie\n")
+            .expect("write");
+        assert_eq!(fb.snapshot_whole_lines().as_bytes(), b"bravo\ncharlie\n");
+        fb.drain_snapshot(None);
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"delta\n")
+            .expect("write");
+        assert_eq!(fb.snapshot_whole_lines().as_bytes(), b"delta\n");
+    }
+
+    #[test]
+    fn prelude_separator_splits_prelude_from_logs() {
+        let write = |fb: &CodexFeedback| {
+            fb.make_writer()
+                .make_writer()
+                .write_all(b"INFO hi\n--- logs --- quoted\n")
+                .expect("write");
+        };
+        let fb = CodexFeedback::new().with_snapshot_schema_version(2);
+        write(&fb);
+        let text = String::from_utf8_lossy(fb.snapshot(None).as_bytes()).into_owned();
+        let separator = format!("\n{DEFAULT_PRELUDE_SEPARATOR}\n");
+        assert_eq!(text.matches(&separator).count(), 1);
+        assert_eq!(
+            text.split_once(&separator),
+            Some((
+                "# codex-feedback-schema: 2",
+                "INFO hi\n--- logs --- quoted\n"
+            ))
+        );
+
+        let fb = CodexFeedback::new()
+            .with_build_info("0123abc", "0.59.0")
+            .with_prelude_separator("=== body ===");
+        write(&fb);
+        assert_eq!(
+            String::from_utf8_lossy(fb.snapshot(None).as_bytes()),
+            "# codex-feedback-build: version 0.59.0, commit 0123abc\n=== body ===\nINFO hi\n--- logs --- quoted\n"
+        );
+
+        let fb = CodexFeedback::new();
+        write(&fb);
+        assert_eq!(
+            fb.snapshot(None).as_bytes(),
+            b"INFO hi\n--- logs --- quoted\n"
+        );
+    }
+
+    #[test]
+    fn build_info_is_in_the_prelude_and_json() {
+        let fb = CodexFeedback::new()
+            .with_snapshot_schema_version(2)
+            .with_build_info("0123abc", "0.59.0");
+        fb.pin_line("session header");
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"INFO hi\n")
+            .expect("write");
+
+        let snapshot = fb.snapshot(None);
+        assert_eq!(
+            String::from_utf8_lossy(snapshot.as_bytes()),
+            "# codex-feedback-schema: 2\n# codex-feedback-build: version 0.59.0, commit 0123abc\n--- logs ---\nsession header\nINFO hi\n"
+        );
+        assert_eq!(
+            snapshot.to_json()["build"],
+            serde_json::json!({"commit": "0123abc", "version": "0.59.0"})
+        );
+        assert_eq!(
+            CodexFeedback::new().snapshot(None).to_json().get("build"),
+            None
+        );
+        assert_eq!(
+            CodexFeedback::new()
+                .with_build_info_from_env()
+                .snapshot(None)
+                .build_info
+                .map(|build| build.version.is_empty()),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn snapshots_never_see_an_empty_buffer_during_oversized_writes() {
+        let fb = CodexFeedback::with_capacity(1024);
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"seed\n")
+            .expect("write");
+        let done = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let mut writer = fb.make_writer().make_writer();
+                for i in 0..200u8 {
+                    writer.write_all(&[b'a' + i % 26; 4096]).expect("write");
+                }
+                done.store(true, Ordering::SeqCst);
+            });
+            let mut snapshots = 0;
+            while !done.load(Ordering::SeqCst) || snapshots == 0 {
+                let len = fb.snapshot(None).as_bytes().len();
+                assert!(len > 0, "snapshot {snapshots} was empty");
+                snapshots += 1;
+            }
+        });
+        assert_eq!(fb.snapshot(None).as_bytes(), [b'a' + 199 % 26; 1024]);
+    }
+
+    #[test]
+    fn set_capacity_races_safely_with_writers() {
+        let fb = CodexFeedback::with_capacity(1024);
+        let done = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let fb = &fb;
+                let done = &done;
+                scope.spawn(move || {
+                    let mut writer = fb.make_writer().make_writer();
+                    let mut i = 0;
+                    while !done.load(Ordering::SeqCst) {
+                        writer
+                            .write_all(format!("INFO thread {thread} line {i}\n").as_bytes())
+                            .expect("write");
+                        i += 1;
+                    }
+                });
+            }
+            for round in 0..500 {
+                fb.set_capacity(if round % 2 == 0 { 16 } else { 4096 });
+                let ring = fb.inner.ring_read();
+                assert!(ring.len() <= ring.max, "{} > {}", ring.len(), ring.max);
+            }
+            done.store(true, Ordering::SeqCst);
+        });
+
+        fb.set_capacity(32);
+        let ring = fb.inner.ring_read();
+        assert_eq!(ring.max, 32);
+        assert!(ring.len() <= 32);
+        assert_eq!(ring.crc, crc::update(0, &ring.snapshot_bytes()));
+    }
+
+    #[test]
+    fn one_line_summary_has_the_documented_format() {
+        let fb = CodexFeedback::with_capacity(16);
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"one\ntwo\nthree\nfour\nfi")
+            .expect("write");
+
+        let snapshot = fb.snapshot(None);
+        assert_eq!(
+            snapshot.one_line_summary(),
+            format!(
+                "feedback thread={} bytes=16 lines=4 truncated=true seq=1",
+                snapshot.thread_id
+            )
+        );
+        assert_eq!(
+            CodexLogSnapshot::new(b"a\n".to_vec(), "t-1".to_string()).one_line_summary(),
+            "feedback thread=t-1 bytes=2 lines=1 truncated=false seq=0"
+        );
+    }
+
+    #[test]
+    fn truncated_counts_only_eviction() {
+        let fb = CodexFeedback::with_capacity(16);
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"one\ntwo\n").unwrap();
+        fb.drain_snapshot(None);
+        w.write_all(b"three\nfour\n").unwrap();
+        let snapshot = fb.snapshot(None);
+        assert!(snapshot.start_offset > 0);
+        assert!(!snapshot.truncated);
+        assert!(!snapshot.summary().truncated);
+
+        fb.clear_keeping_tail(1);
+        assert!(!fb.snapshot(None).truncated);
+
+        w.write_all(b"five\nsix\nseven\n").unwrap();
+        let evicted = fb.snapshot(None);
+        assert!(evicted.truncated);
+        assert!(evicted.one_line_summary().contains("truncated=true"));
+        assert!(fb.snapshot_whole_lines().truncated);
+
+        // Draining after an eviction starts over.
+        assert!(fb.drain_snapshot(None).truncated);
+        w.write_all(b"eight\n").unwrap();
+        assert!(!fb.snapshot(None).truncated);
+    }
+
+    #[test]
+    fn snapshot_allowlisted_reveals_only_safe_lines() {
+        let fb = CodexFeedback::new();
+        fb.make_writer()
+            .make_writer()
+            .write_all(
+                b"INFO user alice opened /home/alice/secret.txt\r\nERROR E1042 sandbox denied\nWARN retrying with token abc\nERROR E2001 timeout",
+            )
+            .expect("write");
+
+        let patterns = [Regex::new(r"^ERROR E\d{4}\b").unwrap()];
+        assert_eq!(
+            String::from_utf8_lossy(fb.snapshot_allowlisted(None, &patterns).as_bytes()),
+            "[hidden]\r\nERROR E1042 sandbox denied\n[hidden]\nERROR E2001 timeout"
+        );
+        assert_eq!(
+            fb.snapshot_allowlisted(None, &[]).lines(),
+            vec!["[hidden]"; 4]
+        );
+    }
+
+    #[test]
+    fn enumerate_lines_pairs_lines_with_their_offsets() {
+        let snapshot = CodexLogSnapshot::new(
+            b"first\r\n\nthird caf\xc3\xa9\nbad \xff bytes\nlast".to_vec(),
+            "t".to_string(),
+        );
+        let lines: Vec<(usize, &str)> = snapshot.enumerate_lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                (0, "first"),
+                (7, ""),
+                (8, "third café"),
+                (20, "bad "),
+                (32, "last"),
+            ]
+        );
+        assert!(lines.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        for &(offset, text) in &lines {
+            assert!(offset == 0 || snapshot.as_bytes()[offset - 1] == b'\n');
+            assert!(snapshot.as_bytes()[offset..].starts_with(text.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn throttled_saves_write_once_per_window() {
+        use crate::clock::MockClock;
+
+        let dir = tempfile::tempdir().expect("tempdir");
+        let clock = Arc::new(MockClock::new());
+        let fb = CodexFeedback::with_capacity(1024).with_clock(clock.clone());
+        let interval = Duration::from_secs(2);
+        let write = |text: &str| {
+            fb.make_writer()
+                .make_writer()
+                .write_all(text.as_bytes())
+                .expect("write");
+        };
+
+        write("first\n");
+        let path = fb
+            .save_to_dir_throttled(None, dir.path(), interval)
+            .expect("save");
+        for _ in 0..5 {
+            write("repeat\n");
+            clock.advance(Duration::from_millis(300));
+            let again = fb
+                .save_to_dir_throttled(None, dir.path(), interval)
+                .expect("save");
+            assert_eq!(again, path);
+        }
+        assert_eq!(fs::read(&path).expect("read"), b"first\n");
+        assert_eq!(fs::read_dir(dir.path()).expect("read_dir").count(), 1);
+
+        clock.advance(Duration::from_millis(500));
+        let next = fb
+            .save_to_dir_throttled(None, dir.path(), interval)
+            .expect("save");
+        assert_eq!(fs::read_to_string(&next).expect("read").lines().count(), 6);
+    }
+
+    #[test]
+    fn throttled_saves_are_tracked_per_session() {
+        use crate::clock::MockClock;
+
+        let dir = tempfile::tempdir().expect("tempdir");
+        let clock = Arc::new(MockClock::new());
+        let fb = CodexFeedback::with_capacity(1024).with_clock(clock.clone());
+        let interval = Duration::from_secs(2);
+        let (a, b) = (ConversationId::new(), ConversationId::new());
+        for (id, text) in [(a, "from a\n"), (b, "from b\n")] {
+            fb.make_writer_for(id)
+                .make_writer()
+                .write_all(text.as_bytes())
+                .expect("write");
+        }
+
+        let path_a = fb
+            .save_to_dir_throttled(Some(a), dir.path(), interval)
+            .expect("save");
+        clock.advance(Duration::from_millis(300));
+        let path_b = fb
+            .save_to_dir_throttled(Some(b), dir.path(), interval)
+            .expect("save");
+        assert_ne!(path_a, path_b);
+        assert_eq!(fs::read(&path_a).expect("read"), b"from a\n");
+        assert_eq!(fs::read(&path_b).expect("read"), b"from b\n");
+
+        // Each session is still throttled on its own.
+        clock.advance(Duration::from_millis(300));
+        for (id, path) in [(a, &path_a), (b, &path_b)] {
+            let again = fb
+                .save_to_dir_throttled(Some(id), dir.path(), interval)
+                .expect("save");
+            assert_eq!(&again, path);
+        }
+        assert_eq!(fs::read_dir(dir.path()).expect("read_dir").count(), 2);
+    }
+
+    #[test]
+    fn capture_returns_only_writes_made_during_the_closure() {
+        let fb = CodexFeedback::with_capacity(1024);
+        let write = |text: &str| {
+            fb.make_writer()
+                .make_writer()
+                .write_all(text.as_bytes())
+                .expect("write");
+        };
+        write("before\n");
+
+        let (answer, snapshot) = fb.capture(|| {
+            write("during 1\n");
+            std::thread::scope(|scope| {
+                scope.spawn(|| write("from another thread\n"));
+            });
+            write("during 2\n");
+            42
+        });
+        write("after\n");
+
+        assert_eq!(answer, 42);
+        assert_eq!(
+            String::from_utf8_lossy(snapshot.as_bytes()),
+            "during 1\nfrom another thread\nduring 2\n"
+        );
+        assert_eq!((snapshot.start_offset, snapshot.end_offset), (7, 45));
+        assert_eq!(fb.capture(|| ()).1.as_bytes(), b"");
+    }
+
+    #[test]
+    fn thread_id_mapper_changes_only_file_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let fb = CodexFeedback::new().with_thread_id_mapper(|id| {
+            let digest = format!("{:x}", Sha256::digest(id.as_bytes()));
+            digest[..12].to_string()
+        });
+        let conversation = ConversationId::new();
+        fb.make_writer_for(conversation)
+            .make_writer()
+            .write_all(b"hello\n")
+            .expect("write");
+
+        let snapshot = fb.snapshot(Some(conversation));
+        let path = snapshot.save_to_dir(dir.path()).unwrap();
+
+        let raw = conversation.to_string();
+        let hashed = &format!("{:x}", Sha256::digest(raw.as_bytes()))[..12];
+        assert_eq!(snapshot.thread_id, raw);
+        assert_eq!(
+            path,
+            dir.path().join(format!("codex-feedback-{hashed}.log"))
+        );
+        assert!(
+            snapshot
+                .to_mime_part()
+                .contains(&format!("codex-feedback-{hashed}.log"))
+        );
+        assert!(!path.to_string_lossy().contains(&raw));
+    }
+
+    #[test]
+    fn file_names_stay_inside_the_target_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let fb = CodexFeedback::new().with_thread_id_mapper(|_| "../x".to_string());
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"hello\n")
+            .unwrap();
+
+        let path = fb.snapshot(None).save_to_dir(dir.path()).unwrap();
+        assert_eq!(path, dir.path().join("codex-feedback-.._x.log"));
+        assert_eq!(fs::read(&path).unwrap(), b"hello\n");
+
+        assert_eq!(
+            log_file_name("a/b\\c d\u{e9}"),
+            "codex-feedback-a_b_c_d_.log"
+        );
+        assert_eq!(log_file_name(".."), "codex-feedback-__.log");
+        assert_eq!(
+            log_file_name("thread-1.v2_x"),
+            "codex-feedback-thread-1.v2_x.log"
+        );
+    }
+
+    #[test]
+    fn long_thread_ids_are_shortened_in_file_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let id = "x".repeat(1000);
+        let save = || {
+            CodexLogSnapshot::new(b"log\n".to_vec(), id.clone())
+                .save_to_dir(dir.path())
+                .unwrap()
+        };
+        let path = save();
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        assert!(name.len() <= 255, "{name}");
+        assert!(
+            name.starts_with(&format!("codex-feedback-{}-", "x".repeat(32))),
+            "{name}"
+        );
+        assert_eq!(fs::read(&path).unwrap(), b"log\n");
+        assert_eq!(save(), path);
+
+        let other = CodexLogSnapshot::new(Vec::new(), format!("{id}y"))
+            .save_to_dir(dir.path())
+            .unwrap();
+        assert_ne!(other, path);
+    }
+
+    #[test]
+    fn since_last_error_starts_at_the_final_error_line() {
+        let fb = CodexFeedback::with_capacity(1024);
+        let mut writer = fb.make_writer().make_writer();
+        writer
+            .write_all(
+                b"2025-01-02T03:04:05Z  INFO start\n\
+                  2025-01-02T03:04:06Z ERROR first failure\n\
+                  2025-01-02T03:04:07Z  INFO retrying\n\
+                  2025-01-02T03:04:08Z ERROR second failure\n\
+                  \x20 caused by: timeout\n\
+                  2025-01-02T03:04:09Z  WARN message mentions ERROR later\n",
+            )
+            .unwrap();
+
+        let full = fb.snapshot(None);
+        let snap = fb.snapshot_since_last_error(None);
+        assert_eq!(
+            String::from_utf8_lossy(snap.as_bytes()),
+            "2025-01-02T03:04:08Z ERROR second failure\n\
+             \x20 caused by: timeout\n\
+             2025-01-02T03:04:09Z  WARN message mentions ERROR later\n"
+        );
+        assert_eq!(
+            snap.start_offset,
+            full.end_offset - snap.as_bytes().len() as u64
+        );
+        let last_error = String::from_utf8_lossy(full.as_bytes())
+            .lines()
+            .rfind(|line| line_level(line) == Some(Level::ERROR))
+            .map(str::to_string);
+        assert_eq!(
+            String::from_utf8_lossy(snap.as_bytes()).lines().next(),
+            last_error.as_deref()
+        );
+
+        let fb = CodexFeedback::with_capacity(64);
+        let mut writer = fb.make_writer().make_writer();
+        writer.write_all(b" INFO all good\n").unwrap();
+        assert_eq!(
+            fb.snapshot_since_last_error(None).as_bytes(),
+            b" INFO all good\n"
+        );
+    }
+
+    #[test]
+    fn snapshot_between_markers_takes_the_last_region() {
+        let fb = CodexFeedback::with_capacity(1024);
+        let mut writer = fb.make_writer().make_writer();
+        writer
+            .write_all(
+                b"setup\n\
+                  BEGIN op 1\n\
+                  work a\n\
+                  END op 1\n\
+                  BEGIN op 2\n\
+                  work b\n\
+                  BEGIN op 3\n\
+                  work c\n\
+                  END op 3\n\
+                  END op 2\n\
+                  trailing\n",
+            )
+            .unwrap();
+
+        let region = fb.snapshot_between_markers(None, "BEGIN", "END").unwrap();
+        assert_eq!(region.as_bytes(), b"BEGIN op 3\nwork c\nEND op 3\n");
+        assert_eq!((region.start_offset, region.end_offset), (51, 78));
+        // Markers are plain substrings, so a narrower `begin` picks an earlier one.
+        assert_eq!(
+            fb.snapshot_between_markers(None, "BEGIN op 1", "END")
+                .unwrap()
+                .as_bytes(),
+            b"BEGIN op 1\nwork a\nEND op 1\n"
+        );
+
+        writer.write_all(b"BEGIN op 4\nstill going").unwrap();
+        assert_eq!(
+            fb.snapshot_between_markers(None, "BEGIN", "END")
+                .unwrap()
+                .as_bytes(),
+            b"BEGIN op 4\nstill going"
+        );
+        assert!(
+            fb.snapshot_between_markers(None, "never logged", "END")
+                .is_none()
+        );
+
+        // Offsets count raw bytes, even around invalid UTF-8.
+        let fb = CodexFeedback::with_capacity(1024);
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"bad \xff\xfe\nBEGIN x \xff\nEND x\nafter\n")
+            .unwrap();
+        let region = fb.snapshot_between_markers(None, "BEGIN", "END").unwrap();
+        assert_eq!((region.start_offset, region.end_offset), (7, 23));
+    }
+
+    #[test]
+    fn flush_propagates_to_the_tee_sink() {
+        #[derive(Clone, Default)]
+        struct RecordingSink {
+            written: Arc<Mutex<Vec<u8>>>,
+            flushes: Arc<std::sync::atomic::AtomicUsize>,
+        }
+
+        impl Write for RecordingSink {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.written.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                self.flushes.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        }
+
+        let sink = RecordingSink::default();
+        let fb = CodexFeedback::with_capacity(64).with_tee(sink.clone());
+        let mut writer = fb.make_writer().make_writer();
+        writer.write_all(b"mirrored\n").unwrap();
+        assert_eq!(sink.flushes.load(Ordering::SeqCst), 0);
+        writer.flush().unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(sink.flushes.load(Ordering::SeqCst), 2);
+        assert_eq!(*sink.written.lock().unwrap(), b"mirrored\n");
+        assert_eq!(fb.snapshot(None).as_bytes(), b"mirrored\n");
+
+        fb.shutdown();
+        writer.write_all(b"after shutdown\n").unwrap();
+        assert_eq!(*sink.written.lock().unwrap(), b"mirrored\n");
+    }
+
+    #[test]
+    fn failing_tee_is_dropped_without_losing_writes() {
+        #[derive(Clone, Default)]
+        struct ClosedPipe {
+            attempts: Arc<std::sync::atomic::AtomicUsize>,
+        }
+
+        impl Write for ClosedPipe {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                self.attempts.fetch_add(1, Ordering::SeqCst);
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let sink = ClosedPipe::default();
+        let fb = CodexFeedback::with_capacity(64).with_tee(sink.clone());
+        let mut writer = fb.make_writer().make_writer();
+        writer.write_all(b"first\n").unwrap();
+        writer.write_all(b"second\n").unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(fb.snapshot(None).as_bytes(), b"first\nsecond\n");
+        assert_eq!(sink.attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn budgeted_snapshot_caps_each_level_keeping_the_newest() {
+        let fb = CodexFeedback::with_capacity(4096);
+        let mut writer = fb.make_writer().make_writer();
+        writer
+            .write_all(
+                b"T ERROR e1\n\
+                  T DEBUG d1\n\
+                  T  INFO i1\n\
+                  T DEBUG d2\n\
+                  \x20 detail\n\
+                  T ERROR e2\n\
+                  T DEBUG d3\n\
+                  T  INFO i2\n",
+            )
+            .unwrap();
+
+        // Each line is 11 bytes; "  detail\n" (9 bytes) counts as DEBUG.
+        let budgets = BTreeMap::from([(Level::DEBUG, 25), (Level::INFO, 11)]);
+        let snap = fb.snapshot_budgeted(None, &budgets);
+        assert_eq!(
+            String::from_utf8_lossy(snap.as_bytes()),
+            "T ERROR e1\n\
+             \x20 detail\n\
+             T ERROR e2\n\
+             T DEBUG d3\n\
+             T  INFO i2\n"
+        );
+        assert_eq!(
+            fb.snapshot_budgeted(None, &BTreeMap::new()).as_bytes(),
+            fb.snapshot(None).as_bytes()
+        );
+    }
+
+    #[test]
+    fn summary_describes_the_snapshot_for_error_context() {
+        use anyhow::Context;
+
+        let fb = CodexFeedback::with_capacity(8);
+        let mut writer = fb.make_writer().make_writer();
+        writer.write_all(b"0123").unwrap();
+        let mut snapshot = fb.snapshot(None);
+        snapshot.thread_id = "t-1".to_string();
+        assert_eq!(
+            snapshot.summary().to_string(),
+            "feedback snapshot #1 for thread t-1 (4 bytes)"
+        );
+
+        writer.write_all(b"456789").unwrap();
+        let mut snapshot = fb.snapshot(None);
+        snapshot.thread_id = "t-1".to_string();
+        let err = Err::<(), _>(io::Error::other("disk full"))
+            .with_context(|| snapshot.summary())
+            .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "feedback snapshot #2 for thread t-1 (8 bytes, truncated): disk full"
+        );
+    }
+
+    #[test]
+    fn max_conversations_evicts_the_least_recently_written() {
+        let fb = CodexFeedback::with_capacity(64).with_max_conversations(2);
+        let ids: Vec<ConversationId> = (0..4).map(|_| ConversationId::new()).collect();
+        let write = |id: ConversationId| {
+            fb.make_writer_for(id)
+                .make_writer()
+                .write_all(b"line\n")
+                .unwrap();
+        };
+
+        write(ids[0]);
+        write(ids[1]);
+        write(ids[0]);
+        write(ids[2]);
+        assert_eq!(fb.conversation_count(), 2);
+        assert!(fb.inner.conversations.read().unwrap().contains_key(&ids[0]));
+        assert!(!fb.inner.conversations.read().unwrap().contains_key(&ids[1]));
+
+        write(ids[3]);
+        let conversations = fb.inner.conversations.read().unwrap();
+        assert_eq!(
+            conversations
+                .keys()
+                .copied()
+                .collect::<std::collections::BTreeSet<_>>(),
+            [ids[2], ids[3]].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn write_to_treats_broken_pipe_as_success() {
+        struct ClosedPipe;
+
+        impl Write for ClosedPipe {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let snapshot = CodexLogSnapshot::new(b"dump me\n".to_vec(), "t".to_string());
+        let mut out = Vec::new();
+        snapshot.write_to(&mut out).unwrap();
+        assert_eq!(out, b"dump me\n");
+        snapshot.write_to(ClosedPipe).unwrap();
+
+        let mut full = std::io::Cursor::new([0u8; 2]);
+        assert_eq!(
+            snapshot.write_to(&mut full).unwrap_err().kind(),
+            io::ErrorKind::WriteZero
+        );
+    }
+
+    #[test]
+    fn bytes_threshold_fires_once_per_threshold_of_new_bytes() {
+        let fb = CodexFeedback::with_capacity(1024);
+        let mut writer = fb.make_writer().make_writer();
+        writer.write_all(b"before registering\n").unwrap();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        fb.on_bytes_threshold(10, {
+            let seen = seen.clone();
+            move |snapshot: &CodexLogSnapshot| {
+                seen.lock().unwrap().push(snapshot.end_offset);
+            }
+        });
+        for chunk in [&b"1234"[..], b"5678", b"90", b"abcdefghijkl", b"m"] {
+            writer.write_all(chunk).unwrap();
+        }
+        // 19 bytes already written; fires at 29 (after "90") and then once the
+        // next 10 bytes are in (at 41, after the long chunk), but not at 42.
+        assert_eq!(*seen.lock().unwrap(), vec![29, 41]);
+    }
+
+    #[test]
+    fn incremental_crc_matches_a_fresh_checksum() {
+        let fb = CodexFeedback::with_capacity(32);
+        let fresh = |fb: &CodexFeedback| {
+            fb.with_slices(|front, back| crc::update(crc::update(0, front), back))
+        };
+        let mut writer = fb.make_writer().make_writer();
+
+        writer.write_all(b"first line\n").unwrap();
+        assert_eq!(fb.crc32(), crc::update(0, b"first line\n"));
+        writer.write_all(b"second line, evicting\n").unwrap();
+        assert_eq!(fb.crc32(), fresh(&fb));
+        writer.write_all(&[b'x'; 100]).unwrap();
+        assert_eq!(fb.crc32(), fresh(&fb));
+        writer.write_all(b"\nthird\npartial").unwrap();
+        fb.drain_lines();
+        assert_eq!(fb.crc32(), crc::update(0, b"partial"));
+        let fb = fb.with_line_capacity(1);
+        fb.ingest("blob", b"a\nb\n");
+        assert_eq!(fb.crc32(), fresh(&fb));
+        fb.drain_snapshot(None);
+        assert_eq!(fb.crc32(), 0);
+    }
+
+    #[test]
+    fn anonymous_snapshots_carry_no_ids() {
+        let fb = CodexFeedback::with_capacity(1024);
+        let id = ConversationId::new();
+        let mut writer = fb.make_writer_for(id).make_writer();
+        writer
+            .write_all(format!("turn started for {id}\n").as_bytes())
+            .unwrap();
+
+        let snapshot = fb.snapshot_anonymous(Some(id));
+        assert_eq!(snapshot.thread_id, ANONYMOUS_THREAD_ID);
+        assert_eq!(snapshot.as_bytes(), b"turn started for <id>\n");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = snapshot.save_to_dir(dir.path()).unwrap();
+        let json = snapshot.to_json().to_string();
+        for output in [
+            path.display().to_string(),
+            json,
+            String::from_utf8(fs::read(&path).unwrap()).unwrap(),
+        ] {
+            assert!(uuid_regex().find(&output).is_none(), "{output}");
+        }
+        assert_eq!(path.file_name().unwrap(), "codex-feedback-anon.log");
+    }
+
+    #[test]
+    fn oversized_ingest_is_truncated_and_keeps_history() {
+        let fb = CodexFeedback::with_capacity(200);
+        let mut writer = fb.make_writer().make_writer();
+        writer.write_all(b"earlier log line\n").unwrap();
+
+        let blob: Vec<u8> = (0..500).map(|i| b'a' + (i % 26) as u8).collect();
+        fb.ingest("stderr", &blob);
+
+        let text = String::from_utf8(fb.snapshot(None).as_bytes().to_vec()).unwrap();
+        let tail = String::from_utf8(blob[400..].to_vec()).unwrap();
+        assert_eq!(
+            text,
+            format!(
+                "earlier log line\n--- begin stderr ---\n[... 400 bytes truncated ...]\n{tail}\n--- end stderr ---\n"
+            )
+        );
+
+        let fb = CodexFeedback::with_capacity(200).with_ingest_cap(4);
+        fb.ingest("small", b"abcdefgh");
+        assert_eq!(
+            fb.snapshot(None).as_bytes(),
+            b"--- begin small ---\n[... 4 bytes truncated ...]\nefgh\n--- end small ---\n"
+        );
+    }
+
+    #[test]
+    fn shutdown_merges_staged_writes_and_drops_later_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let fb = CodexFeedback::with_capacity(4096).with_double_buffering();
+        fb.enable_autosave(dir.path(), Duration::from_secs(3600));
+        let mut writer = fb.make_writer().make_writer();
+        for i in 0..10 {
+            writer.write_all(format!("line {i}\n").as_bytes()).unwrap();
+        }
+        assert!(!fb.inner.staged.lock().unwrap().is_empty());
+
+        fb.shutdown();
+        assert!(fb.inner.staged.lock().unwrap().is_empty());
+        assert!(fb.inner.autosave.lock().unwrap().is_none());
+        writer.write_all(b"too late\n").unwrap();
+
+        let expected: String = (0..10).map(|i| format!("line {i}\n")).collect();
+        assert_eq!(fb.snapshot(None).as_bytes(), expected.as_bytes());
+    }
+
+    #[test]
+    fn double_buffered_snapshots_never_see_torn_records() {
+        const RECORDS: usize = 20_000;
+        let fb = CodexFeedback::with_capacity(4096).with_double_buffering();
+        let done = Arc::new(AtomicBool::new(false));
+
+        let writer = std::thread::spawn({
+            let fb = fb.clone();
+            let done = done.clone();
+            move || {
+                let mut writer = fb.make_writer().make_writer();
+                for i in 0..RECORDS {
+                    writer
+                        .write_all(format!("record {i:08}\n").as_bytes())
+                        .unwrap();
+                }
+                done.store(true, Ordering::SeqCst);
+            }
+        });
+
+        let mut snapshots = 0;
+        let mut last_seen = None;
+        while !done.load(Ordering::SeqCst) || snapshots == 0 {
+            let snapshot = fb.snapshot(None);
+            let text = std::str::from_utf8(snapshot.as_bytes()).unwrap();
+            // Only the oldest line may have lost its start to eviction.
+            let mut previous = None;
+            for line in text.split_inclusive('\n').skip(1) {
+                let n: usize = line
+                    .strip_prefix("record ")
+                    .and_then(|rest| rest.strip_suffix('\n'))
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or_else(|| panic!("torn record {line:?}"));
+                if let Some(previous) = previous {
+                    assert_eq!(n, previous + 1, "records out of order");
+                }
+                previous = Some(n);
+            }
+            if let Some(previous) = previous {
+                assert!(last_seen.is_none_or(|last| previous >= last));
+                last_seen = Some(previous);
+            }
+            snapshots += 1;
+        }
+        writer.join().unwrap();
+
+        let text = String::from_utf8(fb.snapshot(None).as_bytes().to_vec()).unwrap();
+        assert!(
+            text.ends_with(&format!("record {:08}\n", RECORDS - 1)),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn write_rate_limit_drops_storms_and_records_one_marker() {
+        use crate::clock::MockClock;
+
+        let clock = Arc::new(MockClock::new());
+        let fb = CodexFeedback::with_capacity(1024)
+            .with_clock(clock.clone())
+            .with_write_rate_limit(20);
+        let mut writer = fb.make_writer().make_writer();
+
+        writer.write_all(b"ok 1\n").unwrap();
+        for _ in 0..10 {
+            writer.write_all(b"storm!\n").unwrap();
+        }
+        clock.advance(Duration::from_millis(500));
+        writer.write_all(b"storm!\n").unwrap();
+        clock.advance(Duration::from_millis(600));
+        writer.write_all(b"ok 2\n").unwrap();
+
+        // 5 + 2 * 7 bytes fit the first window; the other nine writes were dropped.
+        assert_eq!(
+            String::from_utf8_lossy(fb.snapshot(None).as_bytes()),
+            "ok 1\nstorm!\nstorm!\n[rate limited: dropped 63 bytes]\nok 2\n"
+        );
+    }
+
+    #[test]
+    fn markers_lists_every_elision_line() {
+        use crate::clock::MockClock;
+
+        let clock = Arc::new(MockClock::new());
+        let fb = CodexFeedback::with_capacity(1024)
+            .with_clock(clock.clone())
+            .with_write_rate_limit(24)
+            .with_ingest_cap(4)
+            .with_redaction_limit(1);
+        let mut writer = fb.make_writer().make_writer();
+        writer.write_all(b"password=a\n").unwrap();
+        writer.write_all(b"password=b\n").unwrap();
+        writer.write_all(b"dropped\n").unwrap();
+        clock.advance(Duration::from_secs(1));
+        writer.write_all(b"[not a marker]\n").unwrap();
+        fb.ingest("stderr", b"0123456789");
+
+        let snapshot = fb.snapshot(None);
+        assert_eq!(
+            snapshot.lines(),
+            [
+                "password=[REDACTED]",
+                "password=b",
+                "[rate limited: dropped 8 bytes]",
+                "[not a marker]",
+                "--- begin stderr ---",
+                "[... 6 bytes truncated ...]",
+                "6789",
+                "--- end stderr ---",
+                "[redaction limit reached]",
+            ]
+        );
+        assert_eq!(
+            snapshot.markers(),
+            [
+                (2, "[rate limited: dropped 8 bytes]".to_string()),
+                (5, "[... 6 bytes truncated ...]".to_string()),
+                (8, "[redaction limit reached]".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn added_lines_since_returns_the_appended_suffix() {
+        let fb = CodexFeedback::with_capacity(32);
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"ok\nok\nstep one\nste").unwrap();
+        let baseline = fb.snapshot(None);
+
+        w.write_all(b"p two\nok\n").unwrap();
+        assert_eq!(
+            fb.snapshot(None).added_lines_since(&baseline),
+            ["step two", "ok"]
+        );
+        assert_eq!(
+            fb.snapshot(None)
+                .added_lines_since(&CodexLogSnapshot::new(Vec::new(), "t".into())),
+            ["ok", "ok", "step one", "step two", "ok"]
+        );
+
+        // Evicting part of the baseline still finds the overlap.
+        w.write_all(b"step three!!\n").unwrap();
+        let later = fb.snapshot(None);
+        assert_eq!(later.lines()[0], "ep one");
+        assert_eq!(
+            later.added_lines_since(&baseline),
+            ["step two", "ok", "step three!!"]
+        );
+    }
+
+    #[test]
+    fn added_lines_since_notes_an_evicted_baseline() {
+        let fb = CodexFeedback::with_capacity(16);
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"old line\n").unwrap();
+        let baseline = fb.snapshot(None);
+
+        w.write_all(b"new one\nnew two\n").unwrap();
+        assert_eq!(
+            fb.snapshot(None).added_lines_since(&baseline),
+            [
+                "[baseline not found; showing all lines]",
+                "new one",
+                "new two"
+            ]
+        );
+    }
+
+    #[test]
+    fn snapshots_convert_into_bytes_and_strings() {
+        let snapshot = || CodexLogSnapshot::new(b"caf\xc3\xa9\n".to_vec(), "t".into());
+        assert_eq!(Vec::<u8>::from(snapshot()), b"caf\xc3\xa9\n");
+        assert_eq!(String::try_from(snapshot()).unwrap(), "café\n");
+
+        let invalid = CodexLogSnapshot::new(b"bad \xff\n".to_vec(), "t".into());
+        let err = String::try_from(invalid).unwrap_err();
+        assert_eq!(err.utf8_error().valid_up_to(), 4);
+        assert_eq!(err.into_bytes(), b"bad \xff\n");
+    }
+
+    #[test]
+    fn dominant_level_counts_lines_and_breaks_ties_by_severity() {
+        let snapshot = |text: &str| CodexLogSnapshot::new(text.as_bytes().to_vec(), "t".into());
+
+        let mostly_info = snapshot(
+            "2025-01-02T03:04:05Z  INFO a\n\
+             2025-01-02T03:04:06Z  INFO b\n\
+             2025-01-02T03:04:07Z ERROR c\n\
+             \x20 caused by: timeout\n\
+             2025-01-02T03:04:08Z  INFO d\n",
+        );
+        assert_eq!(mostly_info.dominant_level(), Some(Level::INFO));
+
+        let tied = snapshot("DEBUG a\nWARN b\nDEBUG c\nWARN d\nINFO e\n");
+        assert_eq!(tied.dominant_level(), Some(Level::WARN));
+
+        assert_eq!(snapshot("plain text\nno levels\n").dominant_level(), None);
+    }
+
+    #[test]
+    fn mime_part_round_trips_through_base64() {
+        let snapshot = CodexLogSnapshot::new(
+            "a log line long enough to need more than one base64 line of output\n"
+                .repeat(3)
+                .into_bytes(),
+            "thread-1".to_string(),
+        );
+
+        let part = snapshot.to_mime_part();
+        let (headers, body) = part.split_once("\r\n\r\n").expect("header separator");
+
+        assert_eq!(
+            headers.split("\r\n").collect::<Vec<_>>(),
+            vec![
+                "Content-Type: text/plain; charset=utf-8",
+                "Content-Disposition: attachment; filename=\"codex-feedback-thread-1.log\"",
+                "Content-Transfer-Encoding: base64",
+            ]
+        );
+        assert!(body.split("\r\n").all(|line| line.len() <= 76));
+        let decoded = STANDARD
+            .decode(body.replace("\r\n", ""))
+            .expect("valid base64");
+        assert_eq!(decoded, snapshot.as_bytes());
+    }
+
+    #[test]
+    fn save_if_nonempty_skips_empty_snapshots() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let fb = CodexFeedback::new();
+
+        let empty = fb.snapshot(None);
+        assert_eq!(
+            empty.save_to_dir_if_nonempty(dir.path()).expect("save"),
+            None
+        );
+        assert!(!dir.path().join(log_file_name(&empty.thread_id)).exists());
+
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"hello\n")
+            .expect("write");
+        let snapshot = fb.snapshot(None);
+        let path = snapshot
+            .save_to_dir_if_nonempty(dir.path())
+            .expect("save")
+            .expect("file written");
+        assert_eq!(fs::read(path).expect("read"), b"hello\n");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn in_memory_compression_keeps_history_beyond_capacity() {
+        let fb = CodexFeedback::with_capacity(4096).with_in_memory_gzip();
+        let plain = CodexFeedback::with_capacity(4096);
+        let text: String = (0..200)
+            .map(|i| {
+                format!(
+                    "INFO codex_core::client: request {i:03} completed status=200 elapsed_ms={}\n",
+                    i * 37 % 1000
+                )
+            })
+            .collect();
+        for line in text.split_inclusive('\n') {
+            for target in [&fb, &plain] {
+                target
+                    .make_writer()
+                    .make_writer()
+                    .write_all(line.as_bytes())
+                    .expect("write");
+            }
+        }
+
+        let snapshot = fb.snapshot(None);
+        assert_eq!(String::from_utf8_lossy(snapshot.as_bytes()), text);
+        assert_eq!(snapshot.start_offset, 0);
+        assert!(plain.snapshot(None).as_bytes().len() <= 4096);
+        assert!(
+            fb.memory_footprint() < text.len(),
+            "{} resident bytes for {} bytes of history",
+            fb.memory_footprint(),
+            text.len()
+        );
+
+        // Draining discards the history along with the buffer.
+        fb.drain_snapshot(None);
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"fresh\n")
+            .expect("write");
+        assert_eq!(fb.snapshot(None).as_bytes(), b"fresh\n");
+    }
+
+    #[test]
+    fn snapshot_grouped_collapses_level_runs() {
+        let fb = CodexFeedback::new();
+        fb.make_writer()
+            .make_writer()
+            .write_all(
+                b"banner\n\
+                  INFO start\n\
+                  INFO loading\n\
+                  INFO ready\n\
+                  ERROR boom\n\
+                  \x20 caused by: timeout\n\
+                  INFO retrying\n\
+                  WARN slow\n\
+                  WARN slower\n",
+            )
+            .expect("write");
+
+        assert_eq!(
+            String::from_utf8_lossy(fb.snapshot_grouped(None).as_bytes()),
+            "[- x1] banner\n\
+             [INFO x3] INFO start\n\
+             [ERROR x2] ERROR boom\n\
+             [INFO x1] INFO retrying\n\
+             [WARN x2] WARN slow\n"
+        );
+    }
+
+    #[test]
+    fn snapshot_deduped_drops_scattered_duplicates() {
+        let fb = CodexFeedback::new();
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"warn: a\nb\nwarn: a\nc\nb\nwarn: a\nd")
+            .expect("write");
+
+        let snapshot = fb.snapshot_deduped(None);
+
+        assert_eq!(snapshot.as_bytes(), b"warn: a\nb\nc\nd");
+        assert_eq!(
+            fb.snapshot(None).as_bytes(),
+            b"warn: a\nb\nwarn: a\nc\nb\nwarn: a\nd"
+        );
+    }
+
+    #[test]
+    fn snapshot_scrubbed_applies_the_callback_then_redaction() {
+        let fb = CodexFeedback::new();
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"connect build-7.corp.internal password=hunter2\n")
+            .expect("write");
+
+        let snapshot = fb.snapshot_scrubbed(None, |text| {
+            text.replace("build-7.corp.internal", "<internal-host>")
+        });
+
+        assert_eq!(
+            snapshot.as_bytes(),
+            b"connect <internal-host> password=[REDACTED]\n"
+        );
+        assert_eq!(
+            fb.snapshot(None).as_bytes(),
+            b"connect build-7.corp.internal password=[REDACTED]\n"
+        );
+    }
+
+    #[test]
+    fn rotate_on_marker_returns_the_previous_segment() {
+        let fb = CodexFeedback::new();
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"old 1\nold 2\n").expect("write");
+        assert!(fb.rotate_on_marker("=== conversation").is_none());
+
+        w.write_all(b"=== conversation 2 ===\nnew 1\n")
+            .expect("write");
+        let rotated = fb.rotate_on_marker("=== conversation").expect("rotated");
+
+        assert_eq!(rotated.as_bytes(), b"old 1\nold 2\n");
+        assert_eq!(
+            fb.snapshot(None).as_bytes(),
+            b"=== conversation 2 ===\nnew 1\n"
+        );
+        assert!(fb.rotate_on_marker("=== conversation").is_none());
+    }
+
+    #[test]
+    fn count_matches_spans_the_wrap_around() {
+        let fb = CodexFeedback::with_capacity(32);
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"WARN evicted\nINFO one\n").unwrap();
+        w.write_all(b"WARN two\nERROR three\nWARN four").unwrap();
+        let (_, back) = fb.with_slices(|front, back| (front.to_vec(), back.to_vec()));
+        assert!(!back.is_empty(), "expected the ring to wrap");
+
+        assert_eq!(fb.count_matches(&Regex::new("^WARN").unwrap()), 2);
+        assert_eq!(fb.count_matches(&Regex::new("^ERROR").unwrap()), 1);
+        assert_eq!(fb.count_matches(&Regex::new("").unwrap()), 4);
+        assert_eq!(
+            fb.count_matches(&Regex::new("^WARN").unwrap()),
+            fb.snapshot(None)
+                .lines()
+                .iter()
+                .filter(|line| line.starts_with("WARN"))
+                .count()
+        );
+    }
+
+    #[test]
+    fn tail_uses_a_line_index_that_survives_eviction() {
+        let fb = CodexFeedback::with_capacity(40);
+        let mut w = fb.make_writer().make_writer();
+        for i in 0..20 {
+            writeln!(w, "line {i}").unwrap();
+        }
+        w.write_all(b"partial").unwrap();
+
+        let snapshot = fb.snapshot(None);
+        assert_eq!(fb.line_count(), snapshot.lines().len());
+        for n in [0, 1, 3, snapshot.lines().len(), 100] {
+            assert_eq!(fb.tail(n), snapshot.tail(n), "tail({n})");
+        }
+        assert_eq!(
+            fb.tail(2),
+            vec!["line 19".to_string(), "partial".to_string()]
+        );
+
+        let ring = fb.inner.ring_read();
+        let start = ring.offsets().start;
+        let expected: Vec<u64> = (start..)
+            .zip(ring.iter())
+            .filter(|&(_, &b)| b == b'\n')
+            .map(|(offset, _)| offset + 1)
+            .collect();
+        assert_eq!(Vec::from(ring.line_starts.clone()), expected);
+    }
+
+    #[test]
+    fn utf16le_input_is_stored_as_utf8() {
+        let fb = CodexFeedback::new().with_input_encoding(InputEncoding::Utf16Le);
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(
+            "C:\\Users\\zoë> dir\r\n"
+                .encode_utf16()
+                .flat_map(u16::to_le_bytes),
+        );
+
+        let mut w = fb.make_writer().make_writer();
+        let (first, second) = bytes.split_at(7);
+        assert_eq!(w.write(first).unwrap(), first.len());
+        assert_eq!(w.write(second).unwrap(), second.len());
+
+        assert_eq!(
+            fb.snapshot(None).as_bytes(),
+            "C:\\Users\\zoë> dir\r\n".as_bytes()
+        );
+    }
+
+    #[test]
+    fn level_threshold_fires_once_when_crossed() {
+        let fb = CodexFeedback::with_capacity(100);
+        let fired = Arc::new(AtomicU64::new(0));
+        fb.on_level_threshold(0.9, {
+            let fired = fired.clone();
+            move || {
+                fired.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        let mut w = fb.make_writer().make_writer();
+
+        w.write_all(&[b'x'; 89]).unwrap();
+        assert_eq!(fired.load(Ordering::Relaxed), 0);
+        w.write_all(b"x").unwrap();
+        assert_eq!(fired.load(Ordering::Relaxed), 1);
+        w.write_all(&[b'x'; 50]).unwrap();
+        assert_eq!(fired.load(Ordering::Relaxed), 1);
+
+        // Clearing re-arms it.
+        w.write_all(b"\n").unwrap();
+        fb.clear_keeping_tail(0);
+        w.write_all(&[b'x'; 89]).unwrap();
+        assert_eq!(fired.load(Ordering::Relaxed), 1);
+        w.write_all(b"x").unwrap();
+        assert_eq!(fired.load(Ordering::Relaxed), 2);
+        let _ = fb.drain_snapshot(None);
+        w.write_all(&[b'x'; 95]).unwrap();
+        assert_eq!(fired.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn named_redaction_rulesets_mask_differently() {
+        let fb = CodexFeedback::new();
+        fb.add_redaction_ruleset("default", vec![Regex::new(r"corp-[0-9]+").unwrap()]);
+        fb.add_redaction_ruleset("pii", vec![Regex::new(r"[a-z]+@example\.com").unwrap()]);
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"ticket corp-1234 from jane@example.com password=hunter2222\n")
+            .unwrap();
+
+        assert_eq!(
+            fb.snapshot(None).as_bytes(),
+            b"ticket [REDACTED] from jane@example.com password=[REDACTED]\n"
+        );
+        assert_eq!(
+            fb.snapshot_redacted_with(None, "pii").as_bytes(),
+            b"ticket corp-1234 from [REDACTED] password=[REDACTED]\n"
+        );
+        assert_eq!(
+            fb.snapshot_redacted_with(None, "unknown").as_bytes(),
+            b"ticket corp-1234 from jane@example.com password=[REDACTED]\n"
+        );
+    }
+
+    #[test]
+    fn error_offsets_point_at_error_lines_across_eviction() {
+        let fb = CodexFeedback::with_capacity(130);
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"2025-01-01T00:00:00Z ERROR evicted soon\n")
+            .unwrap();
+        assert_eq!(fb.error_offsets(), Vec::<usize>::new());
+        let fb = fb.with_error_index();
+        assert_eq!(fb.error_offsets(), vec![0]);
+        for i in 0..3 {
+            writeln!(w, "2025-01-01T00:00:0{i}Z  INFO ok").unwrap();
+            writeln!(w, "ERROR failed {i}").unwrap();
+        }
+        w.write_all(b"ERROR still writ").unwrap();
+
+        let snapshot = fb.snapshot(None);
+        let text = String::from_utf8(snapshot.as_bytes().to_vec()).unwrap();
+        let lines: Vec<&str> = fb
+            .error_offsets()
+            .into_iter()
+            .map(|offset| text[offset..].lines().next().unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "ERROR failed 0",
+                "ERROR failed 1",
+                "ERROR failed 2",
+                "ERROR still writ"
+            ]
+        );
+    }
+
+    #[test]
+    fn error_index_reads_a_level_split_by_the_wrap_around() {
+        let mut ring = RingBuffer::new(16)
+            .with_store(Some(Box::new(CircularVecStore::default())))
+            .with_preallocate(true);
+        ring.set_error_index(true);
+        ring.push_bytes(b"0123456789\n");
+        ring.push_bytes(b"ERROR x\n");
+
+        let (front, back) = ring.buf.as_slices();
+        assert_eq!((front, back), (&b"3456789\nERROR"[..], &b" x\n"[..]));
+        assert_eq!(ring.error_offsets(), vec![8]);
+    }
+
+    #[test]
+    fn on_line_fires_once_per_complete_line() {
+        let fb = CodexFeedback::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        fb.on_line({
+            let seen = seen.clone();
+            move |line| seen.lock().unwrap().push(line.to_string())
+        });
+        let mut w = fb.make_writer().make_writer();
+
+        w.write_all(b"first li").unwrap();
+        assert!(seen.lock().unwrap().is_empty());
+        w.write_all(b"ne\r\nsecond\nthi").unwrap();
+        w.write_all(b"rd\n\npartial").unwrap();
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec!["first line", "second", "third", ""]
+        );
+    }
+
+    #[test]
+    fn clear_keeping_tail_leaves_the_last_lines() {
+        let fb = CodexFeedback::new();
+        let mut w = fb.make_writer().make_writer();
+        for i in 0..100 {
+            writeln!(w, "line {i}").unwrap();
+        }
+
+        fb.clear_keeping_tail(200);
+        assert_eq!(fb.line_count(), 100);
+
+        fb.clear_keeping_tail(3);
+        assert_eq!(fb.snapshot(None).as_bytes(), b"line 97\nline 98\nline 99\n");
+
+        w.write_all(b"partial").unwrap();
+        fb.clear_keeping_tail(1);
+        assert_eq!(fb.snapshot(None).as_bytes(), b"line 99\npartial");
+        fb.clear_keeping_tail(0);
+        assert_eq!(fb.snapshot(None).as_bytes(), b"partial");
+    }
+
+    #[test]
+    fn retain_lines_drops_matching_lines_in_place() {
+        use crate::clock::MockClock;
+        use std::time::Duration;
+
+        let clock = Arc::new(MockClock::new());
+        let fb = CodexFeedback::with_capacity(64)
+            .with_clock(clock.clone())
+            .with_line_timestamps()
+            .with_error_index();
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"hello\n").unwrap();
+        clock.advance(Duration::from_secs(1));
+        w.write_all(b"ACME Corp called\nERROR failed\n").unwrap();
+        clock.advance(Duration::from_secs(1));
+        w.write_all(b"bye ACME\npartial ACME").unwrap();
+
+        assert_eq!(fb.retain_lines(|line| !line.contains("ACME")), 2);
+        assert_eq!(
+            fb.snapshot(None).as_bytes(),
+            b"hello\nERROR failed\npartial ACME"
+        );
+        assert_eq!(
+            String::from_utf8_lossy(fb.snapshot_with_line_times(None).as_bytes()),
+            "[+0.000s] hello\n[+1.000s] ERROR failed\n[+2.000s] partial ACME"
+        );
+        assert_eq!(fb.error_offsets(), [6]);
+        assert_eq!(fb.retain_lines(|_| true), 0);
+
+        // Later writes append after the kept lines and evict from the front as usual.
+        w.write_all(b" done\n").unwrap();
+        w.write_all(&[b'x'; 33]).unwrap();
+        assert_eq!(
+            fb.snapshot(None).as_bytes(),
+            [b"ERROR failed\npartial ACME done\n".as_slice(), &[b'x'; 33]]
+                .concat()
+                .as_slice()
+        );
+        assert_eq!(fb.line_count(), 3);
+    }
+
+    #[test]
+    fn tail_since_returns_only_new_complete_lines() {
+        let fb = CodexFeedback::with_capacity(64);
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"one\ntwo\nthr").unwrap();
+
+        let (lines, cursor) = fb.tail_since(TailCursor::default());
+        assert_eq!(lines, ["one", "two"]);
+        let (lines, cursor) = fb.tail_since(cursor);
+        assert_eq!(lines, Vec::<String>::new());
+
+        w.write_all(b"ee\nfour\n").unwrap();
+        let (lines, cursor) = fb.tail_since(cursor);
+        assert_eq!(lines, ["three", "four"]);
+
+        let from_now = fb.tail_cursor();
+        w.write_all(b"five\n").unwrap();
+        assert_eq!(fb.tail_since(from_now).0, ["five"]);
+        assert_eq!(fb.tail_since(cursor).0, ["five"]);
+    }
+
+    #[test]
+    fn tail_since_reports_evicted_gaps() {
+        let fb = CodexFeedback::with_capacity(16);
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"first\n").unwrap();
+        let (_, cursor) = fb.tail_since(TailCursor::default());
+
+        w.write_all(b"second\nthird\nfourth\n").unwrap();
+        let (lines, cursor) = fb.tail_since(cursor);
+        assert_eq!(
+            lines,
+            ["[... 4 bytes evicted ...]", "nd", "third", "fourth"]
+        );
+        assert_eq!(fb.tail_since(cursor).0, Vec::<String>::new());
+    }
+
+    #[test]
+    fn min_snapshot_interval_caches_recent_snapshots() {
+        use crate::clock::MockClock;
+
+        let clock = Arc::new(MockClock::new());
+        let fb = CodexFeedback::with_capacity(64)
+            .with_clock(clock.clone())
+            .with_min_snapshot_interval(Duration::from_millis(100));
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"one\n").unwrap();
+
+        let first = fb.snapshot(None);
+        w.write_all(b"two\n").unwrap();
+        clock.advance(Duration::from_millis(99));
+        let cached = fb.snapshot(None);
+        assert_eq!(cached.as_bytes(), b"one\n");
+        assert_eq!(cached.sequence, first.sequence);
+
+        let id = ConversationId::new();
+        fb.make_writer_for(id)
+            .make_writer()
+            .write_all(b"conv\n")
+            .unwrap();
+        assert_eq!(fb.snapshot(Some(id)).as_bytes(), b"conv\n");
+
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(fb.snapshot(None).as_bytes(), b"one\ntwo\n");
+        let cached_ids = |fb: &CodexFeedback| -> Vec<_> {
+            let cache = fb.inner.snapshot_cache.lock().unwrap();
+            cache.as_ref().unwrap().entries.keys().copied().collect()
+        };
+        assert_eq!(cached_ids(&fb), vec![None, Some(id)]);
+
+        // Closing a conversation drops its cached snapshot along with its buffer.
+        fb.close_conversation(id, None).unwrap();
+        assert_eq!(cached_ids(&fb), vec![None]);
+
+        // Storing a fresh snapshot expires entries older than the interval.
+        let other = ConversationId::new();
+        fb.make_writer_for(other)
+            .make_writer()
+            .write_all(b"other\n")
+            .unwrap();
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(fb.snapshot(Some(other)).as_bytes(), b"other\n");
+        assert_eq!(cached_ids(&fb), vec![Some(other)]);
+    }
+
+    #[test]
+    fn min_retention_keeps_recent_lines_up_to_the_ceiling() {
+        use crate::clock::MockClock;
+
+        let clock = Arc::new(MockClock::new());
+        let fb = CodexFeedback::with_capacity(32)
+            .with_clock(clock.clone())
+            .with_min_retention(Duration::from_secs(10), 64);
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"old line 1\nold line 2\n").unwrap();
+        clock.advance(Duration::from_secs(11));
+
+        // Older lines go first; the 36 recent bytes outgrow the capacity.
+        w.write_all(b"recent 1\nrecent 2\n").unwrap();
+        w.write_all(b"recent 3\n").unwrap();
+        w.write_all(b"recent 4\n").unwrap();
+        assert_eq!(
+            fb.snapshot(None).as_bytes(),
+            b"recent 1\nrecent 2\nrecent 3\nrecent 4\n"
+        );
+
+        // Past the ceiling, even recent bytes are evicted.
+        for i in 5..9 {
+            w.write_all(format!("recent {i}\n").as_bytes()).unwrap();
+        }
+        let snapshot = fb.snapshot(None);
+        assert_eq!(snapshot.as_bytes().len(), 64);
+        assert!(snapshot.as_bytes().ends_with(b"recent 8\n"));
+
+        // Once lines age out of the window the usual capacity applies again.
+        clock.advance(Duration::from_secs(11));
+        w.write_all(b"later\n").unwrap();
+        assert_eq!(fb.snapshot(None).as_bytes().len(), 32);
+    }
+
+    #[test]
+    fn version_changes_on_write_but_not_on_read() {
+        let fb = CodexFeedback::new();
+        let mut w = fb.make_writer().make_writer();
+        let start = fb.version();
+
+        w.write_all(b"one\n").unwrap();
+        let after_write = fb.version();
+        assert!(after_write > start);
+
+        let _ = fb.snapshot(None);
+        let _ = fb.tail(1);
+        let _ = fb.count_matches(&Regex::new("one").unwrap());
+        assert_eq!(fb.version(), after_write);
+
+        w.write_all(b"two\n").unwrap();
+        assert!(fb.version() > after_write);
+    }
+
+    #[test]
+    fn wrapped_breaks_long_lines_on_char_boundaries() {
+        let snapshot = CodexLogSnapshot::new(
+            "short\r\nabcdefghijkl\n日本語のテキスト\n\nlast"
+                .as_bytes()
+                .to_vec(),
+            "thread".to_string(),
+        );
+
+        assert_eq!(
+            snapshot.wrapped(5),
+            "short\r\nabcde\nfghij\nkl\n日本\n語の\nテキ\nスト\n\nlast"
+        );
+        assert_eq!(
+            snapshot.wrapped(80),
+            String::from_utf8_lossy(snapshot.as_bytes())
+        );
+    }
+
+    #[test]
+    fn synced_save_writes_the_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let fb = CodexFeedback::new();
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"durable\n")
+            .unwrap();
+
+        let path = fb.snapshot(None).save_to_dir_synced(dir.path()).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"durable\n");
+        assert_eq!(fb.last_saved_path(), Some(path));
     }
 }
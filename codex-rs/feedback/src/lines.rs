@@ -0,0 +1,103 @@
+//! Shared newline handling for the line-oriented helpers, so they all agree on
+//! what a line is: `\n` and `\r\n` both end a line, and a final line without a
+//! terminator still counts.
+
+/// What to do with the terminator at the end of each line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum TrailingNewline {
+    /// Drop `\n` or `\r\n`.
+    #[default]
+    Strip,
+    /// Yield lines exactly as written, terminator included.
+    Keep,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct LineSplitter {
+    trailing: TrailingNewline,
+}
+
+impl LineSplitter {
+    pub(crate) const fn new(trailing: TrailingNewline) -> Self {
+        Self { trailing }
+    }
+
+    /// Splits `text` into lines according to the policy.
+    pub(crate) fn split<'a>(self, text: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        text.split_inclusive('\n')
+            .map(move |line| match self.trailing {
+                TrailingNewline::Strip => split_terminator(line).0,
+                TrailingNewline::Keep => line,
+            })
+    }
+
+    /// Like [`LineSplitter::split`], for raw bytes, pairing each line with the
+    /// offset of its first byte in `bytes`.
+    pub(crate) fn split_bytes<'a>(
+        self,
+        bytes: &'a [u8],
+    ) -> impl Iterator<Item = (usize, &'a [u8])> + 'a {
+        let mut start = 0;
+        bytes.split_inclusive(|&b| b == b'\n').map(move |line| {
+            let offset = start;
+            start += line.len();
+            let line = match line.strip_suffix(b"\n") {
+                Some(content) if self.trailing == TrailingNewline::Strip => {
+                    self.finish_line(content)
+                }
+                _ => line,
+            };
+            (offset, line)
+        })
+    }
+
+    /// Applies the policy to one complete line given without its final `\n`, as
+    /// found when scanning raw bytes.
+    pub(crate) fn finish_line(self, line: &[u8]) -> &[u8] {
+        match self.trailing {
+            TrailingNewline::Strip => line.strip_suffix(b"\r").unwrap_or(line),
+            TrailingNewline::Keep => line,
+        }
+    }
+}
+
+/// Splits a line as yielded under [`TrailingNewline::Keep`] into its content and
+/// its terminator: `\n`, `\r\n`, or nothing for a final unterminated line.
+pub(crate) fn split_terminator(line: &str) -> (&str, &str) {
+    let content = line
+        .strip_suffix('\n')
+        .map_or(line, |line| line.strip_suffix('\r').unwrap_or(line));
+    line.split_at(content.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn splits_mixed_line_endings() {
+        let text = "a\r\nb\n\r\nc";
+        assert_eq!(
+            LineSplitter::new(TrailingNewline::Strip)
+                .split(text)
+                .collect::<Vec<_>>(),
+            vec!["a", "b", "", "c"]
+        );
+        assert_eq!(
+            LineSplitter::new(TrailingNewline::Keep)
+                .split(text)
+                .collect::<Vec<_>>(),
+            vec!["a\r\n", "b\n", "\r\n", "c"]
+        );
+        assert_eq!(LineSplitter::default().finish_line(b"x\r"), b"x");
+        assert_eq!(
+            LineSplitter::default()
+                .split_bytes(text.as_bytes())
+                .collect::<Vec<_>>(),
+            vec![(0, &b"a"[..]), (3, b"b"), (5, b""), (7, b"c")]
+        );
+        assert_eq!(split_terminator("x\r\n"), ("x", "\r\n"));
+        assert_eq!(split_terminator("x\r\r\n"), ("x\r", "\r\n"));
+    }
+}
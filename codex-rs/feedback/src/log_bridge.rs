@@ -0,0 +1,93 @@
+use std::io::Write;
+
+use chrono::SecondsFormat;
+use chrono::Utc;
+use log::LevelFilter;
+use log::Log;
+use log::Metadata;
+use log::Record;
+use log::SetLoggerError;
+use tracing_subscriber::fmt::writer::MakeWriter;
+
+use crate::CodexFeedback;
+
+/// A [`log::Log`] implementation that writes records into a [`CodexFeedback`]
+/// buffer, one line per record, laid out like the tracing fmt layer's output.
+pub struct FeedbackLogger {
+    feedback: CodexFeedback,
+}
+
+impl CodexFeedback {
+    /// Returns a `log` logger feeding this instance's shared buffer, for crates
+    /// that log through `log` instead of `tracing`.
+    pub fn log_logger(&self) -> FeedbackLogger {
+        FeedbackLogger {
+            feedback: self.clone(),
+        }
+    }
+
+    /// Installs [`CodexFeedback::log_logger`] as the process-wide `log` logger and
+    /// sets `log`'s maximum level to `max_level`. Fails if a logger is already set.
+    pub fn install_log_logger(&self, max_level: LevelFilter) -> Result<(), SetLoggerError> {
+        log::set_logger(Box::leak(Box::new(self.log_logger())))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+}
+
+impl Log for FeedbackLogger {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        self.feedback.is_enabled()
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{} {:>5} {}: {}\n",
+            Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        // Logging must not fail the caller; a rejected write is simply dropped.
+        let _ = self
+            .feedback
+            .make_writer()
+            .make_writer()
+            .write_all(line.as_bytes());
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn log_records_land_in_the_snapshot() {
+        let fb = CodexFeedback::with_capacity(1024);
+        fb.install_log_logger(LevelFilter::Info).unwrap();
+
+        log::info!(target: "codex::deps", "connected to {}", "db");
+        log::warn!("slow query");
+        log::debug!("filtered out by max level");
+
+        let text = String::from_utf8(fb.snapshot(None).as_bytes().to_vec()).unwrap();
+        let lines: Vec<&str> = text
+            .lines()
+            .map(|line| line.split_once(' ').unwrap().1)
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                " INFO codex::deps: connected to db",
+                " WARN codex_feedback::log_bridge::tests: slow query",
+            ]
+        );
+    }
+}
@@ -0,0 +1,611 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::CodexLogSnapshot;
+use crate::FeedbackError;
+
+/// First line of the header written by [`CodexLogSnapshot::save_to_dir_with_meta`].
+const META_HEADER_START: &str = "# codex-feedback-meta v1";
+/// Last line of that header; the log body follows it.
+const META_HEADER_END: &str = "# end";
+const METADATA_KEY_PREFIX: &str = "metadata.";
+/// Opening and closing line of a [`HeaderFormat::YamlFrontMatter`] header.
+const YAML_FRONT_MATTER_FENCE: &str = "---";
+
+/// How the header written by [`CodexLogSnapshot::save_to_dir_with_header`] is laid
+/// out. Each carries the thread id, classification, note, and metadata, in that
+/// order, leaving out the classification and note when unset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HeaderFormat {
+    /// `# key: value` lines between `# codex-feedback-meta v1` and `# end`, as
+    /// read back by [`CodexLogSnapshot::from_file_with_meta`].
+    #[default]
+    Plain,
+    /// YAML front matter between `---` lines, with values as double-quoted
+    /// scalars and metadata nested under `metadata:`.
+    YamlFrontMatter,
+    /// One line holding a JSON object, with metadata nested under `"metadata"`.
+    Json,
+}
+
+impl CodexLogSnapshot {
+    /// Records why this snapshot is being submitted (e.g. `"bug"`); written by
+    /// [`CodexLogSnapshot::save_to_dir_with_meta`].
+    pub fn with_classification(mut self, classification: &str) -> Self {
+        self.classification = Some(classification.to_string());
+        self
+    }
+
+    /// Attaches a free-form note, such as the user's description of the problem.
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.note = Some(note.to_string());
+        self
+    }
+
+    /// Adds one `key: value` pair to the snapshot's metadata, replacing any earlier
+    /// value for `key`.
+    pub fn with_metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Attaches structured context, such as details about the environment, exported
+    /// as is under `context` by [`CodexLogSnapshot::to_json`] instead of being
+    /// flattened into string metadata. Replaces any earlier context.
+    pub fn with_context_json(mut self, context: serde_json::Value) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// Like [`CodexLogSnapshot::save_to_temp_file_with_meta`], but writes into `dir`.
+    pub fn save_to_dir_with_meta(&self, dir: &Path) -> Result<PathBuf, FeedbackError> {
+        self.save_to_dir_with_header(dir, HeaderFormat::Plain)
+    }
+
+    /// Saves the snapshot to the temp directory behind a header carrying its thread
+    /// id, classification, note, and metadata, laid out as `format`.
+    pub fn save_to_temp_file_with_header(
+        &self,
+        format: HeaderFormat,
+    ) -> Result<PathBuf, FeedbackError> {
+        self.save_to_dir_with_header(&std::env::temp_dir(), format)
+    }
+
+    /// Like [`CodexLogSnapshot::save_to_temp_file_with_header`], but writes into `dir`.
+    pub fn save_to_dir_with_header(
+        &self,
+        dir: &Path,
+        format: HeaderFormat,
+    ) -> Result<PathBuf, FeedbackError> {
+        let mut contents = match format {
+            HeaderFormat::Plain => self.plain_header(),
+            HeaderFormat::YamlFrontMatter => self.yaml_header(),
+            HeaderFormat::Json => self.json_header(),
+        }
+        .into_bytes();
+        contents.extend_from_slice(self.as_bytes());
+        self.write_file(dir, &contents)
+    }
+
+    /// The header fields other than metadata, in order.
+    fn header_fields(&self) -> Vec<(&'static str, &str)> {
+        let mut fields = vec![("thread_id", self.thread_id.as_str())];
+        if let Some(classification) = &self.classification {
+            fields.push(("classification", classification));
+        }
+        if let Some(note) = &self.note {
+            fields.push(("note", note));
+        }
+        fields
+    }
+
+    fn plain_header(&self) -> String {
+        let mut header = format!("{META_HEADER_START}\n");
+        let mut field = |key: &str, value: &str| {
+            header.push_str(&format!("# {}: {}\n", escape(key), escape(value)));
+        };
+        for (key, value) in self.header_fields() {
+            field(key, value);
+        }
+        for (key, value) in &self.metadata {
+            field(&format!("{METADATA_KEY_PREFIX}{key}"), value);
+        }
+        header.push_str(META_HEADER_END);
+        header.push('\n');
+        header
+    }
+
+    fn yaml_header(&self) -> String {
+        // A JSON string is also a valid double-quoted YAML scalar.
+        let quote = |value: &str| serde_json::Value::from(value).to_string();
+        let mut header = format!("{YAML_FRONT_MATTER_FENCE}\n");
+        for (key, value) in self.header_fields() {
+            header.push_str(&format!("{key}: {}\n", quote(value)));
+        }
+        if !self.metadata.is_empty() {
+            header.push_str("metadata:\n");
+            for (key, value) in &self.metadata {
+                header.push_str(&format!("  {}: {}\n", quote(key), quote(value)));
+            }
+        }
+        header.push_str(&format!("{YAML_FRONT_MATTER_FENCE}\n"));
+        header
+    }
+
+    fn json_header(&self) -> String {
+        let mut header = serde_json::Map::new();
+        for (key, value) in self.header_fields() {
+            header.insert(key.to_string(), value.into());
+        }
+        if !self.metadata.is_empty() {
+            header.insert("metadata".to_string(), serde_json::json!(self.metadata));
+        }
+        format!("{}\n", serde_json::Value::Object(header))
+    }
+
+    /// Hex SHA-256 of the log body alone, so identical logs hash the same whatever
+    /// their thread id, classification, note, or metadata; use it to dedupe
+    /// submissions. See [`CodexLogSnapshot::artifact_sha256`] to tell artifacts apart.
+    pub fn content_sha256(&self) -> String {
+        format!("{:x}", Sha256::digest(self.as_bytes()))
+    }
+
+    /// Hex SHA-256 of the whole artifact: the log body plus thread id,
+    /// classification, note, and metadata, so any change to them changes the hash.
+    /// Fields are length-prefixed, so moving text between them does too.
+    pub fn artifact_sha256(&self) -> String {
+        let mut hasher = Sha256::new();
+        let mut field = |value: &[u8]| {
+            hasher.update((value.len() as u64).to_le_bytes());
+            hasher.update(value);
+        };
+        field(self.as_bytes());
+        field(self.thread_id.as_bytes());
+        for optional in [&self.classification, &self.note] {
+            match optional {
+                Some(value) => field(value.as_bytes()),
+                // Distinct from `Some("")`, whose length prefix is 0.
+                None => field(&[0xFF]),
+            }
+        }
+        for (key, value) in &self.metadata {
+            field(key.as_bytes());
+            field(value.as_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Saves the snapshot to the temp directory behind a header block carrying its
+    /// thread id, classification, note, and metadata, so that
+    /// [`CodexLogSnapshot::from_file_with_meta`] can restore them for re-submission.
+    pub fn save_to_temp_file_with_meta(&self) -> Result<PathBuf, FeedbackError> {
+        self.save_to_dir_with_meta(&std::env::temp_dir())
+    }
+
+    /// Reads a file written by [`CodexLogSnapshot::save_to_dir_with_meta`] back into
+    /// a snapshot. Files without a header (e.g. from
+    /// [`CodexLogSnapshot::save_to_temp_file`]) are read whole, with no
+    /// classification, note, or metadata; their thread id is taken from the
+    /// `codex-feedback-{thread_id}.log` file name when it has that form.
+    pub fn from_file_with_meta(path: &Path) -> io::Result<CodexLogSnapshot> {
+        let contents = fs::read(path)?;
+        let Some(rest) = contents
+            .strip_prefix(META_HEADER_START.as_bytes())
+            .and_then(|rest| rest.strip_prefix(b"\n"))
+        else {
+            let thread_id = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("codex-feedback-"))
+                .and_then(|name| name.strip_suffix(".log"))
+                .unwrap_or_default()
+                .to_string();
+            return Ok(CodexLogSnapshot::new(contents, thread_id));
+        };
+
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {message}", path.display()),
+            )
+        };
+        let mut snapshot = CodexLogSnapshot::new(Vec::new(), String::new());
+        let mut offset = 0;
+        loop {
+            let Some(len) = rest[offset..].iter().position(|&b| b == b'\n') else {
+                return Err(invalid(
+                    "unterminated codex-feedback-meta header".to_string(),
+                ));
+            };
+            let line = std::str::from_utf8(&rest[offset..offset + len])
+                .map_err(|_| invalid("header is not valid UTF-8".to_string()))?;
+            offset += len + 1;
+            if line == META_HEADER_END {
+                break;
+            }
+            let Some((key, value)) = line
+                .strip_prefix("# ")
+                .and_then(|field| field.split_once(": "))
+            else {
+                return Err(invalid(format!("malformed header line {line:?}")));
+            };
+            let (key, value) = (unescape(key), unescape(value));
+            match key.as_str() {
+                "thread_id" => snapshot.thread_id = value,
+                "classification" => snapshot.classification = Some(value),
+                "note" => snapshot.note = Some(value),
+                _ => {
+                    if let Some(key) = key.strip_prefix(METADATA_KEY_PREFIX) {
+                        snapshot.metadata.insert(key.to_string(), value);
+                    }
+                }
+            }
+        }
+        let bytes = rest[offset..].to_vec();
+        snapshot.end_offset = bytes.len() as u64;
+        snapshot.bytes = bytes;
+        Ok(snapshot)
+    }
+
+    /// Whether the thread id in the header of the file at `path` (in any
+    /// [`HeaderFormat`]) is the one its `codex-feedback-*.log` name was built
+    /// from, to catch renamed files. Files without a header only need a name of
+    /// that form. Names built through [`crate::CodexFeedback::with_thread_id_mapper`]
+    /// do not match their header's raw id.
+    pub fn validate_file(path: &Path) -> io::Result<bool> {
+        let Some(name) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .filter(|name| name.starts_with("codex-feedback-") && name.ends_with(".log"))
+        else {
+            return Ok(false);
+        };
+        let contents = fs::read(path)?;
+        let header_thread_id = if contents.starts_with(format!("{META_HEADER_START}\n").as_bytes())
+        {
+            Some(CodexLogSnapshot::from_file_with_meta(path)?.thread_id)
+        } else {
+            yaml_header_thread_id(&contents).or_else(|| json_header_thread_id(&contents))
+        };
+        Ok(header_thread_id.is_none_or(|thread_id| crate::log_file_name(&thread_id) == name))
+    }
+
+    /// Reassembles a log saved as several chunk files by reading each with
+    /// [`CodexLogSnapshot::from_file_with_meta`] and concatenating their bodies in
+    /// the given order. Classification, note, and metadata come from the first
+    /// chunk. Chunks whose thread id is known (from a header or file name) must all
+    /// agree, otherwise an [`io::ErrorKind::InvalidData`] error is returned.
+    pub fn from_chunk_files(paths: &[&Path]) -> io::Result<CodexLogSnapshot> {
+        let Some((first, rest)) = paths.split_first() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no chunk files to reassemble",
+            ));
+        };
+        let mut snapshot = CodexLogSnapshot::from_file_with_meta(first)?;
+        for path in rest {
+            let chunk = CodexLogSnapshot::from_file_with_meta(path)?;
+            if snapshot.thread_id.is_empty() {
+                snapshot.thread_id = chunk.thread_id.clone();
+            } else if !chunk.thread_id.is_empty() && chunk.thread_id != snapshot.thread_id {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} belongs to thread {}, not {}",
+                        path.display(),
+                        chunk.thread_id,
+                        snapshot.thread_id
+                    ),
+                ));
+            }
+            snapshot.bytes.extend_from_slice(chunk.as_bytes());
+        }
+        snapshot.end_offset = snapshot.bytes.len() as u64;
+        Ok(snapshot)
+    }
+}
+
+/// The thread id of a [`HeaderFormat::YamlFrontMatter`] header, which is always its
+/// first field.
+fn yaml_header_thread_id(contents: &[u8]) -> Option<String> {
+    let rest = contents
+        .strip_prefix(YAML_FRONT_MATTER_FENCE.as_bytes())?
+        .strip_prefix(b"\n")?;
+    let line = rest.split(|&b| b == b'\n').next()?;
+    let value = std::str::from_utf8(line)
+        .ok()?
+        .strip_prefix("thread_id: ")?;
+    serde_json::from_str::<serde_json::Value>(value)
+        .ok()?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// The thread id of a [`HeaderFormat::Json`] header.
+fn json_header_thread_id(contents: &[u8]) -> Option<String> {
+    let line = contents.split(|&b| b == b'\n').next()?;
+    let header: serde_json::Value = serde_json::from_slice(line).ok()?;
+    header["thread_id"].as_str().map(str::to_string)
+}
+
+/// Keeps header values on one line and free of the `": "` separator.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace(':', "\\c")
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('c') => out.push(':'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn header_snapshot() -> CodexLogSnapshot {
+        CodexLogSnapshot::new(b"---\n# not a header\nlog body\n".to_vec(), "t-1".into())
+            .with_classification("bug")
+            .with_note("it broke: \"badly\"\nafter \\ resume")
+            .with_metadata("os", "linux")
+            .with_metadata("cli.version", "0.59.0")
+    }
+
+    #[test]
+    fn validate_file_catches_renamed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = header_snapshot();
+        for format in [
+            HeaderFormat::Plain,
+            HeaderFormat::YamlFrontMatter,
+            HeaderFormat::Json,
+        ] {
+            let path = snapshot
+                .save_to_dir_with_header(dir.path(), format)
+                .unwrap();
+            assert!(
+                CodexLogSnapshot::validate_file(&path).unwrap(),
+                "{format:?}"
+            );
+
+            let renamed = dir.path().join("codex-feedback-t-2.log");
+            fs::rename(&path, &renamed).unwrap();
+            assert!(
+                !CodexLogSnapshot::validate_file(&renamed).unwrap(),
+                "{format:?}"
+            );
+            fs::remove_file(&renamed).unwrap();
+        }
+
+        let headerless = snapshot.save_to_dir(dir.path()).unwrap();
+        assert!(CodexLogSnapshot::validate_file(&headerless).unwrap());
+        let unrelated = dir.path().join("notes.txt");
+        fs::rename(&headerless, &unrelated).unwrap();
+        assert!(!CodexLogSnapshot::validate_file(&unrelated).unwrap());
+    }
+
+    #[test]
+    fn plain_header_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = header_snapshot();
+        let path = snapshot
+            .save_to_dir_with_header(dir.path(), HeaderFormat::Plain)
+            .unwrap();
+
+        let restored = CodexLogSnapshot::from_file_with_meta(&path).unwrap();
+        assert_eq!(restored.as_bytes(), snapshot.as_bytes());
+        assert_eq!(restored.thread_id, snapshot.thread_id);
+        assert_eq!(restored.classification, snapshot.classification);
+        assert_eq!(restored.note, snapshot.note);
+        assert_eq!(restored.metadata, snapshot.metadata);
+    }
+
+    #[test]
+    fn yaml_front_matter_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = header_snapshot();
+        let path = snapshot
+            .save_to_dir_with_header(dir.path(), HeaderFormat::YamlFrontMatter)
+            .unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+
+        // Parses the flat/one-level subset of YAML the header uses.
+        let front_matter = contents.strip_prefix("---\n").unwrap();
+        let (front_matter, body) = front_matter.split_once("\n---\n").unwrap();
+        let unquote = |value: &str| {
+            let value: serde_json::Value = serde_json::from_str(value).unwrap();
+            value.as_str().unwrap().to_string()
+        };
+        let mut fields = BTreeMap::new();
+        let mut metadata = BTreeMap::new();
+        for line in front_matter.lines() {
+            match line.strip_prefix("  ") {
+                Some(nested) => {
+                    let (key, value) = nested.split_once(": ").unwrap();
+                    metadata.insert(unquote(key), unquote(value));
+                }
+                None if line == "metadata:" => {}
+                None => {
+                    let (key, value) = line.split_once(": ").unwrap();
+                    fields.insert(key.to_string(), unquote(value));
+                }
+            }
+        }
+
+        assert_eq!(body.as_bytes(), snapshot.as_bytes());
+        assert_eq!(
+            fields,
+            BTreeMap::from([
+                ("classification".to_string(), "bug".to_string()),
+                ("note".to_string(), snapshot.note.clone().unwrap()),
+                ("thread_id".to_string(), "t-1".to_string()),
+            ])
+        );
+        assert_eq!(metadata, snapshot.metadata);
+    }
+
+    #[test]
+    fn json_header_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = header_snapshot();
+        let path = snapshot
+            .save_to_dir_with_header(dir.path(), HeaderFormat::Json)
+            .unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+
+        let (header, body) = contents.split_once('\n').unwrap();
+        let header: serde_json::Value = serde_json::from_str(header).unwrap();
+        assert_eq!(body.as_bytes(), snapshot.as_bytes());
+        assert_eq!(
+            header,
+            serde_json::json!({
+                "thread_id": "t-1",
+                "classification": "bug",
+                "note": snapshot.note,
+                "metadata": {"cli.version": "0.59.0", "os": "linux"},
+            })
+        );
+        assert_eq!(
+            CodexLogSnapshot::new(Vec::new(), "t-2".into()).json_header(),
+            "{\"thread_id\":\"t-2\"}\n"
+        );
+    }
+
+    #[test]
+    fn headerless_files_get_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = CodexLogSnapshot::new(b"plain log\n".to_vec(), "t-2".into())
+            .save_to_dir(dir.path())
+            .unwrap();
+
+        let restored = CodexLogSnapshot::from_file_with_meta(&path).unwrap();
+        assert_eq!(restored.as_bytes(), b"plain log\n");
+        assert_eq!(restored.thread_id, "t-2");
+        assert_eq!(restored.classification, None);
+        assert_eq!(restored.note, None);
+        assert!(restored.metadata.is_empty());
+
+        let other = dir.path().join("notes.txt");
+        fs::write(&other, "free text").unwrap();
+        let restored = CodexLogSnapshot::from_file_with_meta(&other).unwrap();
+        assert_eq!(restored.thread_id, "");
+        assert_eq!(restored.as_bytes(), b"free text");
+    }
+
+    #[test]
+    fn content_hash_ignores_metadata_but_artifact_hash_does_not() {
+        let base = CodexLogSnapshot::new(b"INFO same body\n".to_vec(), "thread-1".to_string());
+        let content = base.content_sha256();
+        let artifact = base.artifact_sha256();
+
+        let variants = [
+            CodexLogSnapshot::new(b"INFO same body\n".to_vec(), "thread-2".to_string()),
+            CodexLogSnapshot::new(b"INFO same body\n".to_vec(), "thread-1".to_string())
+                .with_classification("bug"),
+            CodexLogSnapshot::new(b"INFO same body\n".to_vec(), "thread-1".to_string())
+                .with_note(""),
+            CodexLogSnapshot::new(b"INFO same body\n".to_vec(), "thread-1".to_string())
+                .with_metadata("os", "linux"),
+        ];
+        for variant in &variants {
+            assert_eq!(variant.content_sha256(), content);
+            assert_ne!(variant.artifact_sha256(), artifact);
+        }
+
+        let other_body = CodexLogSnapshot::new(b"INFO other\n".to_vec(), "thread-1".to_string());
+        assert_ne!(other_body.content_sha256(), content);
+        assert_eq!(
+            CodexLogSnapshot::new(b"INFO same body\n".to_vec(), "thread-1".to_string())
+                .artifact_sha256(),
+            artifact
+        );
+    }
+
+    #[test]
+    fn chunk_files_reassemble_into_the_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let original = b"line one\nline two\nline three\n".to_vec();
+        let paths: Vec<PathBuf> = original
+            .chunks(10)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let chunk_dir = dir.path().join(i.to_string());
+                fs::create_dir(&chunk_dir).unwrap();
+                CodexLogSnapshot::new(chunk.to_vec(), "thread-1".to_string())
+                    .with_classification("bug")
+                    .save_to_dir_with_meta(&chunk_dir)
+                    .unwrap()
+            })
+            .collect();
+        let paths: Vec<&Path> = paths.iter().map(PathBuf::as_path).collect();
+
+        let snapshot = CodexLogSnapshot::from_chunk_files(&paths).unwrap();
+
+        assert_eq!(snapshot.as_bytes(), original);
+        assert_eq!(snapshot.thread_id, "thread-1");
+        assert_eq!(snapshot.classification.as_deref(), Some("bug"));
+    }
+
+    #[test]
+    fn chunk_files_from_different_threads_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = CodexLogSnapshot::new(b"a\n".to_vec(), "thread-a".to_string())
+            .save_to_dir(dir.path())
+            .unwrap();
+        let b = CodexLogSnapshot::new(b"b\n".to_vec(), "thread-b".to_string())
+            .save_to_dir(dir.path())
+            .unwrap();
+
+        let err = CodexLogSnapshot::from_chunk_files(&[&a, &b]).err().unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn context_json_is_exported_unchanged() {
+        let context = serde_json::json!({
+            "os": { "name": "linux", "version": [6, 8, 0] },
+            "features": ["a", "b"],
+            "sandbox": null,
+            "ratio": 0.5,
+        });
+        let snapshot = CodexLogSnapshot::new(b"log\n".to_vec(), "t-1".into())
+            .with_context_json(context.clone());
+
+        let json = snapshot.to_json();
+        assert_eq!(json["context"], context);
+        assert_eq!(json["text"], "log\n");
+        assert_eq!(
+            CodexLogSnapshot::new(Vec::new(), "t-2".into())
+                .to_json()
+                .get("context"),
+            None
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use std::fmt::Write as _;
+#[cfg(feature = "otel")]
+use std::sync::Arc;
+
+use crate::CodexFeedback;
+
+impl CodexFeedback {
+    /// Shared-buffer gauges and counters in the Prometheus text exposition format,
+    /// for inclusion in a metrics endpoint. Every metric name starts with
+    /// `{prefix}_`:
+    ///
+    /// - `buffer_bytes`: bytes currently buffered.
+    /// - `buffer_capacity_bytes`: the buffer's capacity.
+    /// - `evicted_bytes_total`: bytes written but no longer buffered, whether
+    ///   evicted to make room or drained into snapshots.
+    /// - `lines`: buffered lines, counting a trailing partial line.
+    pub fn prometheus_metrics(&self, prefix: &str) -> String {
+        let ring = self.inner.ring_read();
+        let metrics = [
+            (
+                "buffer_bytes",
+                "gauge",
+                "Bytes currently held in the feedback buffer.",
+                ring.len() as u64,
+            ),
+            (
+                "buffer_capacity_bytes",
+                "gauge",
+                "Capacity of the feedback buffer in bytes.",
+                ring.max as u64,
+            ),
+            (
+                "evicted_bytes_total",
+                "counter",
+                "Bytes written to the feedback buffer that it no longer holds.",
+                ring.offsets().start,
+            ),
+            (
+                "lines",
+                "gauge",
+                "Lines held in the feedback buffer.",
+                ring.line_count() as u64,
+            ),
+        ];
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(out, "# HELP {prefix}_{name} {help}");
+            let _ = writeln!(out, "# TYPE {prefix}_{name} {kind}");
+            let _ = writeln!(out, "{prefix}_{name} {value}");
+        }
+        out
+    }
+
+    /// Registers observable instruments on `meter` reporting the shared buffer's
+    /// state at each collection, like [`CodexFeedback::prometheus_metrics`]:
+    ///
+    /// - `codex.feedback.buffer.bytes` (gauge): bytes currently buffered.
+    /// - `codex.feedback.buffer.capacity` (gauge): the buffer's capacity.
+    /// - `codex.feedback.evicted` (counter): bytes written but no longer buffered.
+    ///
+    /// The instruments hold only a weak reference, so they report nothing once
+    /// every handle to this instance is dropped.
+    #[cfg(feature = "otel")]
+    pub fn record_metrics(&self, meter: &opentelemetry::metrics::Meter) {
+        let observe = |read: fn(&crate::RingBuffer) -> u64| {
+            let inner = Arc::downgrade(&self.inner);
+            move |observer: &dyn opentelemetry::metrics::AsyncInstrument<u64>| {
+                if let Some(inner) = inner.upgrade() {
+                    observer.observe(read(&inner.ring_read()), &[]);
+                }
+            }
+        };
+        meter
+            .u64_observable_gauge("codex.feedback.buffer.bytes")
+            .with_description("Bytes currently held in the feedback buffer.")
+            .with_unit("By")
+            .with_callback(observe(|ring| ring.len() as u64))
+            .build();
+        meter
+            .u64_observable_gauge("codex.feedback.buffer.capacity")
+            .with_description("Capacity of the feedback buffer in bytes.")
+            .with_unit("By")
+            .with_callback(observe(|ring| ring.max as u64))
+            .build();
+        meter
+            .u64_observable_counter("codex.feedback.evicted")
+            .with_description("Bytes written to the feedback buffer that it no longer holds.")
+            .with_unit("By")
+            .with_callback(observe(|ring| ring.offsets().start))
+            .build();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::Write;
+
+    use pretty_assertions::assert_eq;
+    use tracing_subscriber::fmt::writer::MakeWriter;
+
+    use super::*;
+
+    #[test]
+    fn metrics_parse_as_prometheus_text() {
+        let fb = CodexFeedback::with_capacity(16);
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"0123456789\nabcdefghij\nxy").unwrap();
+
+        let text = fb.prometheus_metrics("codex_feedback");
+
+        let mut values = BTreeMap::new();
+        let mut types = BTreeMap::new();
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = rest.split_once(' ').unwrap();
+                types.insert(name.to_string(), kind.to_string());
+            } else if !line.starts_with('#') {
+                let (name, value) = line.split_once(' ').unwrap();
+                values.insert(name.to_string(), value.parse::<u64>().unwrap());
+            }
+        }
+        assert_eq!(
+            values,
+            BTreeMap::from([
+                ("codex_feedback_buffer_bytes".to_string(), 16),
+                ("codex_feedback_buffer_capacity_bytes".to_string(), 16),
+                ("codex_feedback_evicted_bytes_total".to_string(), 8),
+                ("codex_feedback_lines".to_string(), 3),
+            ])
+        );
+        assert_eq!(types["codex_feedback_evicted_bytes_total"], "counter");
+        assert_eq!(types["codex_feedback_buffer_bytes"], "gauge");
+    }
+
+    #[cfg(feature = "otel")]
+    #[test]
+    fn otel_instruments_report_buffer_state() {
+        use opentelemetry::metrics::MeterProvider as _;
+        use opentelemetry_sdk::metrics::InMemoryMetricExporter;
+        use opentelemetry_sdk::metrics::PeriodicReader;
+        use opentelemetry_sdk::metrics::SdkMeterProvider;
+        use opentelemetry_sdk::metrics::data::AggregatedMetrics;
+        use opentelemetry_sdk::metrics::data::MetricData;
+
+        let exporter = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter.clone()).build())
+            .build();
+        let fb = CodexFeedback::with_capacity(16);
+        fb.record_metrics(&provider.meter("codex-feedback"));
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"0123456789\nabcdefghij\nxy").unwrap();
+
+        provider.force_flush().unwrap();
+        let mut values = BTreeMap::new();
+        for resource in exporter.get_finished_metrics().unwrap() {
+            for scope in resource.scope_metrics() {
+                for metric in scope.metrics() {
+                    let value = match metric.data() {
+                        AggregatedMetrics::U64(MetricData::Gauge(gauge)) => {
+                            gauge.data_points().map(|point| point.value()).last()
+                        }
+                        AggregatedMetrics::U64(MetricData::Sum(sum)) => {
+                            sum.data_points().map(|point| point.value()).last()
+                        }
+                        _ => None,
+                    };
+                    values.insert(metric.name().to_string(), value);
+                }
+            }
+        }
+        assert_eq!(
+            values,
+            BTreeMap::from([
+                ("codex.feedback.buffer.bytes".to_string(), Some(16)),
+                ("codex.feedback.buffer.capacity".to_string(), Some(16)),
+                ("codex.feedback.evicted".to_string(), Some(8)),
+            ])
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Prefix shared by every file this crate saves.
+const FEEDBACK_FILE_PREFIX: &str = "codex-feedback-";
+
+/// Caps the total size of saved feedback files in `dir`, for apps that save one
+/// file per conversation and would otherwise grow the directory without bound.
+/// Call [`FeedbackDiskQuota::enforce`] after each save.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedbackDiskQuota {
+    pub dir: PathBuf,
+    pub max_total_bytes: u64,
+}
+
+impl FeedbackDiskQuota {
+    pub fn new(dir: impl Into<PathBuf>, max_total_bytes: u64) -> Self {
+        Self {
+            dir: dir.into(),
+            max_total_bytes,
+        }
+    }
+
+    /// Deletes `codex-feedback-*` files in `dir`, least recently modified first,
+    /// until their combined size is at most `max_total_bytes`. Other files are
+    /// neither counted nor touched. Returns the deleted paths, oldest first.
+    pub fn enforce(&self) -> io::Result<Vec<PathBuf>> {
+        let mut files: Vec<(SystemTime, PathBuf, u64)> = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if !entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(FEEDBACK_FILE_PREFIX)
+            {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                files.push((metadata.modified()?, entry.path(), metadata.len()));
+            }
+        }
+        // Ties on the modification time fall back to the name so runs are stable.
+        files.sort();
+
+        let mut total: u64 = files.iter().map(|(_, _, len)| len).sum();
+        let mut deleted = Vec::new();
+        for (_, path, len) in files {
+            if total <= self.max_total_bytes {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                // Someone else pruned it first; it no longer counts either way.
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+            total -= len;
+            deleted.push(path);
+        }
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn enforce_prunes_oldest_feedback_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        for (age, name) in [(3, "a"), (1, "c"), (2, "b"), (0, "d")] {
+            let path = dir.path().join(format!("codex-feedback-{name}.log"));
+            fs::write(&path, [b'x'; 100]).unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(base - Duration::from_secs(age * 60))
+                .unwrap();
+        }
+        fs::write(dir.path().join("unrelated.log"), [b'x'; 1000]).unwrap();
+
+        let quota = FeedbackDiskQuota::new(dir.path(), 250);
+        assert_eq!(
+            quota.enforce().unwrap(),
+            [
+                dir.path().join("codex-feedback-a.log"),
+                dir.path().join("codex-feedback-b.log"),
+            ]
+        );
+
+        let mut remaining: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        remaining.sort();
+        assert_eq!(
+            remaining,
+            [
+                "codex-feedback-c.log",
+                "codex-feedback-d.log",
+                "unrelated.log"
+            ]
+        );
+        // Already under quota: nothing more to do.
+        assert_eq!(quota.enforce().unwrap(), Vec::<PathBuf>::new());
+    }
+}
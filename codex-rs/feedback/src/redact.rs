@@ -0,0 +1,275 @@
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+use regex_lite::Regex;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// How secrets found in snapshots are masked; see
+/// [`crate::CodexFeedback::with_redaction_style`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RedactionStyle {
+    /// Replace the whole secret with `[REDACTED]`.
+    #[default]
+    Full,
+    /// Keep the first and last `keep` characters (`sk-abc…xyz`) so different keys
+    /// can still be told apart. Secrets shorter than `4 * keep` characters are
+    /// masked fully, so at most half of any secret is ever revealed.
+    Partial { keep: usize },
+}
+
+impl RedactionStyle {
+    fn mask(self, secret: &str) -> String {
+        match self {
+            RedactionStyle::Full => REDACTED.to_string(),
+            RedactionStyle::Partial { keep } => {
+                let chars: Vec<char> = secret.chars().collect();
+                if keep == 0 || chars.len() < keep.saturating_mul(4) {
+                    return REDACTED.to_string();
+                }
+                let prefix: String = chars[..keep].iter().collect();
+                let suffix: String = chars[chars.len() - keep..].iter().collect();
+                format!("{prefix}…{suffix}")
+            }
+        }
+    }
+}
+
+const LIMIT_NOTE: &str = "[redaction limit reached]\n";
+
+/// Masks API keys, bearer tokens, and `key=value` credentials in `text`. Returns
+/// the input unchanged (borrowed) when nothing matched.
+///
+/// With a `limit`, scanning stops once that many secrets have been masked and
+/// [`LIMIT_NOTE`] is appended on its own line; anything past that point is left
+/// as is.
+pub(crate) fn redact_secrets(
+    text: &str,
+    style: RedactionStyle,
+    limit: Option<usize>,
+) -> Cow<'_, str> {
+    let mut text = Cow::Borrowed(text);
+    let mut remaining = limit.unwrap_or(usize::MAX);
+    let mut limit_reached = false;
+    for (needles, re) in secret_regexes() {
+        // Cheap prefilter so large buffers without any candidate skip the regex.
+        if !needles
+            .iter()
+            .any(|needle| contains_ignore_ascii_case(&text, needle))
+        {
+            continue;
+        }
+        let mut replaced = String::new();
+        let mut last = 0;
+        for caps in re.captures_iter(&text) {
+            if remaining == 0 {
+                limit_reached = true;
+                break;
+            }
+            remaining -= 1;
+            let (Some(whole), Some(secret)) = (caps.get(0), caps.get(2)) else {
+                continue;
+            };
+            // Group 1 is a non-secret prefix that is kept, group 2 the secret itself.
+            replaced.push_str(&text[last..secret.start()]);
+            replaced.push_str(&style.mask(secret.as_str()));
+            last = whole.end();
+        }
+        if last > 0 {
+            replaced.push_str(&text[last..]);
+            text = Cow::Owned(replaced);
+        }
+        if limit_reached {
+            break;
+        }
+    }
+    if limit_reached {
+        let mut owned = text.into_owned();
+        if !owned.is_empty() && !owned.ends_with('\n') {
+            owned.push('\n');
+        }
+        owned.push_str(LIMIT_NOTE);
+        text = Cow::Owned(owned);
+    }
+    text
+}
+
+/// Shows what `patterns` would mask in `text`, independently of any
+/// [`crate::CodexFeedback`], e.g. to let users try out a custom pattern. Every
+/// match of every pattern is replaced with `[REDACTED]`; matches that overlap or
+/// touch are merged into one replacement. Returns the masked text and the number
+/// of replacements made.
+pub fn redact_preview(text: &str, patterns: &[Regex]) -> (String, usize) {
+    let merged = merged_matches(text, patterns);
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for &(start, end) in &merged {
+        out.push_str(&text[last..start]);
+        out.push_str(REDACTED);
+        last = end;
+    }
+    out.push_str(&text[last..]);
+    (out, merged.len())
+}
+
+/// Masks every match of `patterns` in `text` in the given `style`, merging
+/// overlapping or touching matches as [`redact_preview`] does. Returns the input
+/// unchanged (borrowed) when nothing matched.
+pub(crate) fn redact_patterns<'a>(
+    text: &'a str,
+    patterns: &[Regex],
+    style: RedactionStyle,
+) -> Cow<'a, str> {
+    let merged = merged_matches(text, patterns);
+    if merged.is_empty() {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for &(start, end) in &merged {
+        out.push_str(&text[last..start]);
+        out.push_str(&style.mask(&text[start..end]));
+        last = end;
+    }
+    out.push_str(&text[last..]);
+    Cow::Owned(out)
+}
+
+/// Non-empty match ranges of all `patterns`, sorted, with overlaps merged.
+fn merged_matches(text: &str, patterns: &[Regex]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = patterns
+        .iter()
+        .flat_map(|re| re.find_iter(text))
+        .filter(|m| !m.is_empty())
+        .map(|m| (m.start(), m.end()))
+        .collect();
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn contains_ignore_ascii_case(haystack: &str, needle: &str) -> bool {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .any(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Each pattern paired with substrings, one of which must occur for it to match.
+fn secret_regexes() -> &'static [(&'static [&'static str], Regex)] {
+    static RES: OnceLock<Vec<(&'static [&'static str], Regex)>> = OnceLock::new();
+    RES.get_or_init(|| {
+        let patterns: [(&'static [&'static str], &str); 3] = [
+            (&["sk-"], r"\b(sk-(?:proj-)?)([A-Za-z0-9_\-]{16,})"),
+            (&["bearer"], r"(?i)\b(bearer\s+)([A-Za-z0-9._~+/=\-]{8,})"),
+            (
+                &["key", "token", "secret", "password"],
+                r#"(?i)\b((?:api[_\-]?key|access[_\-]?token|secret|password)["']?\s*[:=]\s*["']?)([^\s"',;]+)"#,
+            ),
+        ];
+        patterns
+            .into_iter()
+            .map(|(needles, pattern)| {
+                (needles, Regex::new(pattern).expect("valid secret regex"))
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn full_and_partial_styles() {
+        let line = "key sk-abcdefghijklmnopxyz auth=Bearer eyJhbGciOi.payload.sig password=hunter2";
+
+        assert_eq!(
+            redact_secrets(line, RedactionStyle::Full, None),
+            "key sk-[REDACTED] auth=Bearer [REDACTED] password=[REDACTED]"
+        );
+        assert_eq!(
+            redact_secrets(line, RedactionStyle::Partial { keep: 3 }, None),
+            "key sk-abc…xyz auth=Bearer eyJ…sig password=[REDACTED]"
+        );
+    }
+
+    #[test]
+    fn partial_fully_masks_short_secrets() {
+        assert_eq!(
+            RedactionStyle::Partial { keep: 3 }.mask("abcdefghijk"),
+            REDACTED
+        );
+        assert_eq!(
+            RedactionStyle::Partial { keep: 3 }.mask("abcdefghijkl"),
+            "abc…jkl"
+        );
+        assert_eq!(
+            RedactionStyle::Partial { keep: 0 }.mask("abcdefghijkl"),
+            REDACTED
+        );
+    }
+
+    #[test]
+    fn leaves_clean_text_borrowed() {
+        assert!(matches!(
+            redact_secrets("nothing to see here", RedactionStyle::Full, None),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn stops_after_the_limit_and_notes_it() {
+        let text = (0..10)
+            .map(|i| format!("password=secret{i}\n"))
+            .collect::<String>();
+
+        let redacted = redact_secrets(&text, RedactionStyle::Full, Some(3));
+        let lines: Vec<&str> = redacted.lines().collect();
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[..3], ["password=[REDACTED]"; 3]);
+        assert_eq!(lines[3], "password=secret3");
+        assert_eq!(lines[10], "[redaction limit reached]");
+
+        // Exactly reaching the limit is not reported.
+        let two = "password=a1\npassword=b2\n";
+        assert_eq!(
+            redact_secrets(two, RedactionStyle::Full, Some(2)),
+            "password=[REDACTED]\npassword=[REDACTED]\n"
+        );
+    }
+
+    #[test]
+    fn preview_merges_overlapping_matches() {
+        let patterns = [
+            Regex::new(r"user-\d+").unwrap(),
+            Regex::new(r"\d+@example\.com").unwrap(),
+            Regex::new(r"tok_[a-z]+").unwrap(),
+        ];
+        assert_eq!(
+            redact_preview("from user-42@example.com, tok_abc and tok_def", &patterns),
+            ("from [REDACTED], [REDACTED] and [REDACTED]".to_string(), 3)
+        );
+    }
+
+    #[test]
+    fn preview_without_matches_is_unchanged() {
+        let patterns = [Regex::new(r"never-matches-\d{5}").unwrap()];
+        assert_eq!(
+            redact_preview("nothing to see", &patterns),
+            ("nothing to see".to_string(), 0)
+        );
+        assert_eq!(
+            redact_preview("no patterns", &[]),
+            ("no patterns".to_string(), 0)
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+
+use codex_protocol::ConversationId;
+use serde_json::Value;
+use serde_json::json;
+
+use crate::CodexFeedback;
+use crate::CodexLogSnapshot;
+use crate::SnapshotStats;
+
+/// Everything support tooling needs about one feedback submission; see
+/// [`CodexFeedback::build_report`].
+pub struct FeedbackReport {
+    /// The logs, with `classification` and `reason` also recorded on the snapshot
+    /// so [`CodexLogSnapshot::save_to_dir_with_meta`] writes them.
+    pub snapshot: CodexLogSnapshot,
+    pub classification: String,
+    pub reason: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+    pub stats: SnapshotStats,
+}
+
+impl FeedbackReport {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "classification": self.classification,
+            "reason": self.reason,
+            "metadata": self.metadata,
+            "summary": self.snapshot.summary().to_string(),
+            "stats": {
+                "bytes": self.stats.bytes,
+                "lines": self.stats.lines,
+                "words": self.stats.words,
+                "chars": self.stats.chars,
+            },
+            "snapshot": self.snapshot.to_json(),
+        })
+    }
+}
+
+impl CodexFeedback {
+    /// Snapshots `session_id` (as [`CodexFeedback::snapshot`] does) and bundles it
+    /// with the submission details and its [`SnapshotStats`].
+    pub fn build_report(
+        &self,
+        session_id: Option<ConversationId>,
+        classification: &str,
+        reason: Option<&str>,
+    ) -> FeedbackReport {
+        let mut snapshot = self
+            .snapshot(session_id)
+            .with_classification(classification);
+        if let Some(reason) = reason {
+            snapshot = snapshot.with_note(reason);
+        }
+        FeedbackReport {
+            classification: classification.to_string(),
+            reason: reason.map(str::to_string),
+            metadata: snapshot.metadata.clone(),
+            stats: snapshot.stats(),
+            snapshot,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use pretty_assertions::assert_eq;
+    use tracing_subscriber::fmt::writer::MakeWriter;
+
+    use super::*;
+
+    #[test]
+    fn report_json_has_every_field() {
+        let fb = CodexFeedback::new();
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"INFO started\nERROR boom\n")
+            .expect("write");
+
+        let report = fb.build_report(None, "bug", Some("it crashed"));
+        let json = report.to_json();
+
+        let mut keys: Vec<&str> = json
+            .as_object()
+            .expect("object")
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort_unstable();
+        assert_eq!(
+            keys,
+            vec![
+                "classification",
+                "metadata",
+                "reason",
+                "snapshot",
+                "stats",
+                "summary"
+            ]
+        );
+        assert_eq!(json["classification"], "bug");
+        assert_eq!(json["reason"], "it crashed");
+        assert_eq!(json["metadata"], json!({}));
+        assert_eq!(
+            json["stats"],
+            json!({ "bytes": 24, "lines": 2, "words": 4, "chars": 24 })
+        );
+        assert_eq!(json["snapshot"]["text"], "INFO started\nERROR boom\n");
+        assert_eq!(
+            json["summary"],
+            format!(
+                "feedback snapshot #1 for thread {} (24 bytes)",
+                report.snapshot.thread_id
+            )
+        );
+        assert_eq!(report.snapshot.classification.as_deref(), Some("bug"));
+        assert_eq!(report.snapshot.note.as_deref(), Some("it crashed"));
+    }
+}
@@ -0,0 +1,79 @@
+//! Serde helpers for [`crate::CodexLogSnapshot`] under the `serde` feature: the log
+//! bytes are stored as a base64 string so they round-trip exactly through text
+//! formats such as JSON, even when they are not valid UTF-8.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serializer;
+
+pub(crate) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD.encode(bytes))
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::CodexLogSnapshot;
+
+    #[test]
+    fn snapshot_round_trips_through_json() {
+        let mut snapshot = CodexLogSnapshot::new(b"line\n\xff\x00raw\n".to_vec(), "t-1".into())
+            .with_classification("bug")
+            .with_metadata("os", "linux");
+        snapshot.start_offset = 5;
+        snapshot.end_offset = 18;
+        snapshot.sequence = 2;
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["bytes"], "bGluZQr/AHJhdwo=");
+        assert_eq!(json["thread_id"], "t-1");
+
+        let restored: CodexLogSnapshot = serde_json::from_value(json).unwrap();
+        assert_eq!(restored.as_bytes(), snapshot.as_bytes());
+        assert_eq!(
+            (
+                restored.thread_id.as_str(),
+                restored.start_offset,
+                restored.end_offset,
+                restored.sequence
+            ),
+            ("t-1", 5, 18, 2)
+        );
+        assert_eq!(restored.classification.as_deref(), Some("bug"));
+        assert_eq!(restored.metadata, snapshot.metadata);
+    }
+
+    #[test]
+    fn snapshot_round_trips_through_bincode() {
+        let mut snapshot = CodexLogSnapshot::new(b"line\n\xff\x00raw\n".to_vec(), "t-1".into())
+            .with_classification("bug")
+            .with_metadata("os", "linux");
+        snapshot.start_offset = 5;
+        snapshot.end_offset = 18;
+        snapshot.sequence = 2;
+
+        let encoded = bincode::serialize(&snapshot).unwrap();
+        let restored: CodexLogSnapshot = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(restored.as_bytes(), snapshot.as_bytes());
+        assert_eq!(
+            (
+                restored.thread_id.as_str(),
+                restored.start_offset,
+                restored.end_offset,
+                restored.sequence,
+                restored.schema_version
+            ),
+            ("t-1", 5, 18, 2, snapshot.schema_version)
+        );
+        assert_eq!(restored.classification.as_deref(), Some("bug"));
+        assert_eq!(restored.metadata, snapshot.metadata);
+    }
+}
@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use codex_protocol::ConversationId;
+
+use crate::CodexFeedback;
+use crate::CodexLogSnapshot;
+
+/// An immutable snapshot whose bytes are shared, so handing it to several
+/// consumers clones a handle instead of the log; see
+/// [`CodexFeedback::snapshot_shared`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedSnapshot {
+    bytes: Arc<[u8]>,
+    thread_id: Arc<str>,
+    /// See [`CodexLogSnapshot::start_offset`].
+    pub start_offset: u64,
+    /// See [`CodexLogSnapshot::end_offset`].
+    pub end_offset: u64,
+    /// See [`CodexLogSnapshot::sequence`].
+    pub sequence: u64,
+}
+
+impl SharedSnapshot {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn thread_id(&self) -> &str {
+        &self.thread_id
+    }
+
+    /// Whether `self` and `other` share the same bytes, i.e. one was cloned from
+    /// the other.
+    pub fn ptr_eq(&self, other: &SharedSnapshot) -> bool {
+        Arc::ptr_eq(&self.bytes, &other.bytes)
+    }
+}
+
+impl From<CodexLogSnapshot> for SharedSnapshot {
+    fn from(snapshot: CodexLogSnapshot) -> Self {
+        Self {
+            bytes: snapshot.bytes.into(),
+            thread_id: snapshot.thread_id.into(),
+            start_offset: snapshot.start_offset,
+            end_offset: snapshot.end_offset,
+            sequence: snapshot.sequence,
+        }
+    }
+}
+
+impl CodexFeedback {
+    /// Like [`CodexFeedback::snapshot`], but returns a [`SharedSnapshot`] that
+    /// consumers can clone, also across threads, without copying the bytes.
+    pub fn snapshot_shared(&self, session_id: Option<ConversationId>) -> SharedSnapshot {
+        self.snapshot(session_id).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::thread;
+
+    use pretty_assertions::assert_eq;
+    use tracing_subscriber::fmt::writer::MakeWriter;
+
+    use super::*;
+
+    #[test]
+    fn clones_share_bytes_across_threads() {
+        let fb = CodexFeedback::new();
+        fb.make_writer()
+            .make_writer()
+            .write_all(b"shared line\n")
+            .unwrap();
+        let shared = fb.snapshot_shared(None);
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let clone = shared.clone();
+                thread::spawn(move || clone)
+            })
+            .collect();
+        for handle in handles {
+            let clone = handle.join().unwrap();
+            assert!(clone.ptr_eq(&shared));
+            assert_eq!(clone.as_bytes().as_ptr(), shared.as_bytes().as_ptr());
+            assert_eq!(clone, shared);
+        }
+        assert_eq!(shared.as_bytes(), b"shared line\n");
+        assert!(shared.thread_id().starts_with("no-active-thread-"));
+        assert!(!shared.ptr_eq(&fb.snapshot_shared(None)));
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::VecDeque;
+
+/// Holds a feedback buffer's bytes, oldest first. The buffer around it decides
+/// what to keep (capacity, line limits, retention) and tells the store what to
+/// append and evict; see [`crate::CodexFeedback::with_buffer_store`].
+pub trait BufferStore: Send + Sync {
+    /// Appends `data` after the newest byte.
+    fn push(&mut self, data: &[u8]);
+
+    /// Removes the oldest `n` bytes, or everything if fewer are held.
+    fn evict(&mut self, n: usize);
+
+    /// The held bytes, oldest first, in at most two pieces so ring-shaped stores
+    /// need not copy.
+    fn as_slices(&self) -> (&[u8], &[u8]);
+
+    /// Rearranges the held bytes into one piece and returns it.
+    fn make_contiguous(&mut self) -> &[u8];
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn clear(&mut self);
+
+    /// Total bytes removed by [`BufferStore::evict`] so far.
+    fn evicted_bytes(&self) -> u64;
+
+    /// Bytes allocated, which may exceed [`BufferStore::len`].
+    fn capacity(&self) -> usize;
+
+    /// Allocates room for `additional` more bytes than are held now.
+    fn reserve(&mut self, additional: usize);
+
+    /// Releases whatever is allocated beyond [`BufferStore::len`].
+    fn shrink_to_fit(&mut self);
+
+    /// Copies out the held bytes.
+    fn snapshot(&self) -> Vec<u8> {
+        let (front, back) = self.as_slices();
+        [front, back].concat()
+    }
+
+    /// Moves out the held bytes, leaving the store empty.
+    fn take(&mut self) -> Vec<u8> {
+        let bytes = self.snapshot();
+        self.clear();
+        bytes
+    }
+}
+
+/// The default [`BufferStore`], backed by a [`VecDeque`].
+#[derive(Debug, Default)]
+pub struct VecDequeStore {
+    buf: VecDeque<u8>,
+    evicted: u64,
+}
+
+impl BufferStore for VecDequeStore {
+    fn push(&mut self, data: &[u8]) {
+        self.buf.extend(data);
+    }
+
+    fn evict(&mut self, n: usize) {
+        let n = n.min(self.buf.len());
+        self.buf.drain(..n);
+        self.evicted += n as u64;
+    }
+
+    fn as_slices(&self) -> (&[u8], &[u8]) {
+        self.buf.as_slices()
+    }
+
+    fn make_contiguous(&mut self) -> &[u8] {
+        self.buf.make_contiguous()
+    }
+
+    fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    fn evicted_bytes(&self) -> u64 {
+        self.evicted
+    }
+
+    fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.buf.reserve_exact(additional);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.buf.shrink_to_fit();
+    }
+
+    fn take(&mut self) -> Vec<u8> {
+        Vec::from(std::mem::take(&mut self.buf))
+    }
+}
+
+/// A [`BufferStore`] that writes into a fixed `Vec` in a circle, growing it only
+/// when more bytes are held than fit.
+#[derive(Debug, Default)]
+pub struct CircularVecStore {
+    data: Vec<u8>,
+    /// Index of the oldest byte.
+    head: usize,
+    len: usize,
+    evicted: u64,
+}
+
+impl CircularVecStore {
+    /// Moves the held bytes into a new ring of `size` bytes, starting at index 0.
+    fn resize(&mut self, size: usize) {
+        let mut data = Vec::with_capacity(size);
+        let (front, back) = self.as_slices();
+        data.extend_from_slice(front);
+        data.extend_from_slice(back);
+        data.resize(size, 0);
+        self.data = data;
+        self.head = 0;
+    }
+}
+
+impl BufferStore for CircularVecStore {
+    fn push(&mut self, data: &[u8]) {
+        let needed = self.len + data.len();
+        if needed > self.data.len() {
+            self.resize(needed.max(self.data.len() * 2));
+        }
+        let size = self.data.len();
+        if size == 0 {
+            return;
+        }
+        let tail = (self.head + self.len) % size;
+        let first = data.len().min(size - tail);
+        self.data[tail..tail + first].copy_from_slice(&data[..first]);
+        self.data[..data.len() - first].copy_from_slice(&data[first..]);
+        self.len = needed;
+    }
+
+    fn evict(&mut self, n: usize) {
+        let n = n.min(self.len);
+        if n > 0 {
+            self.head = (self.head + n) % self.data.len();
+        }
+        self.len -= n;
+        self.evicted += n as u64;
+    }
+
+    fn as_slices(&self) -> (&[u8], &[u8]) {
+        let end = self.head + self.len;
+        if end <= self.data.len() {
+            (&self.data[self.head..end], &[])
+        } else {
+            (&self.data[self.head..], &self.data[..end - self.data.len()])
+        }
+    }
+
+    fn make_contiguous(&mut self) -> &[u8] {
+        self.data.rotate_left(self.head);
+        self.head = 0;
+        &self.data[..self.len]
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    fn evicted_bytes(&self) -> u64 {
+        self.evicted
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        let needed = self.len + additional;
+        if needed > self.data.len() {
+            self.resize(needed);
+        }
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.resize(self.len);
+        self.data.shrink_to_fit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use pretty_assertions::assert_eq;
+    use regex_lite::Regex;
+    use tracing_subscriber::fmt::writer::MakeWriter;
+
+    use super::*;
+    use crate::CodexFeedback;
+
+    /// Exercises eviction, line indexing and removal through the public API, so
+    /// every store must behave identically.
+    fn behavior_suite(make: fn() -> Box<dyn BufferStore>) {
+        let fb = CodexFeedback::with_capacity(63)
+            .with_buffer_store(make)
+            .with_error_index();
+        let write = |text: &str| {
+            fb.make_writer()
+                .make_writer()
+                .write_all(text.as_bytes())
+                .expect("write");
+        };
+        for i in 0..20 {
+            write(&format!("INFO line {i:02}\n"));
+        }
+        write("ERROR broke");
+
+        let snapshot = fb.snapshot(None);
+        assert_eq!(
+            String::from_utf8_lossy(snapshot.as_bytes()),
+            "INFO line 16\nINFO line 17\nINFO line 18\nINFO line 19\nERROR broke"
+        );
+        assert_eq!((snapshot.start_offset, snapshot.end_offset), (208, 271));
+        assert_eq!(fb.tail(2), vec!["INFO line 19", "ERROR broke"]);
+        assert_eq!(fb.line_count(), 5);
+        assert_eq!(fb.error_offsets(), vec![52]);
+        assert_eq!(fb.count_matches(&Regex::new("line 1[78]").unwrap()), 2);
+        assert_eq!(
+            fb.with_slices(|front, back| [front, back].concat()),
+            snapshot.as_bytes()
+        );
+
+        write("\n");
+        assert_eq!(fb.retain_lines(|line| !line.ends_with("18")), 1);
+        assert_eq!(
+            fb.rotate_on_marker("INFO line 19")
+                .map(|rotated| rotated.as_bytes().to_vec()),
+            Some(b"NFO line 16\nINFO line 17\n".to_vec())
+        );
+        assert_eq!(
+            fb.drain_lines(),
+            vec!["INFO line 19".to_string(), "ERROR broke".to_string()]
+        );
+        assert!(fb.is_empty());
+
+        write("a fresh start\n");
+        assert_eq!(fb.drain_snapshot(None).as_bytes(), b"a fresh start\n");
+        assert!(fb.is_empty());
+    }
+
+    #[test]
+    fn vec_deque_store_passes_the_behavior_suite() {
+        behavior_suite(|| Box::new(VecDequeStore::default()));
+    }
+
+    #[test]
+    fn circular_vec_store_passes_the_behavior_suite() {
+        behavior_suite(|| Box::new(CircularVecStore::default()));
+    }
+
+    #[test]
+    fn circular_vec_store_wraps_and_grows() {
+        let mut store = CircularVecStore::default();
+        store.push(b"abcdef");
+        store.evict(4);
+        store.push(b"ghij");
+        assert_eq!(store.as_slices(), (&b"ef"[..], &b"ghij"[..]));
+        assert_eq!(store.capacity(), 6);
+
+        store.push(b"klmnop");
+        assert_eq!(store.snapshot(), b"efghijklmnop");
+        assert_eq!(store.evicted_bytes(), 4);
+
+        store.evict(3);
+        assert_eq!(store.make_contiguous(), b"hijklmnop");
+        store.shrink_to_fit();
+        assert_eq!((store.capacity(), store.len()), (9, 9));
+        assert_eq!(store.take(), b"hijklmnop");
+        assert!(store.is_empty());
+    }
+}
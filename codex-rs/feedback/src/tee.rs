@@ -0,0 +1,150 @@
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Weak;
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::CodexFeedback;
+use crate::FeedbackInner;
+use crate::TailCursor;
+
+/// A running [`CodexFeedback::tee_to`] thread; dropping `stop` wakes it up and
+/// makes it exit.
+pub(crate) struct PeriodicTeeHandle {
+    stop: mpsc::Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl CodexFeedback {
+    /// Starts a background thread that appends the lines newly written to the
+    /// shared buffer to `sink` every `interval`, e.g. a
+    /// `tracing_appender::rolling` appender, so feedback logs also reach the normal
+    /// log pipeline. Lines are delivered once each, redacted, as returned by
+    /// [`CodexFeedback::tail_since`] (including its eviction markers), starting with
+    /// whatever is already buffered. Any such thread already running is stopped
+    /// first.
+    ///
+    /// Unlike [`CodexFeedback::with_tee`], which mirrors raw writes as they happen,
+    /// this never blocks writers on `sink`.
+    pub fn tee_to(&self, sink: impl Write + Send + 'static, interval: Duration) {
+        self.stop_tee();
+        let (stop, stopped) = mpsc::channel();
+        let inner = Arc::downgrade(&self.inner);
+        let thread = std::thread::spawn(move || {
+            let mut sink = sink;
+            let mut cursor = TailCursor::default();
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let Some(next) = tee_once(&inner, cursor, &mut sink) else {
+                    break;
+                };
+                cursor = next;
+            }
+        });
+        *self.inner.periodic_tee.lock().expect("mutex poisoned") =
+            Some(PeriodicTeeHandle { stop, thread });
+    }
+
+    /// Stops the [`CodexFeedback::tee_to`] thread, if any, and waits for it to
+    /// exit. Lines written since its last delivery are not sent.
+    pub fn stop_tee(&self) {
+        let handle = self
+            .inner
+            .periodic_tee
+            .lock()
+            .expect("mutex poisoned")
+            .take();
+        if let Some(PeriodicTeeHandle { stop, thread }) = handle {
+            drop(stop);
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Delivers the lines after `cursor`; returns the next cursor, or `None` once the
+/// feedback instance is gone.
+fn tee_once(
+    inner: &Weak<FeedbackInner>,
+    cursor: TailCursor,
+    sink: &mut impl Write,
+) -> Option<TailCursor> {
+    let inner = inner.upgrade()?;
+    let (lines, next) = CodexFeedback { inner }.tail_since(cursor);
+    let mut chunk = String::new();
+    for line in lines {
+        chunk.push_str(&line);
+        chunk.push('\n');
+    }
+    if chunk.is_empty() {
+        return Some(next);
+    }
+    // Best effort: on failure the same lines are retried on the next tick.
+    match sink.write_all(chunk.as_bytes()).and_then(|()| sink.flush()) {
+        Ok(()) => Some(next),
+        Err(_) => Some(cursor),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    use pretty_assertions::assert_eq;
+    use tracing_subscriber::fmt::writer::MakeWriter;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct MemorySink(Arc<Mutex<Vec<u8>>>);
+
+    impl MemorySink {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+
+        fn wait_for(&self, expected: &str) {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Instant::now() < deadline {
+                if self.contents() == expected {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            assert_eq!(self.contents(), expected);
+        }
+    }
+
+    impl Write for MemorySink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tee_to_delivers_each_new_line_once() {
+        let sink = MemorySink::default();
+        let fb = CodexFeedback::with_capacity(1024);
+        let mut w = fb.make_writer().make_writer();
+        w.write_all(b"before\n").unwrap();
+
+        fb.tee_to(sink.clone(), Duration::from_millis(10));
+        sink.wait_for("before\n");
+        w.write_all(b"password=hunter2\npart").unwrap();
+        sink.wait_for("before\npassword=[REDACTED]\n");
+        w.write_all(b"ial\n").unwrap();
+        sink.wait_for("before\npassword=[REDACTED]\npartial\n");
+
+        fb.stop_tee();
+        assert!(fb.inner.periodic_tee.lock().unwrap().is_none());
+        w.write_all(b"after\n").unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(sink.contents(), "before\npassword=[REDACTED]\npartial\n");
+    }
+}
@@ -0,0 +1,202 @@
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Result;
+use anyhow::anyhow;
+use tokio_util::sync::CancellationToken;
+
+use crate::CodexLogSnapshot;
+
+/// How [`CodexLogSnapshot::upload_with_retry`] retries failed attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadRetryPolicy {
+    /// Total number of attempts, including the first one. Zero is treated as one.
+    pub max_attempts: u64,
+    /// Delay before the second attempt; doubled for every attempt after that.
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay between two attempts.
+    pub max_backoff: Duration,
+}
+
+impl Default for UploadRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl UploadRetryPolicy {
+    /// Delay to wait after the given (1-based) failed attempt.
+    fn backoff(&self, attempt: u64) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31) as u32;
+        self.initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff)
+    }
+}
+
+impl CodexLogSnapshot {
+    /// Drives an upload of this snapshot, retrying failed attempts with exponential
+    /// backoff according to `policy`.
+    ///
+    /// This fork ships no uploader of its own, so the transport is supplied by the
+    /// caller: `attempt` is invoked with the snapshot and the 1-based attempt number
+    /// and performs a single try. Returns the last attempt's error once all attempts
+    /// have failed. Cancelling `cancel` aborts both in-flight attempts and pending
+    /// backoff delays; an already cancelled token makes no attempt at all.
+    pub async fn upload_with_retry<'a, F, Fut>(
+        &'a self,
+        policy: &UploadRetryPolicy,
+        cancel: &CancellationToken,
+        mut attempt: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&'a CodexLogSnapshot, u64) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let max_attempts = policy.max_attempts.max(1);
+        let mut last_error = None;
+        for n in 1..=max_attempts {
+            if cancel.is_cancelled() {
+                return Err(anyhow!("feedback upload cancelled before attempt {n}"));
+            }
+            match cancel.run_until_cancelled(attempt(self, n)).await {
+                Some(Ok(())) => return Ok(()),
+                Some(Err(err)) => last_error = Some(err),
+                None => return Err(anyhow!("feedback upload cancelled during attempt {n}")),
+            }
+            if n < max_attempts
+                && cancel
+                    .run_until_cancelled(tokio::time::sleep(policy.backoff(n)))
+                    .await
+                    .is_none()
+            {
+                return Err(anyhow!(
+                    "feedback upload cancelled after {n} failed attempt(s)"
+                ));
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow!("feedback upload made no attempts")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn fast_policy(max_attempts: u64) -> UploadRetryPolicy {
+        UploadRetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_until_the_server_accepts() {
+        let snapshot = CodexLogSnapshot::new(b"log body".to_vec(), "thread".to_string());
+        let mut received = Vec::new();
+
+        snapshot
+            .upload_with_retry(&fast_policy(5), &CancellationToken::new(), |snap, n| {
+                received.push((n, snap.as_bytes().to_vec()));
+                async move {
+                    if n <= 2 {
+                        Err(anyhow!("503 from mock server"))
+                    } else {
+                        Ok(())
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            received,
+            vec![
+                (1, b"log body".to_vec()),
+                (2, b"log body".to_vec()),
+                (3, b"log body".to_vec()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn returns_last_error_when_attempts_are_exhausted() {
+        let snapshot = CodexLogSnapshot::new(Vec::new(), "thread".to_string());
+        let err = snapshot
+            .upload_with_retry(
+                &fast_policy(3),
+                &CancellationToken::new(),
+                |_, n| async move { Err(anyhow!("attempt {n} failed")) },
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "attempt 3 failed");
+    }
+
+    #[tokio::test]
+    async fn cancellation_stops_retrying() {
+        let snapshot = CodexLogSnapshot::new(Vec::new(), "thread".to_string());
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let mut attempts = 0;
+        let err = snapshot
+            .upload_with_retry(&fast_policy(3), &cancel, |_, _| {
+                attempts += 1;
+                async { Ok(()) }
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "feedback upload cancelled before attempt 1"
+        );
+        assert_eq!(attempts, 0);
+
+        // Cancelling while an attempt fails skips the backoff and every retry.
+        let slow_policy = UploadRetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(3600),
+            max_backoff: Duration::from_secs(3600),
+        };
+        let cancel = CancellationToken::new();
+        let mut attempts = 0;
+        let err = snapshot
+            .upload_with_retry(&slow_policy, &cancel, |_, _| {
+                attempts += 1;
+                let cancel = cancel.clone();
+                async move {
+                    cancel.cancel();
+                    Err(anyhow!("503 from mock server"))
+                }
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "feedback upload cancelled after 1 failed attempt(s)"
+        );
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = UploadRetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+        };
+        let delays: Vec<Duration> = (1..=5).map(|n| policy.backoff(n)).collect();
+        assert_eq!(
+            delays,
+            [100, 200, 400, 500, 500]
+                .map(Duration::from_millis)
+                .to_vec()
+        );
+    }
+}
@@ -560,6 +560,9 @@ pub enum EventMsg {
     AgentMessageContentDelta(AgentMessageContentDeltaEvent),
     ReasoningContentDelta(ReasoningContentDeltaEvent),
     ReasoningRawContentDelta(ReasoningRawContentDeltaEvent),
+
+    /// Feedback logs for a conversation were captured and saved to disk.
+    FeedbackSubmitted(FeedbackSubmittedEvent),
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, TS, JsonSchema)]
@@ -1326,6 +1329,16 @@ pub struct BackgroundEventEvent {
     pub message: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema, TS)]
+pub struct FeedbackSubmittedEvent {
+    /// Conversation the feedback was captured for, if any.
+    pub conversation_id: Option<ConversationId>,
+    /// Category chosen by the user, e.g. `bug` or `good_result`.
+    pub classification: String,
+    /// Path of the saved feedback log.
+    pub path: PathBuf,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, TS)]
 pub struct DeprecationNoticeEvent {
     /// Concise summary of what is deprecated.